@@ -1,7 +1,12 @@
+use std::time::Instant;
+
 use nalgebra::{
     Matrix, Matrix4, Point, Point2, Point3, Quaternion, UnitQuaternion, Vector2, Vector3,
 };
-use winit::event::ElementState;
+use winit::event::{ElementState, MouseButton};
+
+use crate::handle_user_input::UserInputState;
+use crate::render_system::offscreen_rendering;
 
 #[inline]
 fn deg2rad(deg: f32) -> f32 {
@@ -33,13 +38,15 @@ impl DirVecs {
     }
 }
 
+// clip planes of the perspective projection
+pub const PERSPECTIVE_NEAR: f32 = 0.1;
+pub const PERSPECTIVE_FAR: f32 = 100.0;
+
 fn gen_perspective_projection(extent: [u32; 2]) -> Matrix4<f32> {
     let [screen_x, screen_y] = extent;
     let aspect_ratio = screen_x as f32 / screen_y as f32;
     let fov = deg2rad(90.0);
-    let near = 0.1;
-    let far = 100.0;
-    Matrix4::new_perspective(aspect_ratio, fov, near, far)
+    Matrix4::new_perspective(aspect_ratio, fov, PERSPECTIVE_NEAR, PERSPECTIVE_FAR)
 }
 
 // Converts a space with depth values in the range [-1, 1] to a space with depth values in the range [0, 1] 
@@ -48,28 +55,78 @@ fn vk_depth_correction() -> Matrix4<f32> {
     Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 1.0, 0.5)) * Matrix4::new_translation(&Vector3::new(0.0, 0.0, 1.0))
 }
 
-#[allow(dead_code)]
-fn gen_orthographic_projection([screen_x, screen_y]: [u32; 2]) -> Matrix4<f32> {
-    let scale = 100.0;
-    let left = -(screen_x as f32) / scale;
-    let right = screen_x as f32 / scale;
-    let bottom = -(screen_y as f32) / scale;
-    let top = screen_y as f32 / scale;
-    vk_depth_correction() * Matrix4::new_orthographic(left, right, bottom, top, -200.0, 200.0)
+// orthographic projection of the box centered on the view axis, between the near and far planes
+pub(crate) fn gen_orthographic_projection(half_width: f32, half_height: f32, near: f32, far: f32) -> Matrix4<f32> {
+    vk_depth_correction()
+        * Matrix4::new_orthographic(-half_width, half_width, -half_height, half_height, near, far)
+}
+
+// how the size of the orthographic view volume is chosen
+#[derive(Clone, Copy, Debug)]
+enum OrthographicBounds {
+    // a fixed number of pixels per world unit, so the visible area grows with the render target
+    PixelScale(f32),
+    // a fixed width in world units, with the height following the aspect ratio of the render target
+    WorldWidth(f32),
+    // a fixed height in world units, with the width following the aspect ratio of the render target
+    WorldHeight(f32),
+}
+
+impl OrthographicBounds {
+    // half the width and height of the view volume, in world units
+    fn half_extents(&self, [screen_x, screen_y]: [u32; 2]) -> (f32, f32) {
+        match *self {
+            OrthographicBounds::PixelScale(pixels_per_unit) => (
+                screen_x as f32 / pixels_per_unit,
+                screen_y as f32 / pixels_per_unit,
+            ),
+            OrthographicBounds::WorldWidth(width) => {
+                let aspect_ratio = screen_x as f32 / screen_y as f32;
+                (width * 0.5, width * 0.5 / aspect_ratio)
+            }
+            OrthographicBounds::WorldHeight(height) => {
+                let aspect_ratio = screen_x as f32 / screen_y as f32;
+                (height * 0.5 * aspect_ratio, height * 0.5)
+            }
+        }
+    }
 }
 
 pub trait Camera {
-    fn mvp(&self, extent: [u32; 2]) -> Matrix4<f32>;
+    /// transformation from world space to camera space
+    fn view(&self) -> Matrix4<f32>;
+    /// transformation from camera space to clip space for a render target of the given extent
+    fn projection(&self, extent: [u32; 2]) -> Matrix4<f32>;
+    fn mvp(&self, extent: [u32; 2]) -> Matrix4<f32> {
+        self.projection(extent) * self.view()
+    }
+    /// sets the position of the point the camera is attached to
     fn set_position(&mut self, pos: Point3<f32>);
+    /// sets the orientation of the point the camera is attached to.
+    /// any camera-local orientation (e.g. pitch and yaw) is applied relative to this rotation
     fn set_rotation(&mut self, rot: UnitQuaternion<f32>);
+    /// converts a value read from a depth image rendered with this camera back into the distance from the camera.
+    /// the default is for the perspective projection that most cameras use
+    fn linearize_depth(&self, depth: f32) -> f32 {
+        offscreen_rendering::linearize_depth(depth, PERSPECTIVE_NEAR, PERSPECTIVE_FAR)
+    }
 }
 
 
 pub trait InteractiveCamera: Camera {
     fn update(&mut self);
     fn handle_event(&mut self, extent: [u32; 2], input: &winit::event::WindowEvent);
+    // called when the cursor is grabbed (true) or released (false) for mouse look
+    fn set_mouse_captured(&mut self, _captured: bool) {}
+    // raw device events, used for mouse look while the cursor is captured
+    fn handle_device_event(&mut self, _event: &winit::event::DeviceEvent) {}
 }
 
+// how much the distance to the root shrinks for each notch of the scroll wheel
+const ZOOM_PER_SCROLL_NOTCH: f32 = 1.1;
+// how many pixels of scrolling on a touchpad count as one notch of the scroll wheel
+const PIXELS_PER_SCROLL_NOTCH: f32 = 20.0;
+
 fn get_normalized_mouse_coords(e: Point2<f32>, extent: [u32; 2]) -> Point2<f32> {
     let trackball_radius = extent[0].min(extent[1]) as f32;
     let center = Vector2::new(extent[0] as f32 / 2.0, extent[1] as f32 / 2.0);
@@ -77,6 +134,8 @@ fn get_normalized_mouse_coords(e: Point2<f32>, extent: [u32; 2]) -> Point2<f32>
 }
 
 // lets you orbit around the central point by clicking and dragging
+// the pitch and yaw set by the mouse are relative to the root rotation,
+// so the camera turns together with the entity it is attached to
 pub struct SphericalCamera {
     // position of the camera's root point
     root_pos: Point3<f32>,
@@ -84,15 +143,34 @@ pub struct SphericalCamera {
     root_rot: UnitQuaternion<f32>,
     // world up
     worldup: Vector3<f32>,
+    // translation of the orbit target away from the root position
+    pan: Vector3<f32>,
+    // how fast the orbit target slides when panning
+    pan_speed: f32,
     // offset from the root position
     offset: f32,
+    // limits on the offset that can be reached by zooming
+    min_offset: f32,
+    max_offset: f32,
     // pitch
     pitch: f32,
     // yaw
     yaw: f32,
+    // yaw and pitch turned per second by the last update's drag (used for momentum after the mouse is released)
+    yaw_velocity: f32,
+    pitch_velocity: f32,
+    // yaw and pitch turned by dragging since the last update
+    drag_yaw: f32,
+    drag_pitch: f32,
+    // fraction of the velocity kept every 60th of a second
+    damping_factor: f32,
+    momentum_enabled: bool,
+    // when `update` was last called, to scale the glide by the time that passed
+    last_update: Option<Instant>,
 
     // contains mouse data (if being dragged)
     mouse_down: bool,
+    pan_down: bool,
     mouse_start: Point2<f32>,
     mouse_prev: Point2<f32>,
     mouse_curr: Point2<f32>,
@@ -104,25 +182,111 @@ impl SphericalCamera {
             root_pos: Point3::default(),
             root_rot: UnitQuaternion::identity(),
             worldup: Vector3::new(0.0, -1.0, 0.0),
+            pan: Vector3::zeros(),
+            pan_speed: 1.0,
             pitch: 0.0,
             yaw: 0.0,
+            yaw_velocity: 0.0,
+            pitch_velocity: 0.0,
+            drag_yaw: 0.0,
+            drag_pitch: 0.0,
+            damping_factor: 0.9,
+            momentum_enabled: false,
+            last_update: None,
             offset: 3.0,
+            min_offset: 0.5,
+            max_offset: 50.0,
             mouse_down: false,
+            pan_down: false,
             mouse_start: Default::default(),
             mouse_prev: Default::default(),
             mouse_curr: Default::default(),
         }
     }
 
+    /// sets the closest and furthest distance from the root that can be reached by zooming
+    pub fn set_zoom_limits(&mut self, min: f32, max: f32) {
+        assert!(min > 0.0, "minimum zoom distance must be positive");
+        assert!(min <= max, "minimum zoom distance must not exceed the maximum");
+        self.min_offset = min;
+        self.max_offset = max;
+        self.offset = self.offset.clamp(min, max);
+    }
+
+    /// the point the camera orbits around and looks at
+    pub fn target(&self) -> Point3<f32> {
+        self.root_pos + self.pan
+    }
+
+    /// the position of the camera: exactly `offset` away from the target, along the rotated view direction
+    pub fn eye(&self) -> Point3<f32> {
+        let dirs = DirVecs::new(self.worldup, self.pitch, self.yaw);
+        // dirs.front is a unit vector and the rotation preserves length, so offset is a true radius
+        self.target() - self.offset * (self.root_rot * dirs.front)
+    }
+
+    /// sets the fraction of the orbit velocity that is kept every 60th of a second after the mouse is released.
+    /// values close to 1 glide for a long time, 0 stops immediately
+    pub fn set_damping_factor(&mut self, damping_factor: f32) {
+        assert!(
+            (0.0..1.0).contains(&damping_factor),
+            "damping factor must be in [0, 1)"
+        );
+        self.damping_factor = damping_factor;
+    }
+
+    /// if enabled, the camera keeps orbiting after the mouse is released, slowing down by the damping factor.
+    /// disabled by default, so the camera stops as soon as the mouse is released
+    pub fn set_momentum_enabled(&mut self, momentum_enabled: bool) {
+        self.momentum_enabled = momentum_enabled;
+        if !momentum_enabled {
+            self.yaw_velocity = 0.0;
+            self.pitch_velocity = 0.0;
+        }
+    }
+
+    fn rotate(&mut self, dyaw: f32, dpitch: f32) {
+        self.yaw += dyaw;
+        self.pitch += dpitch;
+
+        if self.pitch > deg2rad(89.0) {
+            self.pitch = deg2rad(89.0);
+        } else if self.pitch < -deg2rad(89.0) {
+            self.pitch = -deg2rad(89.0);
+        }
+    }
 
+    // advances the glide by `dt` seconds. the velocity is measured from what was dragged since the last update,
+    // so holding still before letting go doesn't glide
+    fn update_by(&mut self, dt: f32) {
+        let dragged = self.drag_yaw != 0.0 || self.drag_pitch != 0.0;
+        if dt > 0.0 && (self.mouse_down || dragged) {
+            self.yaw_velocity = self.drag_yaw / dt;
+            self.pitch_velocity = self.drag_pitch / dt;
+        }
+        self.drag_yaw = 0.0;
+        self.drag_pitch = 0.0;
+        if self.momentum_enabled && !self.mouse_down {
+            self.rotate(self.yaw_velocity * dt, self.pitch_velocity * dt);
+            let kept = self.damping_factor.powf(dt * 60.0);
+            self.yaw_velocity *= kept;
+            self.pitch_velocity *= kept;
+        }
+    }
+
+    /// sets how fast the orbit target slides when dragging with the right mouse button
+    pub fn set_pan_speed(&mut self, pan_speed: f32) {
+        self.pan_speed = pan_speed;
+    }
 }
 
 impl Camera for SphericalCamera {
-    fn mvp(&self, extent: [u32; 2]) -> Matrix4<f32> {
-        let dirs = DirVecs::new(self.worldup, self.pitch, self.yaw);
-        let projection = gen_perspective_projection(extent);
-        let view = Matrix4::look_at_rh(&(self.root_pos - self.offset*(self.root_rot*dirs.front)), &self.root_pos, &self.worldup);
-        projection * view
+    fn view(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(&self.eye(), &self.target(), &self.worldup)
+    }
+
+    fn projection(&self, extent: [u32; 2]) -> Matrix4<f32> {
+        gen_perspective_projection(extent)
     }
 
     fn set_position(&mut self, pos: Point3<f32>) {
@@ -136,7 +300,12 @@ impl Camera for SphericalCamera {
 
 impl InteractiveCamera for SphericalCamera {
     fn update(&mut self) {
-        // do nothing
+        let now = Instant::now();
+        let dt = self
+            .last_update
+            .replace(now)
+            .map_or(0.0, |last_update| (now - last_update).as_secs_f32());
+        self.update_by(dt);
     }
 
     fn handle_event(&mut self, extent: [u32; 2], event: &winit::event::WindowEvent) {
@@ -144,10 +313,23 @@ impl InteractiveCamera for SphericalCamera {
             // mouse down
             winit::event::WindowEvent::MouseInput {
                 state: ElementState::Pressed,
+                button: MouseButton::Left,
                 ..
             } => {
                 self.mouse_down = true;
                 self.mouse_start = self.mouse_curr;
+                // grabbing the camera stops any remaining momentum
+                self.yaw_velocity = 0.0;
+                self.pitch_velocity = 0.0;
+                self.drag_yaw = 0.0;
+                self.drag_pitch = 0.0;
+            }
+            winit::event::WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Right,
+                ..
+            } => {
+                self.pan_down = true;
             }
             // cursor move
             winit::event::WindowEvent::CursorMoved { position, .. } => {
@@ -156,45 +338,351 @@ impl InteractiveCamera for SphericalCamera {
                     Point2::new(position.x as f32, position.y as f32),
                     extent,
                 );
+                if self.pan_down {
+                    // slide the target in the plane of the screen
+                    // pan faster when further away so that the target keeps up with the cursor
+                    let dirs = DirVecs::new(self.worldup, self.pitch, self.yaw);
+                    let delta = self.mouse_curr - self.mouse_prev;
+                    let right = self.root_rot * dirs.right;
+                    let up = self.root_rot * dirs.up;
+                    self.pan -= (right * delta.x - up * delta.y) * self.pan_speed * self.offset;
+                }
                 if self.mouse_down {
                     // current and past
-                    self.yaw -= (self.mouse_curr.x - self.mouse_prev.x) * 2.0;
-                    self.pitch -= (self.mouse_curr.y - self.mouse_prev.y) * 2.0;
-
-                    if self.pitch > deg2rad(89.0) {
-                        self.pitch = deg2rad(89.0);
-                    } else if self.pitch < -deg2rad(89.0) {
-                        self.pitch = -deg2rad(89.0);
-                    }
+                    let dyaw = -(self.mouse_curr.x - self.mouse_prev.x) * 2.0;
+                    let dpitch = -(self.mouse_curr.y - self.mouse_prev.y) * 2.0;
+                    self.drag_yaw += dyaw;
+                    self.drag_pitch += dpitch;
+                    self.rotate(dyaw, dpitch);
                 }
             }
             // mouse up
             winit::event::WindowEvent::MouseInput {
                 state: ElementState::Released,
+                button: MouseButton::Left,
                 ..
             } => {
                 self.mouse_down = false;
             }
+            winit::event::WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Right,
+                ..
+            } => {
+                self.pan_down = false;
+            }
             // scroll
             winit::event::WindowEvent::MouseWheel { delta, .. } => {
-                match delta {
-                    winit::event::MouseScrollDelta::LineDelta(_, y) => {
-                        self.offset -= 0.1*y;
-                        if self.offset < 0.5 {
-                            self.offset = 0.5;
-                        }
-                        println!("offset: {}", self.offset);
+                // number of notches scrolled
+                let notches = match delta {
+                    winit::event::MouseScrollDelta::LineDelta(_, y) => *y,
+                    winit::event::MouseScrollDelta::PixelDelta(position) => {
+                        position.y as f32 / PIXELS_PER_SCROLL_NOTCH
                     }
-                    winit::event::MouseScrollDelta::PixelDelta(_) => {}
-                }
+                };
+                // zoom multiplicatively, so that each notch feels the same regardless of the distance
+                self.offset *= ZOOM_PER_SCROLL_NOTCH.powf(-notches);
+                self.offset = self.offset.clamp(self.min_offset, self.max_offset);
             }
             _ => {}
         }
     }
 }
 
+/// a direction to move the camera in, relative to where it is looking
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMovementDir {
+    Forward,
+    Backward,
+    Left,
+    Right,
+    Upward,
+    Downward,
+}
+
+/// a direction to turn the camera in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraRotationDir {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// first person camera that flies freely around the scene
+/// WASD moves along the view direction, Q and E move down and up, and the arrow keys look around
+/// the camera ignores the position and rotation of the entity it is attached to
+pub struct FlyCamera {
+    // position of the eye
+    pos: Point3<f32>,
+    // world up
+    worldup: Vector3<f32>,
+    // pitch
+    pitch: f32,
+    // yaw
+    yaw: f32,
+    // distance moved per update
+    movement_speed: f32,
+    // angle (in radians) turned per update
+    rotation_speed: f32,
+    // angle (in radians) turned per pixel of mouse motion while captured
+    mouse_sensitivity: f32,
+    // whether mouse motion turns the camera
+    mouse_captured: bool,
+    // which keys are currently held down
+    input: UserInputState,
+}
+
+impl FlyCamera {
+    pub fn new(pos: Point3<f32>) -> FlyCamera {
+        FlyCamera {
+            pos,
+            worldup: Vector3::new(0.0, -1.0, 0.0),
+            pitch: 0.0,
+            yaw: 0.0,
+            movement_speed: 0.1,
+            rotation_speed: deg2rad(1.0),
+            mouse_sensitivity: deg2rad(0.1),
+            mouse_captured: false,
+            input: UserInputState::new(),
+        }
+    }
+
+    pub fn set_movement_speed(&mut self, movement_speed: f32) {
+        self.movement_speed = movement_speed;
+    }
+
+    pub fn set_rotation_speed(&mut self, rotation_speed: f32) {
+        self.rotation_speed = rotation_speed;
+    }
+
+    pub fn set_mouse_sensitivity(&mut self, mouse_sensitivity: f32) {
+        self.mouse_sensitivity = mouse_sensitivity;
+    }
+
+    /// moves the camera by one step (see `set_movement_speed`) in the given direction
+    pub fn dir_move(&mut self, dir: CameraMovementDir) {
+        let dirs = DirVecs::new(self.worldup, self.pitch, self.yaw);
+        let movement = match dir {
+            CameraMovementDir::Forward => dirs.front,
+            CameraMovementDir::Backward => -dirs.front,
+            CameraMovementDir::Right => dirs.right,
+            CameraMovementDir::Left => -dirs.right,
+            CameraMovementDir::Upward => -self.worldup,
+            CameraMovementDir::Downward => self.worldup,
+        };
+        self.pos += movement * self.movement_speed;
+    }
+
+    /// turns the camera by one step (see `set_rotation_speed`) in the given direction
+    pub fn dir_rotate(&mut self, dir: CameraRotationDir) {
+        match dir {
+            CameraRotationDir::Up => self.pitch += self.rotation_speed,
+            CameraRotationDir::Down => self.pitch -= self.rotation_speed,
+            CameraRotationDir::Left => self.yaw += self.rotation_speed,
+            CameraRotationDir::Right => self.yaw -= self.rotation_speed,
+        }
+        // don't look straight up or down, or the view will flip
+        self.pitch = self.pitch.clamp(-deg2rad(89.0), deg2rad(89.0));
+    }
+}
+
+impl Camera for FlyCamera {
+    fn view(&self) -> Matrix4<f32> {
+        let dirs = DirVecs::new(self.worldup, self.pitch, self.yaw);
+        Matrix4::look_at_rh(&self.pos, &(self.pos + dirs.front), &self.worldup)
+    }
+
+    fn projection(&self, extent: [u32; 2]) -> Matrix4<f32> {
+        gen_perspective_projection(extent)
+    }
+
+    fn set_position(&mut self, _pos: Point3<f32>) {
+        // do nothing
+    }
+
+    fn set_rotation(&mut self, _rot: UnitQuaternion<f32>) {
+        // do nothing
+    }
+}
+
+impl InteractiveCamera for FlyCamera {
+    fn update(&mut self) {
+        let dirs = DirVecs::new(self.worldup, self.pitch, self.yaw);
+
+        // translate
+        let mut movement = Vector3::zeros();
+        if self.input.w {
+            movement += dirs.front;
+        }
+        if self.input.s {
+            movement -= dirs.front;
+        }
+        if self.input.d {
+            movement += dirs.right;
+        }
+        if self.input.a {
+            movement -= dirs.right;
+        }
+        if self.input.e {
+            movement -= self.worldup;
+        }
+        if self.input.q {
+            movement += self.worldup;
+        }
+        if movement.norm() > 0.0 {
+            self.pos += movement.normalize() * self.movement_speed;
+        }
+
+        // rotate
+        if self.input.up {
+            self.dir_rotate(CameraRotationDir::Up);
+        }
+        if self.input.down {
+            self.dir_rotate(CameraRotationDir::Down);
+        }
+        if self.input.left {
+            self.dir_rotate(CameraRotationDir::Left);
+        }
+        if self.input.right {
+            self.dir_rotate(CameraRotationDir::Right);
+        }
+    }
+
+    fn handle_event(&mut self, _extent: [u32; 2], event: &winit::event::WindowEvent) {
+        self.input.handle_input(event);
+    }
+
+    fn set_mouse_captured(&mut self, captured: bool) {
+        self.mouse_captured = captured;
+    }
+
+    fn handle_device_event(&mut self, event: &winit::event::DeviceEvent) {
+        if let winit::event::DeviceEvent::MouseMotion { delta: (dx, dy) } = event {
+            if self.mouse_captured {
+                // same directions as the arrow keys: moving right turns right, moving up looks up
+                self.yaw -= *dx as f32 * self.mouse_sensitivity;
+                self.pitch -= *dy as f32 * self.mouse_sensitivity;
+                self.pitch = self.pitch.clamp(-deg2rad(89.0), deg2rad(89.0));
+            }
+        }
+    }
+}
+
+/// third person camera that follows behind the entity it is attached to.
+/// instead of snapping to the entity every step, it eases towards the pose it wants to be in,
+/// and looks a little ahead of the entity in the direction it is moving
+pub struct ChaseCamera {
+    // position of the entity being followed
+    target_pos: Point3<f32>,
+    // rotation of the entity being followed
+    target_rot: UnitQuaternion<f32>,
+    // target position at the previous update, used to estimate the velocity
+    prev_target_pos: Option<Point3<f32>>,
+    // world up
+    worldup: Vector3<f32>,
+    // where the camera wants to be, relative to the entity (in the entity's frame)
+    follow_offset: Vector3<f32>,
+    // fraction of the remaining distance to the desired pose covered each update
+    smoothing: f32,
+    // how many updates of movement ahead of the entity the camera looks
+    lead: f32,
+    // current (smoothed) position of the eye
+    eye: Point3<f32>,
+    // current (smoothed) rotation used to place the eye
+    rot: UnitQuaternion<f32>,
+    // current (smoothed) point the camera looks at
+    look_at: Point3<f32>,
+}
+
+impl ChaseCamera {
+    pub fn new() -> ChaseCamera {
+        ChaseCamera {
+            target_pos: Point3::origin(),
+            target_rot: UnitQuaternion::identity(),
+            prev_target_pos: None,
+            worldup: Vector3::new(0.0, -1.0, 0.0),
+            // entities face along their local x axis, and y is up
+            follow_offset: Vector3::new(-5.0, 2.0, 0.0),
+            smoothing: 0.1,
+            lead: 5.0,
+            eye: Point3::new(-5.0, 2.0, 0.0),
+            rot: UnitQuaternion::identity(),
+            look_at: Point3::origin(),
+        }
+    }
+
+    /// sets where the camera tries to be relative to the entity, in the entity's frame
+    pub fn set_follow_offset(&mut self, follow_offset: Vector3<f32>) {
+        self.follow_offset = follow_offset;
+    }
+
+    /// sets the fraction of the distance to the desired pose that is covered each update.
+    /// 1 follows the entity rigidly, values close to 0 lag far behind
+    pub fn set_smoothing(&mut self, smoothing: f32) {
+        assert!(
+            smoothing > 0.0 && smoothing <= 1.0,
+            "smoothing must be in (0, 1]"
+        );
+        self.smoothing = smoothing;
+    }
+
+    /// sets how far ahead of the entity the camera looks, in updates worth of its current movement
+    pub fn set_lead(&mut self, lead: f32) {
+        self.lead = lead;
+    }
+}
+
+impl Camera for ChaseCamera {
+    fn view(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(&self.eye, &self.look_at, &self.worldup)
+    }
+
+    fn projection(&self, extent: [u32; 2]) -> Matrix4<f32> {
+        gen_perspective_projection(extent)
+    }
+
+    fn set_position(&mut self, pos: Point3<f32>) {
+        self.target_pos = pos;
+    }
+
+    fn set_rotation(&mut self, rot: UnitQuaternion<f32>) {
+        self.target_rot = rot;
+    }
+}
+
+impl InteractiveCamera for ChaseCamera {
+    fn update(&mut self) {
+        // the first update jumps straight to the entity
+        let prev_target_pos = match self.prev_target_pos.replace(self.target_pos) {
+            Some(prev_target_pos) => prev_target_pos,
+            None => {
+                self.rot = self.target_rot;
+                self.eye = self.target_pos + self.rot * self.follow_offset;
+                self.look_at = self.target_pos;
+                return;
+            }
+        };
+        let velocity = self.target_pos - prev_target_pos;
+
+        self.rot = self
+            .rot
+            .try_slerp(&self.target_rot, self.smoothing, 1.0e-6)
+            // the rotations are opposite, so there's no unique path between them
+            .unwrap_or(self.target_rot);
+        let desired_eye = self.target_pos + self.rot * self.follow_offset;
+        let desired_look_at = self.target_pos + velocity * self.lead;
+        self.eye = self.eye + (desired_eye - self.eye) * self.smoothing;
+        self.look_at = self.look_at + (desired_look_at - self.look_at) * self.smoothing;
+    }
+
+    fn handle_event(&mut self, _extent: [u32; 2], _input: &winit::event::WindowEvent) {
+        // do nothing
+    }
+}
 
 /// bird's eye view camera: orthographic projection, pitch of -90 degrees
+/// only the heading of the root rotation is used: the top of the image faces the direction the root is facing
 pub struct BEVCamera {
         // position of the camera's root point
         root_pos: Point3<f32>,
@@ -202,25 +690,82 @@ pub struct BEVCamera {
         root_rot: UnitQuaternion<f32>,
         // offset from the root position
         offset: f32,
+        // size of the view volume
+        bounds: OrthographicBounds,
+        // clip planes, as distances below the eye
+        near: f32,
+        far: f32,
 }
 
 impl BEVCamera {
+    /// frames a region 12 world units tall, with the width following the aspect ratio of the render target
     pub fn new() -> BEVCamera {
         BEVCamera {
             root_pos: Point3::default(),
             root_rot: UnitQuaternion::identity(),
             offset: 3.0,
+            bounds: OrthographicBounds::WorldHeight(12.0),
+            near: -200.0,
+            far: 200.0,
         }
     }
+
+    /// like `new`, but the size of the framed region is tied to the render target:
+    /// each world unit covers `pixels_per_unit / 2` pixels, so bigger windows see more of the world
+    pub fn with_pixel_scale(pixels_per_unit: f32) -> BEVCamera {
+        assert!(pixels_per_unit > 0.0, "pixel scale must be positive");
+        BEVCamera {
+            bounds: OrthographicBounds::PixelScale(pixels_per_unit),
+            ..BEVCamera::new()
+        }
+    }
+
+    /// camera at `center` looking straight down -Y, framing a region `world_width` units wide
+    /// (the height follows the aspect ratio of the render target).
+    /// only things between `near` and `far` units below `center` are visible
+    pub fn top_down(center: Point3<f32>, world_width: f32, near: f32, far: f32) -> BEVCamera {
+        assert!(world_width > 0.0, "world width must be positive");
+        assert!(near < far, "near plane must be closer than the far plane");
+        // the eye can't be at the point it looks at, so lift it and move the clip planes down to match
+        let offset = 1.0;
+        BEVCamera {
+            root_pos: center,
+            root_rot: UnitQuaternion::identity(),
+            offset,
+            bounds: OrthographicBounds::WorldWidth(world_width),
+            near: near + offset,
+            far: far + offset,
+        }
+    }
+
+    /// sets the width of the framed region, in world units
+    pub fn set_world_width(&mut self, world_width: f32) {
+        assert!(world_width > 0.0, "world width must be positive");
+        self.bounds = OrthographicBounds::WorldWidth(world_width);
+    }
+
+    /// sets the height of the framed region, in world units
+    pub fn set_vertical_extent(&mut self, vertical_extent: f32) {
+        assert!(vertical_extent > 0.0, "vertical extent must be positive");
+        self.bounds = OrthographicBounds::WorldHeight(vertical_extent);
+    }
 }
 
 impl Camera for BEVCamera {
-    fn mvp(&self, extent: [u32; 2]) -> Matrix4<f32> {
+    fn view(&self) -> Matrix4<f32> {
         let front = Vector3::new(-1.0, 0.0, 0.0);
         let worldup = self.root_rot * front;
-        let projection = gen_orthographic_projection(extent);
-        let view = Matrix4::look_at_rh(&(self.root_pos + Vector3::new(0.0, self.offset, 0.0)), &self.root_pos, &worldup);
-        projection * view
+        Matrix4::look_at_rh(&(self.root_pos + Vector3::new(0.0, self.offset, 0.0)), &self.root_pos, &worldup)
+    }
+
+    fn projection(&self, extent: [u32; 2]) -> Matrix4<f32> {
+        let (half_width, half_height) = self.bounds.half_extents(extent);
+        gen_orthographic_projection(half_width, half_height, self.near, self.far)
+    }
+
+    fn linearize_depth(&self, depth: f32) -> f32 {
+        // orthographic depth is already linear between the clip planes
+        self.near + depth * (self.far - self.near)
     }
 
     fn set_position(&mut self, pos: Point3<f32>) {
@@ -241,3 +786,181 @@ impl InteractiveCamera for BEVCamera {
         // do nothing
     }
 }
+
+/// wraps any camera so it can be used in a window without responding to input,
+/// e.g. `Box::new(FixedCamera::new(BEVCamera::new()))` for a window that just follows the tracked entity from above
+pub struct FixedCamera<C> {
+    camera: C,
+}
+
+impl<C: Camera> FixedCamera<C> {
+    pub fn new(camera: C) -> FixedCamera<C> {
+        FixedCamera { camera }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.camera
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.camera
+    }
+}
+
+impl<C: Camera> Camera for FixedCamera<C> {
+    fn view(&self) -> Matrix4<f32> {
+        self.camera.view()
+    }
+
+    fn projection(&self, extent: [u32; 2]) -> Matrix4<f32> {
+        self.camera.projection(extent)
+    }
+
+    fn set_position(&mut self, pos: Point3<f32>) {
+        self.camera.set_position(pos);
+    }
+
+    fn set_rotation(&mut self, rot: UnitQuaternion<f32>) {
+        self.camera.set_rotation(rot);
+    }
+
+    fn linearize_depth(&self, depth: f32) -> f32 {
+        self.camera.linearize_depth(depth)
+    }
+}
+
+impl<C: Camera> InteractiveCamera for FixedCamera<C> {
+    fn update(&mut self) {
+        // do nothing
+    }
+
+    fn handle_event(&mut self, _extent: [u32; 2], _input: &winit::event::WindowEvent) {
+        // do nothing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::dpi::PhysicalPosition;
+    use winit::event::{DeviceId, ModifiersState, WindowEvent};
+
+    const DT: f32 = 1.0 / 60.0;
+
+    #[allow(deprecated)]
+    fn left_button(state: ElementState) -> WindowEvent<'static> {
+        WindowEvent::MouseInput {
+            device_id: unsafe { DeviceId::dummy() },
+            state,
+            button: MouseButton::Left,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    #[allow(deprecated)]
+    fn cursor_at(x: f64) -> WindowEvent<'static> {
+        WindowEvent::CursorMoved {
+            device_id: unsafe { DeviceId::dummy() },
+            position: PhysicalPosition::new(x, 50.0),
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    // drags the camera sideways over one update, optionally holds still for another, and lets go
+    fn drag_and_release(camera: &mut SphericalCamera, hold_still: bool) {
+        let extent = [100, 100];
+        camera.handle_event(extent, &cursor_at(50.0));
+        camera.handle_event(extent, &left_button(ElementState::Pressed));
+        camera.update_by(DT);
+        camera.handle_event(extent, &cursor_at(55.0));
+        camera.handle_event(extent, &cursor_at(60.0));
+        camera.update_by(DT);
+        if hold_still {
+            camera.update_by(DT);
+        }
+        camera.handle_event(extent, &left_button(ElementState::Released));
+    }
+
+    // how far the camera turns on its own in each of the next few updates
+    fn glide(camera: &mut SphericalCamera) -> Vec<f32> {
+        (0..4)
+            .map(|_| {
+                let yaw = camera.yaw;
+                camera.update_by(DT);
+                camera.yaw - yaw
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spherical_camera_stays_offset_away() {
+        let mut camera = SphericalCamera::new();
+        camera.set_position(Point3::new(1.0, 2.0, 3.0));
+        let rotations = [
+            (0.0, 0.0, UnitQuaternion::identity()),
+            (0.7, 0.3, UnitQuaternion::from_euler_angles(0.0, 1.2, 0.0)),
+            (-2.5, -1.0, UnitQuaternion::from_euler_angles(0.4, -0.8, 2.0)),
+            (10.0, 3.0, UnitQuaternion::from_euler_angles(-1.0, 0.0, 0.5)),
+        ];
+        for (dyaw, dpitch, root_rot) in rotations {
+            camera.rotate(dyaw, dpitch);
+            camera.set_rotation(root_rot);
+            let distance = (camera.eye() - camera.target()).norm();
+            assert!((distance - camera.offset).abs() < 1e-5, "eye is {distance} from the target");
+        }
+    }
+
+    #[test]
+    fn top_down_centers_its_view() {
+        let center = Point3::new(3.0, 1.0, -2.0);
+        let camera = BEVCamera::top_down(center, 10.0, -5.0, 5.0);
+        let mvp = camera.mvp([64, 48]);
+        assert!(mvp.iter().all(|x| x.is_finite()));
+        let clip = mvp * center.to_homogeneous();
+        let ndc = clip.xyz() / clip.w;
+        assert!(ndc.x.abs() < 1e-5 && ndc.y.abs() < 1e-5, "center is at {ndc:?}");
+        // and it's between the clip planes
+        assert!((0.0..=1.0).contains(&ndc.z));
+    }
+
+    #[test]
+    fn spherical_camera_doesnt_glide_without_momentum() {
+        let mut camera = SphericalCamera::new();
+        drag_and_release(&mut camera, false);
+        assert!(camera.yaw != 0.0);
+        assert_eq!(glide(&mut camera), vec![0.0; 4]);
+
+        camera.set_momentum_enabled(true);
+        camera.set_momentum_enabled(false);
+        drag_and_release(&mut camera, false);
+        assert_eq!(glide(&mut camera), vec![0.0; 4]);
+    }
+
+    #[test]
+    fn spherical_camera_glide_is_damped() {
+        let mut camera = SphericalCamera::new();
+        camera.set_momentum_enabled(true);
+        camera.set_damping_factor(0.5);
+        drag_and_release(&mut camera, false);
+        let steps = glide(&mut camera);
+        assert!(steps[0] != 0.0);
+        for pair in steps.windows(2) {
+            assert!((pair[1] / pair[0] - 0.5).abs() < 1e-4, "glide went {steps:?}");
+        }
+
+        // with no damping, the camera stops as soon as it's let go
+        camera.set_damping_factor(0.0);
+        drag_and_release(&mut camera, false);
+        let steps = glide(&mut camera);
+        assert!(steps[0] != 0.0);
+        assert_eq!(steps[1..], [0.0; 3]);
+    }
+
+    #[test]
+    fn spherical_camera_doesnt_glide_after_holding_still() {
+        let mut camera = SphericalCamera::new();
+        camera.set_momentum_enabled(true);
+        drag_and_release(&mut camera, true);
+        assert_eq!(glide(&mut camera), vec![0.0; 4]);
+    }
+}
@@ -1,48 +1,159 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 
+use image::RgbaImage;
 use nalgebra::Isometry3;
+use nalgebra::Matrix4;
+use nalgebra::Point3;
 use nalgebra::Vector3;
-use rapier3d::dynamics::CCDSolver;
-use rapier3d::dynamics::ImpulseJointSet;
-use rapier3d::dynamics::IntegrationParameters;
-use rapier3d::dynamics::IslandManager;
-use rapier3d::dynamics::MultibodyJointSet;
-use rapier3d::dynamics::RigidBodyBuilder;
-use rapier3d::dynamics::RigidBodyHandle;
-use rapier3d::dynamics::RigidBodySet;
-use rapier3d::geometry::ColliderBuilder;
-use rapier3d::geometry::ColliderSet;
-use rapier3d::geometry::NarrowPhase;
-use rapier3d::pipeline::PhysicsPipeline;
-use rapier3d::prelude::DefaultBroadPhase;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rapier3d::dynamics::ImpulseJointHandle;
+use vulkano::buffer::allocator::SubbufferAllocator;
+use vulkano::buffer::allocator::SubbufferAllocatorCreateInfo;
+use vulkano::buffer::Buffer;
+use vulkano::buffer::BufferCreateInfo;
+use vulkano::buffer::BufferUsage;
 use vulkano::buffer::Subbuffer;
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::command_buffer::CommandBufferUsage;
+use vulkano::command_buffer::CopyBufferToImageInfo;
+use vulkano::command_buffer::PrimaryCommandBufferAbstract;
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::layout::DescriptorSetLayout;
+use vulkano::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor_set::WriteDescriptorSet;
 use vulkano::device::DeviceOwned;
 use vulkano::device::Queue;
+use vulkano::format::Format;
+use vulkano::image::sampler::Sampler;
+use vulkano::image::sampler::SamplerAddressMode;
+use vulkano::image::sampler::SamplerCreateInfo;
+use vulkano::image::view::ImageView;
+use vulkano::image::view::ImageViewCreateInfo;
+use vulkano::image::view::ImageViewType;
+use vulkano::image::Image;
+use vulkano::image::ImageCreateFlags;
+use vulkano::image::ImageCreateInfo;
+use vulkano::image::ImageType;
+use vulkano::image::ImageUsage;
+use vulkano::memory::allocator::AllocationCreateInfo;
+use vulkano::memory::allocator::MemoryTypeFilter;
 use vulkano::memory::allocator::StandardMemoryAllocator;
+use vulkano::pipeline::cache::PipelineCache;
+use vulkano::pipeline::cache::PipelineCacheCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::PipelineShaderStageCreateInfo;
 use vulkano::shader::EntryPoint;
+use vulkano::swapchain::PresentMode;
 use vulkano::swapchain::Surface;
+use vulkano::sync::GpuFuture;
 
 use crate::camera;
 use crate::camera::Camera;
 use crate::camera::InteractiveCamera;
+use crate::handle_user_input::ControlAction;
+use crate::handle_user_input::ControlBindings;
 use crate::handle_user_input::UserInputState;
+use crate::light::DirectionalLight;
+use crate::light::PointLight;
+use crate::light::MAX_POINT_LIGHTS;
 use crate::object;
+use crate::physics::PhysicsWorld;
 use crate::render_system::interactive_rendering;
 use crate::render_system::offscreen_rendering;
 use crate::render_system::scene::Scene;
+use crate::render_system::scene::DrawBuffers;
+use crate::render_system::scene::InstanceData;
+use crate::render_system::shadow_map::ShadowMap;
 use crate::shader;
 use crate::vertex::mVertex;
 
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColliderShape {
+    // box fitted to the axis-aligned bounding box of the mesh
+    Cuboid,
+    Ball {
+        radius: f32,
+    },
+    // capsule along the y axis
+    Capsule {
+        half_height: f32,
+        radius: f32,
+    },
+    // convex hull of the mesh's vertices, or the cuboid if the mesh is flat
+    ConvexHull,
+    // the mesh's triangles themselves. best used for static objects
+    TriMesh,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EntityCreationPhysicsData {
     // if true, the object can be moved by the physics engine
     // if false, then the object will not move due to forces. If hitbox is specified, it can still be collided with
     pub is_dynamic: bool,
+    // shape of the hitbox
+    pub shape: ColliderShape,
+    // how much grip the hitbox has against other objects
+    pub friction: f32,
+    // how bouncy the hitbox is, 0 means no bounce at all
+    pub restitution: f32,
+    // if true, the hitbox only detects overlaps (see `GameWorld::intersections`) and doesn't push other objects
+    pub is_sensor: bool,
+    // overrides the mass computed from the hitbox's volume. the moment of inertia is scaled to match
+    pub mass: Option<f32>,
+    // overrides the center of mass (in the entity's local frame), e.g. lower it to keep a vehicle from tipping over
+    pub center_of_mass: Option<Point3<f32>>,
+    // world x, y and z axes the body can't move along, e.g. lock y to keep a vehicle on the ground
+    pub locked_translations: [bool; 3],
+    // world x, y and z axes the body can't rotate around, e.g. lock x and z to keep an object from tipping over
+    pub locked_rotations: [bool; 3],
+}
+
+impl Default for EntityCreationPhysicsData {
+    // same as rapier's defaults
+    fn default() -> Self {
+        EntityCreationPhysicsData {
+            is_dynamic: false,
+            shape: ColliderShape::Cuboid,
+            friction: 0.5,
+            restitution: 0.0,
+            is_sensor: false,
+            mass: None,
+            center_of_mass: None,
+            locked_translations: [false; 3],
+            locked_rotations: [false; 3],
+        }
+    }
 }
 
 pub struct EntityCreationCameraData {
     pub camera: Box<dyn Camera>,
     pub extent: [u32; 2],
+    // if true, the camera also renders a segmentation mask, where each entity is drawn in the flat color given by `segmentation_color`
+    pub segmentation: bool,
+}
+
+/// the entity id of observations that don't come from an entity's camera, see `GameWorld::render_from`
+pub const NO_ENTITY: u32 = u32::MAX;
+
+/// what one of an entity's cameras saw during a `step`
+#[derive(Clone, Debug)]
+pub struct CameraObservation {
+    pub entity_id: u32,
+    // index into the entity's `EntityCreationData::cameras`
+    pub camera_index: usize,
+    // width and height of the image, in pixels
+    pub extent: [u32; 2],
+    // number of color channels per pixel, e.g. 4 for RGBA
+    pub channels: u8,
+    // the image, row by row from the top, with each pixel's channels next to each other
+    pub pixels: Vec<u8>,
 }
 
 pub struct EntityCreationData {
@@ -52,25 +163,36 @@ pub struct EntityCreationData {
     pub physics: Option<EntityCreationPhysicsData>,
     // mesh (untransformed)
     pub mesh: Vec<mVertex>,
+    // if specified, the mesh's triangles are given by indices into its vertices
+    pub indices: Option<Vec<u32>>,
     // initial transformation
     // position and rotation in space
     pub isometry: Isometry3<f32>,
+    // unique name the entity can be looked up by, see `GameWorld::entity_by_name`
+    pub name: Option<String>,
+    // labels that any number of entities can share, see `GameWorld::entities_with_tag`
+    pub tags: Vec<String>,
 }
 
 struct PerCameraData {
     camera: Box<dyn Camera>,
     renderer: offscreen_rendering::Renderer<mVertex>,
+    segmentation_renderer: Option<offscreen_rendering::Renderer<mVertex>>,
 }
 
 struct Entity {
     // gather data
     cameras: Vec<PerCameraData>,
-    // physics
-    rigid_body_handle: Option<RigidBodyHandle>,
+    // what the rigid body was created from, kept so it can be rebuilt by `load_state`
+    physics: Option<EntityCreationPhysicsData>,
     // mesh (untransformed)
     mesh: Vec<mVertex>,
-    // transformation from origin
+    indices: Option<Vec<u32>>,
+    // where the entity is drawn and its cameras are. for entities with physics this is only updated from the rigid body
+    // in `submit_observations`, and may be interpolated: the simulated pose is always the one in `physics`
     isometry: Isometry3<f32>,
+    name: Option<String>,
+    tags: Vec<String>,
 }
 
 struct PerWindowState {
@@ -78,13 +200,504 @@ struct PerWindowState {
     surface: Arc<Surface>,
     camera: Box<dyn InteractiveCamera>,
     renderer: interactive_rendering::Renderer<mVertex>,
+    // whether the cursor is grabbed for mouse look
+    mouse_captured: bool,
 }
 
 struct PerDeviceState {
     queue: Arc<Queue>,
+    // used to read back offscreen renders, may be the same as `queue`
+    transfer_queue: Arc<Queue>,
     memory_allocator: Arc<StandardMemoryAllocator>,
     fs: EntryPoint,
     vs: EntryPoint,
+    seg_fs: EntryPoint,
+    seg_vs: EntryPoint,
+    // the point lights are uploaded to a fresh uniform buffer every frame
+    uniform_buffer_allocator: SubbufferAllocator,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    // layout of set 0 of the lit shaders, which holds the point lights and the shadow map
+    lighting_set_layout: Arc<DescriptorSetLayout>,
+    // layout of set 1 of the lit shaders, which holds the object's texture
+    texture_set_layout: Arc<DescriptorSetLayout>,
+    texture_sampler: Arc<Sampler>,
+    sky_vs: EntryPoint,
+    sky_fs: EntryPoint,
+    // layout of set 0 of the sky shaders, which holds the cubemap and the camera's orientation
+    sky_set_layout: Arc<DescriptorSetLayout>,
+    sky_sampler: Arc<Sampler>,
+    shadow_vs: EntryPoint,
+    shadow_fs: EntryPoint,
+    shadow_sampler: Arc<Sampler>,
+    // used for one-off uploads, like the skybox
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    // shared by every renderer, so pipelines that were built before don't have to be compiled again
+    pipeline_cache: Arc<PipelineCache>,
+}
+
+/// the color an entity is drawn with in segmentation masks.
+/// the color encodes `entity_id + 1`, one byte per channel, so that the black background never matches an entity
+pub fn segmentation_color(entity_id: u32) -> [f32; 4] {
+    let code = entity_id + 1;
+    assert!(code < 1 << 24, "entity id too large to be encoded in a segmentation color");
+    [
+        (code & 0xFF) as f32 / 255.0,
+        ((code >> 8) & 0xFF) as f32 / 255.0,
+        ((code >> 16) & 0xFF) as f32 / 255.0,
+        1.0,
+    ]
+}
+
+/// the entity id encoded by a pixel of a segmentation mask, or None for the background
+pub fn segmentation_entity([r, g, b]: [u8; 3]) -> Option<u32> {
+    let code = r as u32 | (g as u32) << 8 | (b as u32) << 16;
+    code.checked_sub(1)
+}
+
+fn push_constant_data(mvp: Matrix4<f32>, light: &DirectionalLight) -> shader::vert::PushConstantData {
+    let [r, g, b] = light.color;
+    shader::vert::PushConstantData {
+        mvp: mvp.into(),
+        light_dir: light.direction.normalize().push(0.0).into(),
+        light_color: [r, g, b, 1.0],
+    }
+}
+
+/// an image uploaded for sampling by the lit shaders, see `GameWorld::create_texture`
+#[derive(Clone)]
+pub struct Texture(Arc<PersistentDescriptorSet>);
+
+// the lit shaders always sample a texture, so draws without one get `default_texture` (a single white texel)
+fn with_texture(mut draw_buffers: DrawBuffers<mVertex>, default_texture: &Texture) -> DrawBuffers<mVertex> {
+    draw_buffers
+        .texture
+        .get_or_insert_with(|| default_texture.0.clone());
+    draw_buffers
+}
+
+// the opaque objects of both scenes, followed by the textured objects and the instanced meshes.
+// if `view_proj` is given, objects entirely outside its view volume are left out (instanced meshes are always drawn)
+fn opaque_draw_buffers(
+    dynamic_scene: &mut Scene<u32, mVertex>,
+    static_scene: &mut Scene<u32, mVertex>,
+    default_texture: &Texture,
+    view_proj: Option<Matrix4<f32>>,
+) -> Vec<DrawBuffers<mVertex>> {
+    let (untextured, textured) = match view_proj {
+        Some(view_proj) => {
+            let untextured = dynamic_scene
+                .visible_draw_buffers(view_proj)
+                .into_iter()
+                .chain(static_scene.visible_draw_buffers(view_proj))
+                .collect::<Vec<_>>();
+            let textured = dynamic_scene
+                .visible_object_draw_buffers(view_proj)
+                .into_iter()
+                .chain(static_scene.visible_object_draw_buffers(view_proj))
+                .filter(|(_, draw_buffers)| draw_buffers.texture.is_some())
+                .collect::<Vec<_>>();
+            (untextured, textured)
+        }
+        None => {
+            let untextured = [dynamic_scene.draw_buffers(), static_scene.draw_buffers()]
+                .into_iter()
+                .flatten()
+                .collect();
+            let textured = dynamic_scene
+                .textured_draw_buffers()
+                .into_iter()
+                .chain(static_scene.textured_draw_buffers())
+                .collect();
+            (untextured, textured)
+        }
+    };
+    untextured
+        .into_iter()
+        .chain(
+            textured
+                .into_iter()
+                .chain(static_scene.instanced_draw_buffers())
+                .map(|(_, draw_buffers)| draw_buffers),
+        )
+        .map(|draw_buffers| with_texture(draw_buffers, default_texture))
+        .collect()
+}
+
+// the transparent objects of both scenes, sorted back to front from where the camera is
+fn transparent_draw_buffers<C: Camera + ?Sized>(
+    dynamic_scene: &mut Scene<u32, mVertex>,
+    static_scene: &mut Scene<u32, mVertex>,
+    camera: &C,
+    default_texture: &Texture,
+) -> Vec<(u32, DrawBuffers<mVertex>)> {
+    let inverse_view = camera.view().try_inverse().unwrap_or_else(Matrix4::identity);
+    let eye = Point3::from(inverse_view.fixed_view::<3, 1>(0, 3).into_owned());
+    let mut buffers = dynamic_scene.transparent_draw_buffers(eye);
+    buffers.extend(static_scene.transparent_draw_buffers(eye));
+    buffers.sort_by(|a, b| b.1.total_cmp(&a.1));
+    buffers
+        .into_iter()
+        .map(|(entity_id, _, draw_buffers)| (entity_id, with_texture(draw_buffers, default_texture)))
+        .collect()
+}
+
+// uploads the point lights and the shadow parameters, and wraps them with the shadow map in a descriptor set for the
+// lit shaders. `light_view_proj` is what the shadow map was rendered with, or None if shadows are disabled
+fn lighting_descriptor_set(
+    per_device_state: &PerDeviceState,
+    point_lights: &[PointLight],
+    shadow_map: &ShadowMap<mVertex>,
+    light_view_proj: Option<Matrix4<f32>>,
+) -> Arc<PersistentDescriptorSet> {
+    let lights = std::array::from_fn(|i| match point_lights.get(i) {
+        Some(light) => {
+            let [r, g, b] = light.color;
+            shader::frag::PointLight {
+                position: light.position.coords.push(light.radius).into(),
+                color: [r, g, b, 1.0],
+            }
+        }
+        None => shader::frag::PointLight {
+            position: [0.0; 4],
+            color: [0.0; 4],
+        },
+    });
+    let buffer = per_device_state
+        .uniform_buffer_allocator
+        .allocate_sized()
+        .unwrap();
+    *buffer.write().unwrap() = shader::frag::PointLights {
+        lights,
+        count: point_lights.len() as u32,
+    };
+    let shadow_buffer = per_device_state
+        .uniform_buffer_allocator
+        .allocate_sized()
+        .unwrap();
+    *shadow_buffer.write().unwrap() = shader::frag::Shadow {
+        light_view_proj: light_view_proj.unwrap_or_else(Matrix4::identity).into(),
+        enabled: light_view_proj.is_some() as u32,
+    };
+    PersistentDescriptorSet::new(
+        &per_device_state.descriptor_set_allocator,
+        per_device_state.lighting_set_layout.clone(),
+        [
+            WriteDescriptorSet::buffer(0, buffer),
+            WriteDescriptorSet::image_view_sampler(
+                1,
+                shadow_map.image_view(),
+                per_device_state.shadow_sampler.clone(),
+            ),
+            WriteDescriptorSet::buffer(2, shadow_buffer),
+        ],
+        [],
+    )
+    .unwrap()
+}
+
+// uploads each of `layers` (which must all be the same size) into a layer of a sampled image
+fn upload_image(
+    per_device_state: &PerDeviceState,
+    layers: &[RgbaImage],
+    flags: ImageCreateFlags,
+) -> Arc<Image> {
+    let (width, height) = layers[0].dimensions();
+    let buffer = Buffer::from_iter(
+        per_device_state.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        layers.iter().flat_map(|layer| layer.as_raw().iter().copied()),
+    )
+    .unwrap();
+    let image = Image::new(
+        per_device_state.memory_allocator.clone(),
+        ImageCreateInfo {
+            flags,
+            image_type: ImageType::Dim2d,
+            format: Format::R8G8B8A8_SRGB,
+            extent: [width, height, 1],
+            array_layers: layers.len() as u32,
+            usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        &per_device_state.command_buffer_allocator,
+        per_device_state.queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+    builder
+        .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(buffer, image.clone()))
+        .unwrap();
+    builder
+        .build()
+        .unwrap()
+        .execute(per_device_state.queue.clone())
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+    image
+}
+
+// uploads the image and wraps it in a descriptor set for the lit shaders
+fn create_texture(per_device_state: &PerDeviceState, image: &RgbaImage) -> Texture {
+    let image = upload_image(
+        per_device_state,
+        std::slice::from_ref(image),
+        ImageCreateFlags::empty(),
+    );
+    Texture(
+        PersistentDescriptorSet::new(
+            &per_device_state.descriptor_set_allocator,
+            per_device_state.texture_set_layout.clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                ImageView::new_default(image).unwrap(),
+                per_device_state.texture_sampler.clone(),
+            )],
+            [],
+        )
+        .unwrap(),
+    )
+}
+
+// uploads the six faces into a cubemap
+fn upload_cubemap(
+    per_device_state: &PerDeviceState,
+    faces: &[RgbaImage; 6],
+) -> Result<Arc<ImageView>, GameWorldError> {
+    let (width, height) = faces[0].dimensions();
+    if width != height || faces.iter().any(|face| face.dimensions() != (width, height)) {
+        return Err(GameWorldError::InvalidSkybox);
+    }
+    let image = upload_image(per_device_state, faces, ImageCreateFlags::CUBE_COMPATIBLE);
+    Ok(ImageView::new(
+        image.clone(),
+        ImageViewCreateInfo {
+            view_type: ImageViewType::Cube,
+            ..ImageViewCreateInfo::from_image(&image)
+        },
+    )
+    .unwrap())
+}
+
+// starts rendering the lit scenes (and the skybox, if any) from `camera` into `renderer`, without waiting for it to finish
+fn render_scenes<C: Camera + ?Sized>(
+    renderer: &mut offscreen_rendering::Renderer<mVertex>,
+    camera: &C,
+    dynamic_scene: &mut Scene<u32, mVertex>,
+    static_scene: &mut Scene<u32, mVertex>,
+    per_device_state: &PerDeviceState,
+    light: &DirectionalLight,
+    lighting_set: Arc<PersistentDescriptorSet>,
+    skybox: Option<&Arc<ImageView>>,
+    default_texture: &Texture,
+) {
+    let extent = renderer.extent();
+    let mvp = camera.mvp(extent);
+    let push_data = push_constant_data(mvp, light);
+    let vertex_buffers = opaque_draw_buffers(dynamic_scene, static_scene, default_texture, Some(mvp))
+        .into_iter()
+        .map(|draw_buffers| (draw_buffers, push_data.clone()));
+    let transparent_buffers =
+        transparent_draw_buffers(dynamic_scene, static_scene, camera, default_texture)
+            .into_iter()
+            .map(|(_, draw_buffers)| (draw_buffers, push_data.clone()))
+            .collect::<Vec<_>>();
+    renderer.set_descriptor_sets(vec![lighting_set]);
+    if let Some(skybox) = skybox {
+        renderer.set_background_descriptor_sets(vec![sky_descriptor_set(
+            per_device_state,
+            skybox,
+            camera,
+            extent,
+        )]);
+    }
+    renderer.render_objects_with_transparent(vertex_buffers, transparent_buffers);
+}
+
+// uploads the camera's orientation and wraps it with the skybox in a descriptor set for the sky shaders
+fn sky_descriptor_set<C: Camera + ?Sized>(
+    per_device_state: &PerDeviceState,
+    skybox: &Arc<ImageView>,
+    camera: &C,
+    extent: [u32; 2],
+) -> Arc<PersistentDescriptorSet> {
+    // the sky is infinitely far away, so only the direction the camera faces matters
+    let mut view = camera.view();
+    view.fixed_view_mut::<3, 1>(0, 3).fill(0.0);
+    let inv_view_proj = (camera.projection(extent) * view)
+        .try_inverse()
+        .unwrap_or_else(Matrix4::identity);
+    let buffer = per_device_state
+        .uniform_buffer_allocator
+        .allocate_sized()
+        .unwrap();
+    *buffer.write().unwrap() = shader::sky_vert::SkyView {
+        inv_view_proj: inv_view_proj.into(),
+    };
+    PersistentDescriptorSet::new(
+        &per_device_state.descriptor_set_allocator,
+        per_device_state.sky_set_layout.clone(),
+        [
+            WriteDescriptorSet::image_view_sampler(
+                0,
+                skybox.clone(),
+                per_device_state.sky_sampler.clone(),
+            ),
+            WriteDescriptorSet::buffer(1, buffer),
+        ],
+        [],
+    )
+    .unwrap()
+}
+
+// add the entity's mesh to the scene, transformed into world space
+fn add_mesh_to_scene(
+    scene: &mut Scene<u32, mVertex>,
+    entity_id: u32,
+    mesh: &Vec<mVertex>,
+    indices: &Option<Vec<u32>>,
+    isometry: &Isometry3<f32>,
+) {
+    let vertices = object::transform(mesh, isometry);
+    match indices {
+        Some(indices) => scene.add_indexed_object(entity_id, vertices, indices.clone()),
+        None => scene.add_object(entity_id, vertices),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContactKind {
+    // the two entities started touching during the step
+    Started,
+    // the two entities stopped touching during the step
+    Stopped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameWorldError {
+    // no entity with this id exists
+    EntityNotFound(u32),
+    // the entity exists, but is visual only and has no rigid body
+    NoRigidBody(u32),
+    // the skybox faces aren't all square and the same size
+    InvalidSkybox,
+}
+
+impl std::fmt::Display for GameWorldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameWorldError::EntityNotFound(entity_id) => write!(f, "entity {entity_id} not found"),
+            GameWorldError::NoRigidBody(entity_id) => {
+                write!(f, "entity {entity_id} has no rigid body")
+            }
+            GameWorldError::InvalidSkybox => {
+                write!(f, "skybox faces must all be square and the same size")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GameWorldError {}
+
+/// the saved state of a single entity. its mesh and cameras aren't saved: the entity id is used to find them again
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EntityState {
+    pub id: u32,
+    pub isometry: Isometry3<f32>,
+    // zero for entities without physics
+    pub linear_velocity: Vector3<f32>,
+    pub angular_velocity: Vector3<f32>,
+    pub physics: Option<EntityCreationPhysicsData>,
+}
+
+/// a snapshot of the world, see `GameWorld::save_state` and `GameWorld::load_state`
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WorldState {
+    // sorted by id
+    pub entities: Vec<EntityState>,
+}
+
+/// how hard the driving controls push the tracked entity.
+/// both are impulses applied once per physics tick, so the change in velocity they cause is divided by the body's mass
+/// (and the change in angular velocity by its angular inertia): heavier vehicles need proportionally larger values
+#[derive(Clone, Copy, Debug)]
+pub struct VehicleParams {
+    // forward/backward impulse, along the entity's local x axis
+    pub acceleration_impulse: f32,
+    // torque impulse around the y axis
+    pub steering_torque: f32,
+}
+
+impl Default for VehicleParams {
+    fn default() -> Self {
+        VehicleParams {
+            acceleration_impulse: 0.09,
+            steering_torque: 0.01,
+        }
+    }
+}
+
+// width and height of the shadow map, in pixels
+const SHADOW_MAP_SIZE: u32 = 2048;
+
+/// how far from the tracked entity shadows are drawn, in world units
+pub const SHADOW_HALF_EXTENT: f32 = 50.0;
+
+// weight of the newest frame in the smoothed fps
+const FPS_SMOOTHING: f32 = 0.1;
+
+// time between calls to something that happens once per frame
+struct FrameTiming {
+    // when the last frame happened, None before the first one
+    last_frame: Option<Instant>,
+    // time between the last two frames
+    last_frame_time: Duration,
+    // exponential moving average of the frame rate
+    fps: f32,
+}
+
+impl FrameTiming {
+    fn new() -> FrameTiming {
+        FrameTiming {
+            last_frame: None,
+            last_frame_time: Duration::ZERO,
+            fps: 0.0,
+        }
+    }
+
+    fn tick(&mut self) {
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame.replace(now) {
+            self.last_frame_time = now.duration_since(last_frame);
+            let frame_secs = self.last_frame_time.as_secs_f32();
+            if frame_secs > 0.0 {
+                let instant_fps = 1.0 / frame_secs;
+                // start from the first measurement instead of ramping up from 0
+                self.fps = if self.fps == 0.0 {
+                    instant_fps
+                } else {
+                    self.fps + (instant_fps - self.fps) * FPS_SMOOTHING
+                };
+            }
+        }
+    }
 }
 
 pub struct GameWorld {
@@ -94,51 +707,168 @@ pub struct GameWorld {
     // scene for objects that change frequently (e.g. cars, pedestrians)
     static_scene: Scene<u32, mVertex>,
     // physics data
-    rigid_body_set: RigidBodySet,
-    collider_set: ColliderSet,
-    physics_pipeline: PhysicsPipeline,
-    island_manager: IslandManager,
-    broad_phase: DefaultBroadPhase,
-    narrow_phase: NarrowPhase,
-    impulse_joint_set: ImpulseJointSet,
-    multibody_joint_set: MultibodyJointSet,
-    ccd_solver: CCDSolver,
+    physics: PhysicsWorld,
+    // which entity has each name
+    entity_names: HashMap<String, u32>,
+    // pairs of entities overlapping a sensor after the last step
+    intersections: Vec<(u32, u32)>,
     // state per window
     per_window_state: Option<PerWindowState>,
     // per device vulkan objects
     per_device_state: PerDeviceState,
     // handle user input
     user_input_state: UserInputState,
+    // keys used to drive the tracked entity
+    control_bindings: ControlBindings,
+    // strength of the driving controls
+    vehicle_params: VehicleParams,
+    // None if gamepad support couldn't be initialized
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
+    // light shining on the whole scene
+    light: DirectionalLight,
+    // at most MAX_POINT_LIGHTS
+    point_lights: Vec<PointLight>,
+    // seeded, so that any randomness in spawning is reproducible
+    rng: StdRng,
+    // cubemap drawn behind the scene, if any
+    skybox: Option<Arc<ImageView>>,
+    // used by everything without a texture of its own
+    default_texture: Texture,
+    // depth of the scene from the directional light, only rendered if shadows are enabled
+    shadow_map: ShadowMap<mVertex>,
+    // what the shadow map was last rendered with, None if shadows are disabled
+    light_view_proj: Option<Matrix4<f32>>,
+    shadows_enabled: bool,
+    // if true, entities are drawn between their last two physics poses instead of at the latest one
+    interpolation_enabled: bool,
+    // renderers used by `render_from`, one per extent
+    capture_renderers: HashMap<[u32; 2], offscreen_rendering::Renderer<mVertex>>,
+    // timing of the calls to `render`
+    render_timing: FrameTiming,
+    // timing of the calls to the step functions, reported instead if the world is never rendered
+    step_timing: FrameTiming,
 }
 
 pub struct InteractiveRenderingConfig {
     pub tracking_entity: u32,
     pub surface: Arc<Surface>,
     pub camera: Box<dyn InteractiveCamera>,
+    // Fifo for vsync, Mailbox or Immediate for lower latency
+    pub present_mode: PresentMode,
 }
 
 impl GameWorld {
+    /// `seed` initializes the world's random number generator (see `rng`),
+    /// so two worlds created with the same seed and given the same inputs evolve identically
     pub fn new(
         queue: Arc<Queue>,
+        transfer_queue: Arc<Queue>,
         memory_allocator: Arc<StandardMemoryAllocator>,
         interactive_rendering_config: Option<InteractiveRenderingConfig>,
+        seed: u64,
     ) -> GameWorld {
         let device = queue.device();
 
         assert!(device == memory_allocator.device());
+        assert!(device == transfer_queue.device());
 
         // initialize vulkan objects
+        let vs = shader::vert::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+        let fs = shader::frag::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+        let (lighting_set_layout, texture_set_layout) = {
+            let stages = [vs.clone(), fs.clone()].map(PipelineShaderStageCreateInfo::new);
+            let layout_create_info = PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages);
+            let set_layout = |set: usize| {
+                DescriptorSetLayout::new(device.clone(), layout_create_info.set_layouts[set].clone())
+                    .unwrap()
+            };
+            (set_layout(0), set_layout(1))
+        };
+        let sky_vs = shader::sky_vert::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+        let sky_fs = shader::sky_frag::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+        let sky_set_layout = {
+            let stages = [sky_vs.clone(), sky_fs.clone()].map(PipelineShaderStageCreateInfo::new);
+            let layout_create_info = PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages);
+            DescriptorSetLayout::new(device.clone(), layout_create_info.set_layouts[0].clone())
+                .unwrap()
+        };
         let per_device_state = PerDeviceState {
             queue: queue.clone(),
+            transfer_queue,
             memory_allocator: memory_allocator.clone(),
-            vs: shader::vert::load(device.clone())
+            vs,
+            fs,
+            seg_vs: shader::seg_vert::load(device.clone())
+                .unwrap()
+                .entry_point("main")
+                .unwrap(),
+            seg_fs: shader::seg_frag::load(device.clone())
                 .unwrap()
                 .entry_point("main")
                 .unwrap(),
-            fs: shader::frag::load(device.clone())
+            uniform_buffer_allocator: SubbufferAllocator::new(
+                memory_allocator.clone(),
+                SubbufferAllocatorCreateInfo {
+                    buffer_usage: BufferUsage::UNIFORM_BUFFER,
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+            ),
+            descriptor_set_allocator: StandardDescriptorSetAllocator::new(
+                device.clone(),
+                Default::default(),
+            ),
+            lighting_set_layout,
+            texture_set_layout,
+            texture_sampler: Sampler::new(
+                device.clone(),
+                SamplerCreateInfo::simple_repeat_linear_no_mipmap(),
+            )
+            .unwrap(),
+            sky_vs,
+            sky_fs,
+            sky_set_layout,
+            sky_sampler: Sampler::new(device.clone(), SamplerCreateInfo::simple_repeat_linear_no_mipmap())
+                .unwrap(),
+            shadow_vs: shader::shadow_vert::load(device.clone())
+                .unwrap()
+                .entry_point("main")
+                .unwrap(),
+            shadow_fs: shader::shadow_frag::load(device.clone())
                 .unwrap()
                 .entry_point("main")
                 .unwrap(),
+            shadow_sampler: Sampler::new(
+                device.clone(),
+                SamplerCreateInfo {
+                    address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                    ..Default::default()
+                },
+            )
+            .unwrap(),
+            command_buffer_allocator: StandardCommandBufferAllocator::new(
+                device.clone(),
+                Default::default(),
+            ),
+            // safe because the cache starts out empty, so there's no untrusted data for the driver to read
+            pipeline_cache: unsafe {
+                PipelineCache::new(device.clone(), PipelineCacheCreateInfo::default())
+            }
+            .unwrap(),
         };
 
         // initialize interactive rendering if necessary
@@ -147,23 +877,43 @@ impl GameWorld {
                 tracking_entity,
                 surface,
                 camera,
+                present_mode,
             }) => {
                 let renderer = interactive_rendering::Renderer::new(
                     vec![per_device_state.vs.clone(), per_device_state.fs.clone()],
                     surface.clone(),
                     per_device_state.queue.clone(),
                     per_device_state.memory_allocator.clone(),
+                    present_mode,
+                    Some(per_device_state.pipeline_cache.clone()),
+                    false,
                 );
                 Some(PerWindowState {
                     entity_id: tracking_entity,
                     camera,
                     surface,
                     renderer,
+                    mouse_captured: false,
                 })
             }
             None => None,
         };
 
+        let shadow_map = ShadowMap::new(
+            SHADOW_MAP_SIZE,
+            vec![
+                per_device_state.shadow_vs.clone(),
+                per_device_state.shadow_fs.clone(),
+            ],
+            per_device_state.queue.clone(),
+            per_device_state.memory_allocator.clone(),
+        );
+
+        let default_texture = create_texture(
+            &per_device_state,
+            &RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255])),
+        );
+
         let dynamic_scene = Scene::new(memory_allocator.clone(), HashMap::new());
         let static_scene = Scene::new(memory_allocator.clone(), HashMap::new());
 
@@ -171,85 +921,317 @@ impl GameWorld {
             entities: HashMap::new(),
             dynamic_scene,
             static_scene,
-            rigid_body_set: RigidBodySet::new(),
-            collider_set: ColliderSet::new(),
-            physics_pipeline: PhysicsPipeline::new(),
-            island_manager: IslandManager::new(),
-            broad_phase: DefaultBroadPhase::new(),
-            narrow_phase: NarrowPhase::new(),
-            impulse_joint_set: ImpulseJointSet::new(),
-            multibody_joint_set: MultibodyJointSet::new(),
-            ccd_solver: CCDSolver::new(),
+            physics: PhysicsWorld::new(),
+            entity_names: HashMap::new(),
+            intersections: vec![],
             per_device_state,
             per_window_state,
             user_input_state: UserInputState::new(),
+            control_bindings: ControlBindings::default(),
+            vehicle_params: VehicleParams::default(),
+            #[cfg(feature = "gamepad")]
+            gilrs: gilrs::Gilrs::new().ok(),
+            light: DirectionalLight::default(),
+            point_lights: vec![],
+            rng: StdRng::seed_from_u64(seed),
+            skybox: None,
+            default_texture,
+            shadow_map,
+            light_view_proj: None,
+            shadows_enabled: false,
+            interpolation_enabled: false,
+            capture_renderers: HashMap::new(),
+            render_timing: FrameTiming::new(),
+            step_timing: FrameTiming::new(),
+        }
+    }
+
+    /// the world's seeded random number generator. use this for any randomness in spawning or scene setup,
+    /// so that runs are reproducible. `step` itself never consumes randomness: rapier is deterministic
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    /// sets the light used by both the interactive and offscreen renderers
+    pub fn set_light(&mut self, light: DirectionalLight) {
+        self.light = light;
+    }
+
+    /// adds a point light, returning its index in `point_lights`.
+    /// panics if there are already MAX_POINT_LIGHTS lights
+    pub fn add_point_light(&mut self, light: PointLight) -> usize {
+        assert!(
+            self.point_lights.len() < MAX_POINT_LIGHTS,
+            "at most {MAX_POINT_LIGHTS} point lights are supported"
+        );
+        self.point_lights.push(light);
+        self.point_lights.len() - 1
+    }
+
+    /// removes the point light at `index`, shifting the lights after it down by one
+    pub fn remove_point_light(&mut self, index: usize) -> PointLight {
+        self.point_lights.remove(index)
+    }
+
+    pub fn clear_point_lights(&mut self) {
+        self.point_lights.clear();
+    }
+
+    /// draws a cubemap behind the scene in every (non segmentation) view, instead of the clear color.
+    /// `faces` must be square and the same size, in the order +x, -x, +y, -y, +z, -z
+    pub fn set_skybox(&mut self, faces: [RgbaImage; 6]) -> Result<(), GameWorldError> {
+        self.skybox = Some(upload_cubemap(&self.per_device_state, &faces)?);
+        self.set_background(true);
+        Ok(())
+    }
+
+    /// goes back to the plain clear color
+    pub fn clear_skybox(&mut self) {
+        self.skybox = None;
+        self.set_background(false);
+    }
+
+    // an offscreen renderer for the lit scenes, drawing the skybox if there is one
+    fn create_scene_renderer(&self, extent: [u32; 2]) -> offscreen_rendering::Renderer<mVertex> {
+        let mut renderer = offscreen_rendering::Renderer::new(
+            extent,
+            Format::R8G8B8A8_UNORM,
+            1,
+            vec![
+                self.per_device_state.vs.clone(),
+                self.per_device_state.fs.clone(),
+            ],
+            self.per_device_state.queue.clone(),
+            self.per_device_state.transfer_queue.clone(),
+            self.per_device_state.memory_allocator.clone(),
+            None,
+            Some(self.per_device_state.pipeline_cache.clone()),
+        );
+        if self.skybox.is_some() {
+            renderer.set_background(Some(vec![
+                self.per_device_state.sky_vs.clone(),
+                self.per_device_state.sky_fs.clone(),
+            ]));
+        }
+        renderer
+    }
+
+    // turns the sky pass on or off in every renderer that draws the scene
+    fn set_background(&mut self, sky: bool) {
+        let stages = sky.then(|| {
+            vec![
+                self.per_device_state.sky_vs.clone(),
+                self.per_device_state.sky_fs.clone(),
+            ]
+        });
+        for entity in self.entities.values_mut() {
+            for per_camera_data in entity.cameras.iter_mut() {
+                per_camera_data.renderer.set_background(stages.clone());
+            }
+        }
+        for renderer in self.capture_renderers.values_mut() {
+            renderer.set_background(stages.clone());
+        }
+        if let Some(ref mut per_window_state) = self.per_window_state {
+            per_window_state.renderer.set_background(stages);
         }
     }
 
-    pub fn step(&mut self) -> HashMap<u32, Vec<Vec<u8>>> {
-        // step physics
-        self.physics_pipeline.step(
-            &Vector3::new(0.0, -9.81, 0.0),
-            &IntegrationParameters::default(),
-            &mut self.island_manager,
-            &mut self.broad_phase,
-            &mut self.narrow_phase,
-            &mut self.rigid_body_set,
-            &mut self.collider_set,
-            &mut self.impulse_joint_set,
-            &mut self.multibody_joint_set,
-            &mut self.ccd_solver,
+    /// turns shadows from the directional light on or off. shadows cost an extra render pass every `step`,
+    /// and only cover the area within SHADOW_HALF_EXTENT of the tracked entity (or of the origin, without a window)
+    pub fn set_shadows_enabled(&mut self, enabled: bool) {
+        self.shadows_enabled = enabled;
+    }
+
+    // renders the shadow map around the tracked entity if shadows are enabled, and remembers what it was rendered with
+    fn update_shadow_map(&mut self) {
+        if !self.shadows_enabled {
+            self.light_view_proj = None;
+            return;
+        }
+        let center = self
+            .per_window_state
+            .as_ref()
+            .and_then(|per_window_state| self.entities.get(&per_window_state.entity_id))
+            .map_or(Point3::origin(), |entity| entity.isometry.translation.vector.into());
+        let light_view_proj = self.light.view_projection(center, SHADOW_HALF_EXTENT);
+        // things outside the camera's view can still cast shadows into it, so nothing is culled here
+        let vertex_buffers = opaque_draw_buffers(
+            &mut self.dynamic_scene,
+            &mut self.static_scene,
+            &self.default_texture,
             None,
-            &(),
-            &(),
         );
+        self.shadow_map.render(
+            vertex_buffers,
+            shader::shadow_vert::PushConstantData {
+                mvp: light_view_proj.into(),
+            },
+        );
+        self.light_view_proj = Some(light_view_proj);
+    }
+
+    pub fn point_lights(&self) -> &[PointLight] {
+        &self.point_lights
+    }
+
+    /// puts an infinite, fixed floor at height `y` that everything above it rests on, replacing any previous one.
+    /// this is a physics-only collider: it has no mesh, so add an entity if the ground should be visible.
+    /// it doesn't belong to any entity, so it never shows up in `collision_events` or `intersections`
+    pub fn set_ground_plane(&mut self, y: f32) {
+        self.physics.set_ground_plane(y);
+    }
+
+    /// removes the floor added by `set_ground_plane`, if any
+    pub fn clear_ground_plane(&mut self) {
+        self.physics.clear_ground_plane();
+    }
+
+    /// if enabled, `step` draws (and observes) each entity with physics between its poses at the last two physics ticks,
+    /// according to how much time is left over in the accumulator. this hides the stutter of rendering faster than
+    /// the physics runs, at the cost of showing entities one tick behind the simulation
+    pub fn set_interpolation_enabled(&mut self, enabled: bool) {
+        self.interpolation_enabled = enabled;
+    }
+
+    /// sets the length of a physics tick, in seconds
+    pub fn set_timestep(&mut self, dt: f32) {
+        self.physics.set_timestep(dt);
+    }
+
+    /// advances the world by `real_dt` seconds, running as many fixed-length physics ticks as fit
+    /// (the remainder is carried over to the next call), then renders observations.
+    /// this is deterministic: it doesn't use the world's rng
+    pub fn step(&mut self, real_dt: f32) -> HashMap<u32, Vec<CameraObservation>> {
+        self.step_timing.tick();
+        self.physics.clear_collision_events();
+        self.run_physics(real_dt);
+        self.observe()
+    }
+
+    /// like `step`, but overlaps physics with rendering: the offscreen renders of the current state are submitted first,
+    /// the physics ticks run while the GPU works, and the images are collected afterwards.
+    /// the observations therefore show the world as it was *before* this step's physics, one step behind `step`
+    pub fn step_pipelined(&mut self, real_dt: f32) -> HashMap<u32, Vec<CameraObservation>> {
+        self.step_timing.tick();
+        self.physics.clear_collision_events();
+        // the scenes are only updated while submitting, so the physics ticks don't touch buffers the GPU is reading
+        self.submit_observations();
+        self.run_physics(real_dt);
+        self.collect_observations()
+    }
+
+    // runs as many physics ticks as fit in the accumulated time
+    fn run_physics(&mut self, real_dt: f32) {
+        for _ in 0..self.physics.accumulate(real_dt) {
+            self.physics_tick();
+        }
+    }
+
+    /// advances the world by exactly one physics tick, then renders observations.
+    /// independent of wall clock time, so runs are reproducible
+    pub fn step_fixed(&mut self) -> HashMap<u32, Vec<CameraObservation>> {
+        self.step_timing.tick();
+        self.physics.clear_collision_events();
+        self.physics_tick();
+        self.observe()
+    }
+
+    fn physics_tick(&mut self) {
+        self.apply_controls();
+        self.physics.tick();
+    }
+
+    // pushes the entity that the camera is tracking according to the driving controls, once per physics tick
+    fn apply_controls(&mut self) {
+        #[cfg(feature = "gamepad")]
+        if let Some(ref mut gilrs) = self.gilrs {
+            self.user_input_state.poll_gamepad(gilrs);
+        }
+
+        let entity_id = match self.per_window_state {
+            Some(ref per_window_state) => per_window_state.entity_id,
+            None => return,
+        };
+        // the simulated pose, rather than the (possibly interpolated) one that was drawn
+        let isometry = match self.physics.isometry(entity_id) {
+            Some(isometry) => isometry,
+            None => return,
+        };
+        let active = |action| {
+            self.control_bindings
+                .is_active(action, &self.user_input_state)
+        };
+        let impulse = if self.user_input_state.gamepad_connected {
+            // analog controls take priority over the keyboard
+            let throttle = self.user_input_state.throttle_axis - self.user_input_state.brake_axis;
+            Vector3::new(throttle, 0.0, 0.0)
+        } else if active(ControlAction::Accelerate) {
+            Vector3::new(1.0, 0.0, 0.0)
+        } else if active(ControlAction::Brake) {
+            Vector3::new(-1.0, 0.0, 0.0)
+        } else {
+            Vector3::new(0.0, 0.0, 0.0)
+        };
+        let torque_impulse = if self.user_input_state.gamepad_connected {
+            Vector3::new(0.0, self.user_input_state.steering_axis, 0.0)
+        } else if active(ControlAction::SteerLeft) {
+            Vector3::new(0.0, -1.0, 0.0)
+        } else if active(ControlAction::SteerRight) {
+            Vector3::new(0.0, 1.0, 0.0)
+        } else {
+            Vector3::new(0.0, 0.0, 0.0)
+        };
+        let VehicleParams {
+            acceleration_impulse,
+            steering_torque,
+        } = self.vehicle_params;
+        self.physics
+            .apply_impulse(
+                entity_id,
+                (isometry.rotation * impulse) * acceleration_impulse,
+                true,
+            )
+            .unwrap();
+        self.physics
+            .apply_torque_impulse(entity_id, torque_impulse * steering_torque)
+            .unwrap();
+    }
+
+    // update scenes and cameras from the physics state, then render and collect observations
+    fn observe(&mut self) -> HashMap<u32, Vec<CameraObservation>> {
+        self.submit_observations();
+        self.collect_observations()
+    }
+
+    // update scenes and cameras from the physics state, and start rendering every camera without waiting for it
+    fn submit_observations(&mut self) {
+        self.intersections = self.physics.intersections();
 
         // update entity positions from physics and update mesh if necessary
         for (&entity_id, entity) in self.entities.iter_mut() {
-            let (scene, new_isometry) = match entity {
-                Entity {
-                    rigid_body_handle: Some(rigid_body_handle),
-                    ..
-                } => (
-                    &mut self.dynamic_scene,
-                    self.rigid_body_set[*rigid_body_handle].position(),
-                ),
-                Entity { ref isometry, .. } => (&mut self.static_scene, isometry),
+            let physics_isometry = match self.interpolation_enabled {
+                true => self.physics.interpolated_isometry(entity_id),
+                false => self.physics.isometry(entity_id),
+            };
+            let (scene, new_isometry) = match physics_isometry {
+                Some(isometry) => (&mut self.dynamic_scene, isometry),
+                None => (&mut self.static_scene, entity.isometry),
             };
 
-            if new_isometry != &entity.isometry {
-                entity.isometry = *new_isometry;
-                scene.add_object(entity_id, object::transform(&entity.mesh, &entity.isometry));
+            if new_isometry != entity.isometry {
+                entity.isometry = new_isometry;
+                add_mesh_to_scene(scene, entity_id, &entity.mesh, &entity.indices, &entity.isometry);
             }
         }
 
-        // update the entity that the camera is tracking
-        if let Some(ref mut per_window_state) = self.per_window_state {
-            if let Some(Entity {
-                rigid_body_handle: Some(handle),
-                isometry,
-                ..
-            }) = self.entities.get(&per_window_state.entity_id)
-            {
-                let impulse = if self.user_input_state.w {
-                    Vector3::new(1.0, 0.0, 0.0)
-                } else if self.user_input_state.s {
-                    Vector3::new(-1.0, 0.0, 0.0)
-                } else {
-                    Vector3::new(0.0, 0.0, 0.0)
-                };
-                let torque_impulse = if self.user_input_state.a {
-                    Vector3::new(0.0, -1.0, 0.0)
-                } else if self.user_input_state.d {
-                    Vector3::new(0.0, 1.0, 0.0)
-                } else {
-                    Vector3::new(0.0, 0.0, 0.0)
-                };
-                self.rigid_body_set[*handle]
-                    .apply_impulse((isometry.rotation * impulse) * 0.09, true);
-                self.rigid_body_set[*handle].apply_torque_impulse(torque_impulse * 0.01, true)
-            }
-        }
+        self.update_shadow_map();
+        let lighting_set = lighting_descriptor_set(
+            &self.per_device_state,
+            &self.point_lights,
+            &self.shadow_map,
+            self.light_view_proj,
+        );
 
         // update cameras and start offscreen rendering process for each of the entities that requires it
         for (_, entity) in self.entities.iter_mut() {
@@ -264,16 +1246,44 @@ impl GameWorld {
 
                 // start rendering
                 let extent = per_camera_data.renderer.extent();
-                let push_data = shader::vert::PushConstantData {
-                    mvp: per_camera_data.camera.mvp(extent).into(),
-                };
-                let vertex_buffers = [
-                    self.dynamic_scene.vertex_buffer(),
-                    self.static_scene.vertex_buffer(),
-                ]
-                .into_iter()
-                .flatten();
-                per_camera_data.renderer.render(vertex_buffers, push_data);
+                render_scenes(
+                    &mut per_camera_data.renderer,
+                    per_camera_data.camera.as_ref(),
+                    &mut self.dynamic_scene,
+                    &mut self.static_scene,
+                    &self.per_device_state,
+                    &self.light,
+                    lighting_set.clone(),
+                    self.skybox.as_ref(),
+                    &self.default_texture,
+                );
+
+                // render segmentation mask, one draw per entity so each can have its own color
+                if let Some(ref mut segmentation_renderer) = per_camera_data.segmentation_renderer {
+                    let mvp = per_camera_data.camera.mvp(extent);
+                    let objects = self
+                        .dynamic_scene
+                        .visible_object_draw_buffers(mvp)
+                        .into_iter()
+                        .chain(self.static_scene.visible_object_draw_buffers(mvp))
+                        // transparent objects are still solid in the mask
+                        .chain(transparent_draw_buffers(
+                            &mut self.dynamic_scene,
+                            &mut self.static_scene,
+                            per_camera_data.camera.as_ref(),
+                            &self.default_texture,
+                        ))
+                        .map(|(entity_id, draw_buffers)| {
+                            (
+                                draw_buffers,
+                                shader::seg_vert::PushConstantData {
+                                    mvp: mvp.into(),
+                                    color: segmentation_color(entity_id),
+                                },
+                            )
+                        });
+                    segmentation_renderer.render_objects(objects);
+                }
             }
         }
 
@@ -290,8 +1300,10 @@ impl GameWorld {
                 per_window_state.camera.update();
             }
         }
+    }
 
-        // get observations for each entity
+    // wait for the renders started by `submit_observations` and read back the images of each entity's cameras
+    fn collect_observations(&mut self) -> HashMap<u32, Vec<CameraObservation>> {
         self.entities
             .iter_mut()
             .map(|(&entity_id, entity)| {
@@ -300,7 +1312,23 @@ impl GameWorld {
                     entity
                         .cameras
                         .iter_mut()
-                        .map(|per_camera_data| per_camera_data.renderer.get_image())
+                        .enumerate()
+                        .map(|(camera_index, per_camera_data)| {
+                            let renderer = &mut per_camera_data.renderer;
+                            let channels = renderer
+                                .format()
+                                .components()
+                                .iter()
+                                .filter(|&&bits| bits > 0)
+                                .count() as u8;
+                            CameraObservation {
+                                entity_id,
+                                camera_index,
+                                extent: renderer.extent(),
+                                channels,
+                                pixels: renderer.get_image(),
+                            }
+                        })
                         .collect(),
                 )
             })
@@ -312,110 +1340,629 @@ impl GameWorld {
             cameras,
             physics,
             mesh,
+            indices,
             isometry,
+            name,
+            tags,
         } = entity_creation_data;
 
-        // add to physics solver if necessary
-        let (scene, rigid_body_handle) = match physics {
-            Some(EntityCreationPhysicsData { is_dynamic }) => {
-                // cuboid constructor uses "half-extents", which is just half of the cuboid's width, height, and depth
-                let hitbox = object::get_aabb(&mesh) / 2.0;
-                let rigid_body = match is_dynamic {
-                    true => RigidBodyBuilder::dynamic(),
-                    false => RigidBodyBuilder::fixed(),
-                }
-                .position(isometry)
-                .build();
-
-                let collider = ColliderBuilder::cuboid(hitbox.x, hitbox.y, hitbox.z).build();
-
-                let rigid_body_handle = self.rigid_body_set.insert(rigid_body);
-                self.collider_set.insert_with_parent(
-                    collider,
-                    rigid_body_handle,
-                    &mut self.rigid_body_set,
-                );
+        // a name can only refer to one entity, the newest one takes it over
+        if let Some(ref name) = name {
+            self.entity_names.insert(name.clone(), entity_id);
+        }
 
-                (&mut self.dynamic_scene, Some(rigid_body_handle))
+        // add to physics solver if necessary
+        let scene = match physics {
+            Some(ref physics) => {
+                self.physics.insert(entity_id, physics, &mesh, &indices, isometry);
+                &mut self.dynamic_scene
             }
-            None => (&mut self.static_scene, None),
+            None => &mut self.static_scene,
         };
 
         // add mesh to scene
-        scene.add_object(entity_id, object::transform(&mesh, &isometry));
+        add_mesh_to_scene(scene, entity_id, &mesh, &indices, &isometry);
 
         // create renderers
         let cameras = cameras
             .into_iter()
-            .map(|EntityCreationCameraData { camera, extent }| {
-                let renderer = offscreen_rendering::Renderer::new(
-                    extent,
-                    vec![
-                        self.per_device_state.vs.clone(),
-                        self.per_device_state.fs.clone(),
-                    ],
-                    self.per_device_state.queue.clone(),
-                    self.per_device_state.memory_allocator.clone(),
-                );
-                PerCameraData { camera, renderer }
-            })
+            .map(
+                |EntityCreationCameraData {
+                     camera,
+                     extent,
+                     segmentation,
+                 }| {
+                    let renderer = self.create_scene_renderer(extent);
+                    let segmentation_renderer = match segmentation {
+                        true => {
+                            let mut segmentation_renderer = offscreen_rendering::Renderer::new(
+                                extent,
+                                Format::R8G8B8A8_UNORM,
+                                1,
+                                vec![
+                                    self.per_device_state.seg_vs.clone(),
+                                    self.per_device_state.seg_fs.clone(),
+                                ],
+                                self.per_device_state.queue.clone(),
+                                self.per_device_state.transfer_queue.clone(),
+                                self.per_device_state.memory_allocator.clone(),
+                                None,
+                                Some(self.per_device_state.pipeline_cache.clone()),
+                            );
+                            segmentation_renderer.set_clear_color([0.0, 0.0, 0.0, 1.0]);
+                            Some(segmentation_renderer)
+                        }
+                        false => None,
+                    };
+                    PerCameraData {
+                        camera,
+                        renderer,
+                        segmentation_renderer,
+                    }
+                },
+            )
             .collect();
 
         self.entities.insert(
             entity_id,
             Entity {
                 cameras,
-                rigid_body_handle,
+                physics,
                 mesh,
+                indices,
                 isometry,
+                name,
+                tags,
             },
         );
     }
 
+    /// captures the pose, velocity and physics settings of every entity
+    pub fn save_state(&self) -> WorldState {
+        let mut entities: Vec<EntityState> = self
+            .entities
+            .iter()
+            .map(|(&id, entity)| {
+                let (isometry, linear_velocity, angular_velocity) = match self.physics.isometry(id) {
+                    Some(isometry) => (
+                        isometry,
+                        self.physics.linear_velocity(id).unwrap(),
+                        self.physics.angular_velocity(id).unwrap(),
+                    ),
+                    None => (entity.isometry, Vector3::zeros(), Vector3::zeros()),
+                };
+                EntityState {
+                    id,
+                    isometry,
+                    linear_velocity,
+                    angular_velocity,
+                    physics: entity.physics.clone(),
+                }
+            })
+            .collect();
+        entities.sort_by_key(|entity_state| entity_state.id);
+        WorldState { entities }
+    }
+
+    /// restores a snapshot taken by `save_state`, rebuilding every rigid body and scene object.
+    /// meshes aren't part of the snapshot, so every entity in it must still exist in the world.
+    /// entities that aren't in the snapshot are removed, as are joints connected to restored entities
+    pub fn load_state(&mut self, state: &WorldState) -> Result<(), GameWorldError> {
+        if let Some(missing) = state
+            .entities
+            .iter()
+            .find(|entity_state| !self.entities.contains_key(&entity_state.id))
+        {
+            return Err(GameWorldError::EntityNotFound(missing.id));
+        }
+
+        let stale: Vec<u32> = self
+            .entities
+            .keys()
+            .filter(|&&id| !state.entities.iter().any(|entity_state| entity_state.id == id))
+            .copied()
+            .collect();
+        for entity_id in stale {
+            self.remove_entity(entity_id);
+        }
+
+        for entity_state in &state.entities {
+            let entity_id = entity_state.id;
+            let mut entity = self.entities.remove(&entity_id).unwrap();
+            self.physics.remove(entity_id);
+            self.dynamic_scene.remove_object(entity_id);
+            self.static_scene.remove_object(entity_id);
+
+            entity.isometry = entity_state.isometry;
+            entity.physics = entity_state.physics.clone();
+            let scene = match entity.physics {
+                Some(ref physics) => {
+                    self.physics.insert(
+                        entity_id,
+                        physics,
+                        &entity.mesh,
+                        &entity.indices,
+                        entity.isometry,
+                    );
+                    self.physics
+                        .set_linear_velocity(entity_id, entity_state.linear_velocity)
+                        .unwrap();
+                    self.physics
+                        .set_angular_velocity(entity_id, entity_state.angular_velocity)
+                        .unwrap();
+                    &mut self.dynamic_scene
+                }
+                None => &mut self.static_scene,
+            };
+            add_mesh_to_scene(scene, entity_id, &entity.mesh, &entity.indices, &entity.isometry);
+            self.entities.insert(entity_id, entity);
+        }
+
+        // time and events from before the snapshot don't carry over
+        self.physics.reset_time();
+        self.intersections.clear();
+        Ok(())
+    }
+
+    /// get the segmentation masks rendered during the last `step`, in the same layout as the observations.
+    /// cameras without segmentation enabled have no mask
+    pub fn get_segmentation_images(&mut self) -> HashMap<u32, Vec<Option<Vec<u8>>>> {
+        self.entities
+            .iter_mut()
+            .map(|(&entity_id, entity)| {
+                (
+                    entity_id,
+                    entity
+                        .cameras
+                        .iter_mut()
+                        .map(|per_camera_data| {
+                            per_camera_data
+                                .segmentation_renderer
+                                .as_mut()
+                                .map(|renderer| renderer.get_image())
+                        })
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
     /// render to screen (if interactive rendering is enabled)
     /// Note that all offscreen rendering is done during `step`
     pub fn render(&mut self) {
+        self.render_timing.tick();
         if let Some(ref mut per_window_state) = self.per_window_state {
             let extent = interactive_rendering::get_surface_extent(&per_window_state.surface);
-            let push_data = shader::vert::PushConstantData {
-                mvp: per_window_state.camera.mvp(extent).into(),
-            };
-            let vertex_buffers = [
-                self.dynamic_scene.vertex_buffer(),
-                self.static_scene.vertex_buffer(),
-            ]
-            .into_iter()
-            .flatten();
-            per_window_state.renderer.render(vertex_buffers, push_data)
+            let mvp = per_window_state.camera.mvp(extent);
+            let push_data = push_constant_data(mvp, &self.light);
+            let vertex_buffers = opaque_draw_buffers(
+                &mut self.dynamic_scene,
+                &mut self.static_scene,
+                &self.default_texture,
+                Some(mvp),
+            );
+            let transparent_buffers = transparent_draw_buffers(
+                &mut self.dynamic_scene,
+                &mut self.static_scene,
+                per_window_state.camera.as_ref(),
+                &self.default_texture,
+            );
+            // the shadow map is rendered during `step`
+            let lighting_set = lighting_descriptor_set(
+                &self.per_device_state,
+                &self.point_lights,
+                &self.shadow_map,
+                self.light_view_proj,
+            );
+            per_window_state
+                .renderer
+                .set_descriptor_sets(vec![lighting_set]);
+            if let Some(ref skybox) = self.skybox {
+                per_window_state
+                    .renderer
+                    .set_background_descriptor_sets(vec![sky_descriptor_set(
+                        &self.per_device_state,
+                        skybox,
+                        per_window_state.camera.as_ref(),
+                        extent,
+                    )]);
+            }
+            per_window_state.renderer.render_with_transparent(
+                vertex_buffers,
+                transparent_buffers.into_iter().map(|(_, draw_buffers)| draw_buffers),
+                push_data,
+            )
+        }
+    }
+
+    /// renders the world as it is now from `camera`, which doesn't have to be attached to an entity,
+    /// e.g. for a screenshot or thumbnail. blocks until the image has been read back.
+    /// the returned observation has `NO_ENTITY` as its entity id.
+    /// renderers are kept around per extent, so capturing at the same size again is cheap
+    pub fn render_from<C: Camera + ?Sized>(&mut self, camera: &C, extent: [u32; 2]) -> CameraObservation {
+        if !self.capture_renderers.contains_key(&extent) {
+            let renderer = self.create_scene_renderer(extent);
+            self.capture_renderers.insert(extent, renderer);
+        }
+        let lighting_set = lighting_descriptor_set(
+            &self.per_device_state,
+            &self.point_lights,
+            &self.shadow_map,
+            self.light_view_proj,
+        );
+        let renderer = self.capture_renderers.get_mut(&extent).unwrap();
+        render_scenes(
+            renderer,
+            camera,
+            &mut self.dynamic_scene,
+            &mut self.static_scene,
+            &self.per_device_state,
+            &self.light,
+            lighting_set,
+            self.skybox.as_ref(),
+            &self.default_texture,
+        );
+        CameraObservation {
+            entity_id: NO_ENTITY,
+            camera_index: 0,
+            extent,
+            channels: 4,
+            pixels: renderer.get_image(),
         }
     }
 
+    /// the contents of the pipeline cache shared by all of the world's renderers, e.g. to write to disk.
+    /// only useful to the same driver on the same device
+    pub fn pipeline_cache_data(&self) -> Vec<u8> {
+        self.per_device_state
+            .pipeline_cache
+            .get_data()
+            .expect("failed to read pipeline cache")
+    }
+
+    // the timing of `render` once it has been called, otherwise that of the step functions
+    fn frame_timing(&self) -> &FrameTiming {
+        match self.render_timing.last_frame {
+            Some(_) => &self.render_timing,
+            None => &self.step_timing,
+        }
+    }
+
+    /// time between the last two calls to `render`.
+    /// a world that is never rendered (e.g. headless) reports the time between the last two steps instead
+    pub fn last_frame_time(&self) -> Duration {
+        self.frame_timing().last_frame_time
+    }
+
+    /// frames rendered per second, smoothed over the last several frames.
+    /// like `last_frame_time`, this counts steps instead if the world is never rendered
+    pub fn fps(&self) -> f32 {
+        self.frame_timing().fps
+    }
+
+    /// change how hard the driving controls push the tracked entity
+    pub fn set_vehicle_params(&mut self, vehicle_params: VehicleParams) {
+        self.vehicle_params = vehicle_params;
+    }
+
+    /// change which keys drive the tracked entity
+    pub fn set_bindings(&mut self, control_bindings: ControlBindings) {
+        self.control_bindings = control_bindings;
+    }
+
+    /// the pairs of entities that started or stopped touching during the last `step`
+    pub fn collision_events(&self) -> &[(u32, u32, ContactKind)] {
+        self.physics.collision_events()
+    }
+
+    /// the pairs of entities where one is a sensor that the other overlaps, as of the last `step`
+    pub fn intersections(&self) -> &[(u32, u32)] {
+        &self.intersections
+    }
+
+    /// the ids of every entity in the world, in no particular order
+    pub fn entity_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.entities.keys().copied()
+    }
+
+    /// the id of the entity with the given name, see `EntityCreationData::name`
+    pub fn entity_by_name(&self, name: &str) -> Option<u32> {
+        self.entity_names.get(name).copied()
+    }
+
+    /// the ids of every entity that has the given tag, in no particular order
+    pub fn entities_with_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = u32> + 'a {
+        self.entities
+            .iter()
+            .filter(move |(_, entity)| entity.tags.iter().any(|t| t == tag))
+            .map(|(&entity_id, _)| entity_id)
+    }
+
+    /// where the entity currently is. for entities with physics this is the rigid body's latest position,
+    /// which may be ahead of what was last rendered
+    pub fn entity_isometry(&self, entity_id: u32) -> Option<Isometry3<f32>> {
+        let entity = self.entities.get(&entity_id)?;
+        Some(self.physics.isometry(entity_id).unwrap_or(entity.isometry))
+    }
+
+    /// teleports the entity to `isometry`. entities with physics also lose all their velocity
+    pub fn set_entity_isometry(
+        &mut self,
+        entity_id: u32,
+        isometry: Isometry3<f32>,
+    ) -> Result<(), GameWorldError> {
+        let entity = self
+            .entities
+            .get_mut(&entity_id)
+            .ok_or(GameWorldError::EntityNotFound(entity_id))?;
+        let scene = match self.physics.set_isometry(entity_id, isometry) {
+            Ok(()) => &mut self.dynamic_scene,
+            Err(_) => &mut self.static_scene,
+        };
+        entity.isometry = isometry;
+        add_mesh_to_scene(scene, entity_id, &entity.mesh, &entity.indices, &entity.isometry);
+        Ok(())
+    }
+
+    /// uploads an image so that it can be put on entities with `set_entity_texture`.
+    /// the same texture can be shared by any number of entities
+    pub fn create_texture(&self, image: &RgbaImage) -> Texture {
+        create_texture(&self.per_device_state, image)
+    }
+
+    /// draws the entity with `texture` (sampled at its vertices' uv coordinates and multiplied by their colors),
+    /// or with just its vertex colors if None. textured entities take a draw call each
+    pub fn set_entity_texture(
+        &mut self,
+        entity_id: u32,
+        texture: Option<Texture>,
+    ) -> Result<(), GameWorldError> {
+        if !self.entities.contains_key(&entity_id) {
+            return Err(GameWorldError::EntityNotFound(entity_id));
+        }
+        let texture = texture.map(|texture| texture.0);
+        self.dynamic_scene.set_texture(&entity_id, texture.clone());
+        self.static_scene.set_texture(&entity_id, texture);
+        Ok(())
+    }
+
+    /// adds a mesh that is drawn once per instance, uploading the mesh only once (e.g. for many identical cones).
+    /// instanced meshes are visual only, and `key` is separate from entity ids.
+    /// they don't show up in segmentation masks
+    pub fn add_instanced_mesh(&mut self, key: u32, mesh: Vec<mVertex>, instances: Vec<InstanceData>) {
+        self.static_scene.add_instanced(key, mesh, instances);
+    }
+
+    /// moves, adds or removes instances of an instanced mesh
+    pub fn set_mesh_instances(&mut self, key: u32, instances: Vec<InstanceData>) {
+        self.static_scene.set_instances(&key, instances);
+    }
+
+    pub fn remove_instanced_mesh(&mut self, key: u32) {
+        self.static_scene.remove_instanced(&key);
+    }
+
+    /// draws the entity with alpha blending (using the alpha of its vertex colors), after all the opaque entities
+    pub fn set_entity_transparent(
+        &mut self,
+        entity_id: u32,
+        transparent: bool,
+    ) -> Result<(), GameWorldError> {
+        if !self.entities.contains_key(&entity_id) {
+            return Err(GameWorldError::EntityNotFound(entity_id));
+        }
+        self.dynamic_scene.set_transparent(&entity_id, transparent);
+        self.static_scene.set_transparent(&entity_id, transparent);
+        Ok(())
+    }
+
+    fn check_entity_exists(&self, entity_id: u32) -> Result<(), GameWorldError> {
+        match self.entities.contains_key(&entity_id) {
+            true => Ok(()),
+            false => Err(GameWorldError::EntityNotFound(entity_id)),
+        }
+    }
+
+    pub fn linear_velocity(&self, entity_id: u32) -> Result<Vector3<f32>, GameWorldError> {
+        self.check_entity_exists(entity_id)?;
+        self.physics.linear_velocity(entity_id)
+    }
+
+    pub fn angular_velocity(&self, entity_id: u32) -> Result<Vector3<f32>, GameWorldError> {
+        self.check_entity_exists(entity_id)?;
+        self.physics.angular_velocity(entity_id)
+    }
+
+    /// sets the linear velocity of the entity, waking it up if it was sleeping
+    pub fn set_linear_velocity(
+        &mut self,
+        entity_id: u32,
+        v: Vector3<f32>,
+    ) -> Result<(), GameWorldError> {
+        self.check_entity_exists(entity_id)?;
+        self.physics.set_linear_velocity(entity_id, v)
+    }
+
+    /// sets the angular velocity of the entity, waking it up if it was sleeping
+    pub fn set_angular_velocity(
+        &mut self,
+        entity_id: u32,
+        v: Vector3<f32>,
+    ) -> Result<(), GameWorldError> {
+        self.check_entity_exists(entity_id)?;
+        self.physics.set_angular_velocity(entity_id, v)
+    }
+
+    /// applies an impulse at the entity's center of mass, instantly changing its momentum
+    pub fn apply_impulse(
+        &mut self,
+        entity_id: u32,
+        impulse: Vector3<f32>,
+        wake: bool,
+    ) -> Result<(), GameWorldError> {
+        self.check_entity_exists(entity_id)?;
+        self.physics.apply_impulse(entity_id, impulse, wake)
+    }
+
+    /// adds a force at the entity's center of mass.
+    /// forces keep being applied every step until `reset_forces` is called
+    pub fn apply_force(
+        &mut self,
+        entity_id: u32,
+        force: Vector3<f32>,
+    ) -> Result<(), GameWorldError> {
+        self.check_entity_exists(entity_id)?;
+        self.physics.apply_force(entity_id, force)
+    }
+
+    /// removes all forces added with `apply_force`
+    pub fn reset_forces(&mut self, entity_id: u32) -> Result<(), GameWorldError> {
+        self.check_entity_exists(entity_id)?;
+        self.physics.reset_forces(entity_id)
+    }
+
+    /// applies an angular impulse, instantly changing the entity's angular momentum
+    pub fn apply_torque_impulse(
+        &mut self,
+        entity_id: u32,
+        torque: Vector3<f32>,
+    ) -> Result<(), GameWorldError> {
+        self.check_entity_exists(entity_id)?;
+        self.physics.apply_torque_impulse(entity_id, torque)
+    }
+
+    /// casts a ray from `origin` along `dir`, and returns the first entity hit along with the time of impact.
+    /// the hit point is at `origin + dir * toi`. only entities with physics can be hit
+    pub fn cast_ray(
+        &self,
+        origin: Point3<f32>,
+        dir: Vector3<f32>,
+        max_toi: f32,
+    ) -> Option<(u32, f32)> {
+        self.physics.cast_ray(origin, dir, max_toi)
+    }
+
+    /// connects two entities with a hinge that rotates around `axis`.
+    /// the anchors are in each entity's local coordinates
+    pub fn add_revolute_joint(
+        &mut self,
+        a: u32,
+        b: u32,
+        anchor_a: Point3<f32>,
+        anchor_b: Point3<f32>,
+        axis: Vector3<f32>,
+    ) -> Result<ImpulseJointHandle, GameWorldError> {
+        self.check_entity_exists(a)?;
+        self.check_entity_exists(b)?;
+        self.physics
+            .add_revolute_joint(a, b, anchor_a, anchor_b, axis)
+    }
+
+    /// rigidly attaches two entities to each other.
+    /// the anchors are in each entity's local coordinates
+    pub fn add_fixed_joint(
+        &mut self,
+        a: u32,
+        b: u32,
+        anchor_a: Point3<f32>,
+        anchor_b: Point3<f32>,
+    ) -> Result<ImpulseJointHandle, GameWorldError> {
+        self.check_entity_exists(a)?;
+        self.check_entity_exists(b)?;
+        self.physics.add_fixed_joint(a, b, anchor_a, anchor_b)
+    }
+
+    pub fn remove_joint(&mut self, joint_handle: ImpulseJointHandle) {
+        self.physics.remove_joint(joint_handle);
+    }
+
     pub fn remove_entity(&mut self, entity_id: u32) {
         let entity = self.entities.remove(&entity_id);
-        match entity {
-            Some(Entity {
-                rigid_body_handle: Some(rigid_body_handle),
-                ..
-            }) => {
-                self.rigid_body_set.remove(
-                    rigid_body_handle,
-                    &mut self.island_manager,
-                    &mut self.collider_set,
-                    &mut self.impulse_joint_set,
-                    &mut self.multibody_joint_set,
-                    true,
-                );
+        if let Some(Entity {
+            name: Some(ref name),
+            ..
+        }) = entity
+        {
+            // unless the name has been taken over by another entity since
+            if self.entity_names.get(name) == Some(&entity_id) {
+                self.entity_names.remove(name);
             }
-            _ => (),
         }
+        self.physics.remove(entity_id);
         self.dynamic_scene.remove_object(entity_id);
         self.static_scene.remove_object(entity_id);
     }
 
+    /// removes every entity, along with their rigid bodies, colliders and joints, e.g. to reset between episodes.
+    /// the ground plane, lights and instanced meshes are kept
+    pub fn clear_entities(&mut self) {
+        let entity_ids: Vec<u32> = self.entity_ids().collect();
+        for entity_id in entity_ids {
+            self.remove_entity(entity_id);
+        }
+    }
+
+    /// removes every entity that the physics engine moves, keeping static ones such as terrain and roads
+    pub fn clear_dynamic_entities(&mut self) {
+        let entity_ids: Vec<u32> = self
+            .entities
+            .iter()
+            .filter(|(_, entity)| entity.physics.as_ref().map_or(false, |physics| physics.is_dynamic))
+            .map(|(&entity_id, _)| entity_id)
+            .collect();
+        for entity_id in entity_ids {
+            self.remove_entity(entity_id);
+        }
+    }
+
+    /// forwards the input events of an event loop to the world. the application still decides when to advance and draw:
+    /// e.g. call `step` and then `render` on `Event::RedrawEventsCleared`, and exit on `WindowEvent::CloseRequested`.
+    /// events other than window and device events are ignored
+    pub fn handle_event(&mut self, event: &winit::event::Event<()>) {
+        match event {
+            winit::event::Event::WindowEvent { event, .. } => self.handle_window_event(event),
+            winit::event::Event::DeviceEvent { event, .. } => self.handle_device_event(event),
+            _ => {}
+        }
+    }
+
     pub fn handle_window_event(&mut self, input: &winit::event::WindowEvent) {
+        // only toggle on the initial press, not on key repeats
+        let toggle_was_active = self
+            .control_bindings
+            .is_active(ControlAction::ToggleMouseCapture, &self.user_input_state);
         self.user_input_state.handle_input(input);
+        let toggle_is_active = self
+            .control_bindings
+            .is_active(ControlAction::ToggleMouseCapture, &self.user_input_state);
+
         match self.per_window_state {
             Some(ref mut per_window_state) => {
+                let mouse_captured = match input {
+                    // losing focus always gives the cursor back
+                    winit::event::WindowEvent::Focused(false) => false,
+                    _ if toggle_is_active && !toggle_was_active => {
+                        !per_window_state.mouse_captured
+                    }
+                    _ => per_window_state.mouse_captured,
+                };
+                if mouse_captured != per_window_state.mouse_captured {
+                    per_window_state.mouse_captured = mouse_captured;
+                    interactive_rendering::set_cursor_captured(
+                        &per_window_state.surface,
+                        mouse_captured,
+                    );
+                    per_window_state.camera.set_mouse_captured(mouse_captured);
+                }
+
+                // rebuild before the next frame rather than drawing one stretched frame at the old size.
+                // a minimized window reports a zero size, and nothing is drawn until it's restored anyway
+                if let winit::event::WindowEvent::Resized(size) = input {
+                    if size.width > 0 && size.height > 0 {
+                        per_window_state.renderer.mark_needs_rebuild();
+                    }
+                }
+
                 per_window_state.camera.handle_event(
                     interactive_rendering::get_surface_extent(&per_window_state.surface),
                     input,
@@ -424,4 +1971,242 @@ impl GameWorld {
             None => (),
         }
     }
+
+    pub fn handle_device_event(&mut self, event: &winit::event::DeviceEvent) {
+        if let Some(ref mut per_window_state) = self.per_window_state {
+            per_window_state.camera.handle_device_event(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use crate::render_system::testing;
+
+    // a world without a window, or None if there's no gpu to render with
+    fn headless_world() -> Option<GameWorld> {
+        seeded_headless_world(0)
+    }
+
+    fn seeded_headless_world(seed: u64) -> Option<GameWorld> {
+        let (queue, transfer_queue, memory_allocator) = testing::headless_device()?;
+        Some(GameWorld::new(queue, transfer_queue, memory_allocator, None, seed))
+    }
+
+    fn cube(physics: Option<EntityCreationPhysicsData>, position: Vector3<f32>) -> EntityCreationData {
+        EntityCreationData {
+            cameras: vec![],
+            physics,
+            mesh: object::unitcube(),
+            indices: None,
+            isometry: Isometry3::translation(position.x, position.y, position.z),
+            name: None,
+            tags: vec![],
+        }
+    }
+
+    fn dynamic() -> Option<EntityCreationPhysicsData> {
+        Some(EntityCreationPhysicsData {
+            is_dynamic: true,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn clear_entities_empties_both_scenes() {
+        let mut world = match headless_world() {
+            Some(world) => world,
+            None => return,
+        };
+        world.add_entity(0, cube(dynamic(), Vector3::new(0.0, 10.0, 0.0)));
+        world.add_entity(1, cube(None, Vector3::zeros()));
+        world.add_entity(2, cube(Some(Default::default()), Vector3::new(3.0, 0.0, 0.0)));
+
+        world.clear_dynamic_entities();
+        let mut remaining: Vec<u32> = world.entity_ids().collect();
+        remaining.sort();
+        assert_eq!(remaining, vec![1, 2]);
+
+        world.clear_entities();
+        assert_eq!(world.entity_ids().count(), 0);
+        assert!(world.dynamic_scene.draw_buffers().is_none());
+        assert!(world.static_scene.draw_buffers().is_none());
+        assert_eq!(world.cast_ray(Point3::new(3.0, 5.0, 0.0), -Vector3::y(), 10.0), None);
+    }
+
+    #[test]
+    fn load_state_restores_saved_state() {
+        let mut world = match headless_world() {
+            Some(world) => world,
+            None => return,
+        };
+        world.add_entity(0, cube(dynamic(), Vector3::new(0.0, 10.0, 0.0)));
+        world.add_entity(1, cube(None, Vector3::new(5.0, 0.0, 0.0)));
+        for _ in 0..10 {
+            world.step_fixed();
+        }
+        let saved = world.save_state();
+        for _ in 0..20 {
+            world.step_fixed();
+        }
+        let expected = world.entity_isometry(0).unwrap();
+
+        // an entity added after the snapshot is removed again
+        world.add_entity(2, cube(dynamic(), Vector3::zeros()));
+        world.load_state(&saved).unwrap();
+        assert_eq!(world.entity_ids().count(), 2);
+        assert_eq!(world.entity_isometry(0), Some(saved.entities[0].isometry));
+        assert_eq!(world.linear_velocity(0).unwrap(), saved.entities[0].linear_velocity);
+
+        // still falling freely, so it takes the same path again
+        for _ in 0..20 {
+            world.step_fixed();
+        }
+        let replayed = world.entity_isometry(0).unwrap();
+        assert!((replayed.translation.vector - expected.translation.vector).norm() < 1e-4);
+    }
+
+    #[test]
+    fn render_from_depends_on_the_camera() {
+        let mut world = match headless_world() {
+            Some(world) => world,
+            None => return,
+        };
+        world.add_entity(0, cube(None, Vector3::zeros()));
+
+        // the camera starts out facing +x, straight at the cube
+        let mut camera = camera::FlyCamera::new(Point3::new(-3.0, 0.0, 0.0));
+        let facing = world.render_from(&camera, [32, 32]);
+        assert_eq!(facing.entity_id, NO_ENTITY);
+        assert_eq!(facing.pixels.len(), 32 * 32 * 4);
+
+        // then turns away from it, to look at nothing but the background
+        camera.set_rotation_speed(std::f32::consts::FRAC_PI_2);
+        camera.dir_rotate(camera::CameraRotationDir::Left);
+        let away = world.render_from(&camera, [32, 32]);
+        assert_eq!(away.pixels.len(), facing.pixels.len());
+        assert_ne!(away.pixels, facing.pixels);
+    }
+
+    #[test]
+    fn teleported_cube_is_drawn_at_its_new_place() {
+        let mut world = match headless_world() {
+            Some(world) => world,
+            None => return,
+        };
+        // starts out far above the camera's view, moving
+        world.add_entity(0, cube(dynamic(), Vector3::new(0.0, 20.0, 0.0)));
+        world.set_linear_velocity(0, Vector3::new(1.0, 2.0, 3.0)).unwrap();
+        world.set_angular_velocity(0, Vector3::new(0.5, 0.0, 0.0)).unwrap();
+
+        let camera = camera::FlyCamera::new(Point3::new(-3.0, 0.0, 0.0));
+        let center = |observation: &CameraObservation| {
+            let i = ((16 * 32 + 16) * 4) as usize;
+            observation.pixels[i..i + 4].to_vec()
+        };
+        let background = center(&world.render_from(&camera, [32, 32]));
+
+        let target = Isometry3::translation(0.0, 0.0, 0.0);
+        world.set_entity_isometry(0, target).unwrap();
+        assert_eq!(world.entity_isometry(0), Some(target));
+        assert_eq!(world.linear_velocity(0).unwrap(), Vector3::zeros());
+        assert_eq!(world.angular_velocity(0).unwrap(), Vector3::zeros());
+
+        // now right in front of the camera
+        assert_ne!(center(&world.render_from(&camera, [32, 32])), background);
+    }
+
+    #[test]
+    fn orthographic_camera_keeps_size_with_distance() {
+        let mut world = match headless_world() {
+            Some(world) => world,
+            None => return,
+        };
+        // both cameras look straight down from one unit above the origin
+        let orthographic = camera::BEVCamera::top_down(Point3::origin(), 4.0, 0.0, 20.0);
+        let mut perspective = camera::FlyCamera::new(Point3::new(0.0, 1.0, 0.0));
+        perspective.set_rotation_speed(89f32.to_radians());
+        perspective.dir_rotate(camera::CameraRotationDir::Down);
+        let camera_data = |camera: Box<dyn Camera>| EntityCreationCameraData {
+            camera,
+            extent: [32, 32],
+            segmentation: false,
+        };
+        world.add_entity(
+            0,
+            EntityCreationData {
+                cameras: vec![camera_data(Box::new(orthographic)), camera_data(Box::new(perspective))],
+                mesh: vec![],
+                ..cube(None, Vector3::zeros())
+            },
+        );
+        world.add_entity(1, cube(None, Vector3::new(0.0, -3.0, 0.0)));
+
+        // how many pixels each camera sees the cube in
+        let coverage = |world: &mut GameWorld| {
+            let observations = world.step_fixed().remove(&0).unwrap();
+            observations
+                .iter()
+                .map(|observation| {
+                    let pixels = observation.pixels.chunks_exact(4).collect::<Vec<_>>();
+                    pixels.iter().filter(|&&pixel| pixel != pixels[0]).count()
+                })
+                .collect::<Vec<_>>()
+        };
+        let near = coverage(&mut world);
+        world
+            .set_entity_isometry(1, Isometry3::translation(0.0, -9.0, 0.0))
+            .unwrap();
+        let far = coverage(&mut world);
+
+        // the cube is 1 unit wide in a view 4 units wide, so 8 by 8 pixels
+        assert_eq!(near[0], 8 * 8);
+        assert_eq!(far[0], near[0]);
+        // while in perspective it shrinks as it moves away
+        assert!(near[1] > 0);
+        assert!(far[1] < near[1], "perspective coverage went from {} to {}", near[1], far[1]);
+    }
+
+    // drops a pile of cubes at positions drawn from the world's rng, and returns where they all end up
+    fn settle_random_pile(seed: u64) -> Option<Vec<Isometry3<f32>>> {
+        let mut world = seeded_headless_world(seed)?;
+        world.set_ground_plane(0.0);
+        for entity_id in 0..10 {
+            let position = Vector3::new(
+                world.rng().gen_range(-1.0..1.0),
+                1.0 + entity_id as f32 * 1.5,
+                world.rng().gen_range(-1.0..1.0),
+            );
+            world.add_entity(entity_id, cube(dynamic(), position));
+        }
+        for _ in 0..120 {
+            world.step_fixed();
+        }
+        Some((0..10).map(|entity_id| world.entity_isometry(entity_id).unwrap()).collect())
+    }
+
+    #[test]
+    fn same_seed_gives_same_run() {
+        let first = match settle_random_pile(3) {
+            Some(run) => run,
+            None => return,
+        };
+        assert_eq!(settle_random_pile(3), Some(first.clone()));
+        assert_ne!(settle_random_pile(4), Some(first));
+    }
+
+    #[test]
+    fn headless_worlds_time_their_steps() {
+        let mut world = match headless_world() {
+            Some(world) => world,
+            None => return,
+        };
+        world.step_fixed();
+        std::thread::sleep(Duration::from_millis(10));
+        world.step(1.0 / 60.0);
+        assert!(world.last_frame_time() >= Duration::from_millis(10));
+        assert!(world.fps() > 0.0 && world.fps() <= 100.0);
+    }
 }
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use nalgebra::Point2;
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
 
@@ -19,6 +21,17 @@ pub struct UserInputState {
     pub left: bool,
     pub down: bool,
     pub right: bool,
+    // every key currently held down, including the ones above
+    pub pressed: HashSet<VirtualKeyCode>,
+
+    // gamepad state, only updated when the "gamepad" feature is enabled
+    pub gamepad_connected: bool,
+    // left stick x, from -1 (left) to 1 (right)
+    pub steering_axis: f32,
+    // right trigger, from 0 to 1
+    pub throttle_axis: f32,
+    // left trigger, from 0 to 1
+    pub brake_axis: f32,
 }
 
 impl UserInputState {
@@ -37,8 +50,45 @@ impl UserInputState {
             left: false,
             right: false,
             down: false,
+            pressed: HashSet::new(),
+            gamepad_connected: false,
+            steering_axis: 0.0,
+            throttle_axis: 0.0,
+            brake_axis: 0.0,
         }
     }
+
+    pub fn is_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    // read the axes of the first connected gamepad
+    #[cfg(feature = "gamepad")]
+    pub fn poll_gamepad(&mut self, gilrs: &mut gilrs::Gilrs) {
+        // gilrs only updates its gamepad state while processing events
+        while gilrs.next_event().is_some() {}
+
+        match gilrs.gamepads().next() {
+            Some((_, gamepad)) => {
+                let trigger = |button| {
+                    gamepad
+                        .button_data(button)
+                        .map_or(0.0, |data| data.value())
+                };
+                self.gamepad_connected = true;
+                self.steering_axis = gamepad.value(gilrs::Axis::LeftStickX);
+                self.throttle_axis = trigger(gilrs::Button::RightTrigger2);
+                self.brake_axis = trigger(gilrs::Button::LeftTrigger2);
+            }
+            None => {
+                self.gamepad_connected = false;
+                self.steering_axis = 0.0;
+                self.throttle_axis = 0.0;
+                self.brake_axis = 0.0;
+            }
+        }
+    }
+
     pub fn handle_input(&mut self, input: &winit::event::WindowEvent) {
         match input {
             winit::event::WindowEvent::CursorMoved { position, .. } => {
@@ -46,7 +96,7 @@ impl UserInputState {
                 self.pos = Point2::new(position.x as f32, position.y as f32);
             }
             winit::event::WindowEvent::MouseInput { state, .. } => {
-                self.down = *state == ElementState::Pressed;
+                self.mouse_down = *state == ElementState::Pressed;
             }
             winit::event::WindowEvent::KeyboardInput {
                 input:
@@ -56,20 +106,131 @@ impl UserInputState {
                         ..
                     },
                 ..
-            } => match kc {
-                VirtualKeyCode::W => self.w = state == &ElementState::Pressed,
-                VirtualKeyCode::A => self.a = state == &ElementState::Pressed,
-                VirtualKeyCode::S => self.s = state == &ElementState::Pressed,
-                VirtualKeyCode::D => self.d = state == &ElementState::Pressed,
-                VirtualKeyCode::Q => self.q = state == &ElementState::Pressed,
-                VirtualKeyCode::E => self.e = state == &ElementState::Pressed,
-                VirtualKeyCode::Up => self.up = state == &ElementState::Pressed,
-                VirtualKeyCode::Left => self.left = state == &ElementState::Pressed,
-                VirtualKeyCode::Down => self.down = state == &ElementState::Pressed,
-                VirtualKeyCode::Right => self.right = state == &ElementState::Pressed,
-                _ => (),
-            },
+            } => {
+                match state {
+                    ElementState::Pressed => self.pressed.insert(*kc),
+                    ElementState::Released => self.pressed.remove(kc),
+                };
+                match kc {
+                    VirtualKeyCode::W => self.w = state == &ElementState::Pressed,
+                    VirtualKeyCode::A => self.a = state == &ElementState::Pressed,
+                    VirtualKeyCode::S => self.s = state == &ElementState::Pressed,
+                    VirtualKeyCode::D => self.d = state == &ElementState::Pressed,
+                    VirtualKeyCode::Q => self.q = state == &ElementState::Pressed,
+                    VirtualKeyCode::E => self.e = state == &ElementState::Pressed,
+                    VirtualKeyCode::Up => self.up = state == &ElementState::Pressed,
+                    VirtualKeyCode::Left => self.left = state == &ElementState::Pressed,
+                    VirtualKeyCode::Down => self.down = state == &ElementState::Pressed,
+                    VirtualKeyCode::Right => self.right = state == &ElementState::Pressed,
+                    _ => (),
+                }
+            }
             _ => (),
         }
     }
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ControlAction {
+    Accelerate,
+    Brake,
+    SteerLeft,
+    SteerRight,
+    // grab or release the cursor for mouse look
+    ToggleMouseCapture,
+}
+
+// which key triggers each driving action
+#[derive(Clone, Debug)]
+pub struct ControlBindings {
+    pub accelerate: VirtualKeyCode,
+    pub brake: VirtualKeyCode,
+    pub steer_left: VirtualKeyCode,
+    pub steer_right: VirtualKeyCode,
+    pub toggle_mouse_capture: VirtualKeyCode,
+}
+
+impl ControlBindings {
+    pub fn key(&self, action: ControlAction) -> VirtualKeyCode {
+        match action {
+            ControlAction::Accelerate => self.accelerate,
+            ControlAction::Brake => self.brake,
+            ControlAction::SteerLeft => self.steer_left,
+            ControlAction::SteerRight => self.steer_right,
+            ControlAction::ToggleMouseCapture => self.toggle_mouse_capture,
+        }
+    }
+
+    // whether the key bound to the action is held down
+    pub fn is_active(&self, action: ControlAction, input: &UserInputState) -> bool {
+        input.is_pressed(self.key(action))
+    }
+}
+
+impl Default for ControlBindings {
+    fn default() -> Self {
+        ControlBindings {
+            accelerate: VirtualKeyCode::W,
+            brake: VirtualKeyCode::S,
+            steer_left: VirtualKeyCode::A,
+            steer_right: VirtualKeyCode::D,
+            toggle_mouse_capture: VirtualKeyCode::Tab,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::event::{DeviceId, ModifiersState, MouseButton, WindowEvent};
+
+    #[allow(deprecated)]
+    fn key_event(kc: VirtualKeyCode, state: ElementState) -> WindowEvent<'static> {
+        WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(kc),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: true,
+        }
+    }
+
+    #[allow(deprecated)]
+    fn mouse_event(state: ElementState) -> WindowEvent<'static> {
+        WindowEvent::MouseInput {
+            device_id: unsafe { DeviceId::dummy() },
+            state,
+            button: MouseButton::Left,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    #[test]
+    fn keys_are_pressed_until_released() {
+        let mut input = UserInputState::new();
+        let bindings = ControlBindings::default();
+
+        input.handle_input(&key_event(VirtualKeyCode::W, ElementState::Pressed));
+        input.handle_input(&key_event(VirtualKeyCode::Space, ElementState::Pressed));
+        assert!(input.w);
+        assert!(input.is_pressed(VirtualKeyCode::Space));
+        assert!(bindings.is_active(ControlAction::Accelerate, &input));
+
+        input.handle_input(&key_event(VirtualKeyCode::W, ElementState::Released));
+        assert!(!input.w);
+        assert!(!bindings.is_active(ControlAction::Accelerate, &input));
+        assert!(input.is_pressed(VirtualKeyCode::Space));
+    }
+
+    #[test]
+    fn mouse_button_toggles_mouse_down() {
+        let mut input = UserInputState::new();
+        input.handle_input(&mouse_event(ElementState::Pressed));
+        assert!(input.mouse_down);
+        input.handle_input(&mouse_event(ElementState::Released));
+        assert!(!input.mouse_down);
+    }
+}
@@ -0,0 +1,65 @@
+use nalgebra::{Matrix4, Point3, Vector3};
+
+use crate::camera::gen_orthographic_projection;
+
+/// a light infinitely far away (like the sun) that shines on every surface from the same direction
+#[derive(Clone, Copy, Debug)]
+pub struct DirectionalLight {
+    // direction the light travels in, in world space
+    pub direction: Vector3<f32>,
+    // color of the light, multiplied with the color of the surfaces it lights
+    pub color: [f32; 3],
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vector3<f32>, color: [f32; 3]) -> DirectionalLight {
+        DirectionalLight { direction, color }
+    }
+
+    /// transformation from world space to the clip space of an orthographic view looking along the light,
+    /// covering everything within `half_extent` of `center` (used to render shadow maps)
+    pub fn view_projection(&self, center: Point3<f32>, half_extent: f32) -> Matrix4<f32> {
+        let direction = self.direction.normalize();
+        // any up vector works, as long as it isn't parallel to the light
+        let up = if direction.z.abs() > 0.99 {
+            Vector3::x()
+        } else {
+            Vector3::z()
+        };
+        let eye = center - direction * 2.0 * half_extent;
+        gen_orthographic_projection(half_extent, half_extent, 0.0, 4.0 * half_extent)
+            * Matrix4::look_at_rh(&eye, &center, &up)
+    }
+}
+
+impl Default for DirectionalLight {
+    // white light, coming down from above at an angle
+    fn default() -> DirectionalLight {
+        DirectionalLight::new(Vector3::new(-0.3, -1.0, -0.5), [1.0, 1.0, 1.0])
+    }
+}
+
+/// the most point lights that can shine on the scene at once. must match MAX_POINT_LIGHTS in the fragment shader
+pub const MAX_POINT_LIGHTS: usize = 8;
+
+/// a light that shines in every direction from a point (like a street lamp), fading out with distance
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    // position of the light, in world space
+    pub position: Point3<f32>,
+    // color of the light, multiplied with the color of the surfaces it lights
+    pub color: [f32; 3],
+    // distance at which the light has faded out completely
+    pub radius: f32,
+}
+
+impl PointLight {
+    pub fn new(position: Point3<f32>, color: [f32; 3], radius: f32) -> PointLight {
+        assert!(radius > 0.0, "radius must be positive");
+        PointLight {
+            position,
+            color,
+            radius,
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use entity::{
-    EntityCreationData, EntityCreationPhysicsData, GameWorld, InteractiveRenderingConfig, EntityCreationCameraData,
+    ColliderShape, EntityCreationData, EntityCreationPhysicsData, GameWorld, InteractiveRenderingConfig, EntityCreationCameraData,
 };
-use nalgebra::{Isometry, Isometry3, Point3, Vector3};
+use nalgebra::{Isometry, Isometry3, Point3, Vector2, Vector3};
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::sync::Arc;
@@ -29,7 +29,9 @@ use vulkano::pipeline::{
 };
 use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
 use vulkano::shader::EntryPoint;
-use vulkano::swapchain::{self, Surface, Swapchain, SwapchainCreateInfo, SwapchainPresentInfo};
+use vulkano::swapchain::{
+    self, PresentMode, Surface, Swapchain, SwapchainCreateInfo, SwapchainPresentInfo,
+};
 use vulkano::sync::GpuFuture;
 use vulkano::{format::*, Validated, VulkanLibrary};
 use vulkano::{sync, VulkanError};
@@ -41,13 +43,17 @@ use winit::window::{Window, WindowBuilder};
 mod camera;
 mod entity;
 mod handle_user_input;
+mod light;
+mod mesh_loader;
 mod object;
+mod physics;
 mod render_system;
 mod shader;
 mod vertex;
 
 fn build_scene(
     queue: Arc<vulkano::device::Queue>,
+    transfer_queue: Arc<vulkano::device::Queue>,
     memory_allocator: Arc<StandardMemoryAllocator>,
     surface: Arc<Surface>,
 ) -> GameWorld {
@@ -85,29 +91,40 @@ fn build_scene(
         [15.0, 0.0, 15.0].into(),
     ];
 
-    let g = vec![[0.0, -0.1, -50.0].into(), [0.0, -0.1, 50.0].into()];
 
     let mut world = GameWorld::new(
         queue,
+        transfer_queue,
         memory_allocator,
         Some(InteractiveRenderingConfig {
             surface,
             tracking_entity: 0,
             camera: Box::new(camera::SphericalCamera::new()),
+            present_mode: PresentMode::Mailbox,
         }),
+        0,
     );
 
     // add ego agent
+    let (ego_mesh, ego_indices) = object::unitcube_indexed();
     world.add_entity(
         0,
         EntityCreationData {
             cameras: vec![EntityCreationCameraData {
                 camera: Box::new(camera::BEVCamera::new()),
-                extent: [128, 128]
+                extent: [128, 128],
+                segmentation: false,
             }],
-            physics: Some(EntityCreationPhysicsData { is_dynamic: true }),
-            mesh: object::unitcube(),
+            physics: Some(EntityCreationPhysicsData {
+                is_dynamic: true,
+                shape: ColliderShape::Cuboid,
+                ..Default::default()
+            }),
+            mesh: ego_mesh,
+            indices: Some(ego_indices),
             isometry: Isometry3::translation(0.0, 5.0, 0.0),
+            name: Some("ego".to_string()),
+            tags: vec![],
         },
     );
 
@@ -118,7 +135,10 @@ fn build_scene(
             cameras: vec![],
             physics: None,
             mesh: object::flat_polyline(rd.clone(), 1.0, [0.5, 0.5, 0.5, 1.0]),
+            indices: None,
             isometry: Isometry3::identity(),
+            name: Some("road".to_string()),
+            tags: vec!["road".to_string()],
         },
     );
 
@@ -133,18 +153,36 @@ fn build_scene(
                 0.1,
                 [1.0, 1.0, 0.0, 1.0],
             ),
+            indices: None,
             isometry: Isometry3::identity(),
+            name: Some("road_line".to_string()),
+            tags: vec!["road".to_string()],
         },
     );
 
+    // the ground entity's collider is only as big as its mesh, so nothing can fall off the edge
+    world.set_ground_plane(-0.1);
+
     // add ground
     world.add_entity(
         3,
         EntityCreationData {
             cameras: vec![],
-            physics: Some(EntityCreationPhysicsData { is_dynamic: false }),
-            mesh: object::flat_polyline(g.clone(), 50.0, [0.5, 1.0, 0.5, 1.0]),
+            physics: Some(EntityCreationPhysicsData {
+                is_dynamic: false,
+                shape: ColliderShape::Cuboid,
+                ..Default::default()
+            }),
+            mesh: object::plane(
+                Point3::new(0.0, -0.1, 0.0),
+                Vector2::new(50.0, 100.0),
+                [10, 20],
+                [0.5, 1.0, 0.5, 1.0],
+            ),
+            indices: None,
             isometry: Isometry3::identity(),
+            name: Some("ground".to_string()),
+            tags: vec![],
         },
     );
 
@@ -170,7 +208,7 @@ fn main() {
 
     let surface = Surface::from_window(instance.clone(), window).unwrap();
 
-    let (device, queue) = render_system::interactive_rendering::get_device_for_rendering_on(
+    let (device, queue, transfer_queue) = render_system::interactive_rendering::get_device_or_panic(
         instance.clone(),
         surface.clone(),
     );
@@ -184,10 +222,15 @@ fn main() {
 
     let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
 
-    let mut start_time = std::time::Instant::now();
-    let mut frame_count = 0;
+    let mut last_fps_print = std::time::Instant::now();
+    let mut last_frame_time = std::time::Instant::now();
 
-    let mut world = build_scene(queue.clone(), memory_allocator.clone(), surface.clone());
+    let mut world = build_scene(
+        queue.clone(),
+        transfer_queue.clone(),
+        memory_allocator.clone(),
+        surface.clone(),
+    );
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
@@ -196,21 +239,21 @@ fn main() {
         } => {
             *control_flow = ControlFlow::Exit;
         }
-        Event::WindowEvent { event, .. } => {
-            world.handle_window_event(&event);
+        event @ (Event::WindowEvent { .. } | Event::DeviceEvent { .. }) => {
+            world.handle_event(&event);
         }
         Event::RedrawEventsCleared => {
             // print fps
-            frame_count += 1;
-            let elapsed = start_time.elapsed();
-            if elapsed.as_secs() >= 1 {
-                println!("fps: {}", frame_count);
-                frame_count = 0;
-                start_time = std::time::Instant::now();
+            if last_fps_print.elapsed().as_secs() >= 1 {
+                println!("fps: {:.0}", world.fps());
+                last_fps_print = std::time::Instant::now();
             }
 
             // game step and render
-            let observations = world.step();
+            let now = std::time::Instant::now();
+            let dt = now.duration_since(last_frame_time).as_secs_f32();
+            last_frame_time = now;
+            let observations = world.step(dt);
             world.render();
         }
         _ => (),
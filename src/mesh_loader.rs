@@ -0,0 +1,260 @@
+use std::path::Path;
+
+use nalgebra::{Matrix3, Matrix4, Point3, Vector3};
+
+use crate::vertex::mVertex as Vertex;
+
+#[derive(Debug)]
+pub enum ObjError {
+    // the obj file (or its mtl file) couldn't be read or parsed
+    Load(tobj::LoadError),
+}
+
+impl std::fmt::Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Load(e) => write!(f, "failed to load obj: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<tobj::LoadError> for ObjError {
+    fn from(e: tobj::LoadError) -> Self {
+        ObjError::Load(e)
+    }
+}
+
+#[derive(Debug)]
+pub enum GltfError {
+    // the gltf file (or one of its buffers) couldn't be read or parsed
+    Import(gltf::Error),
+    // a primitive of the named node has no POSITION attribute
+    MissingPositions(String),
+}
+
+impl std::fmt::Display for GltfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GltfError::Import(e) => write!(f, "failed to load gltf: {e}"),
+            GltfError::MissingPositions(name) => {
+                write!(f, "a primitive of node {name} has no positions")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+impl From<gltf::Error> for GltfError {
+    fn from(e: gltf::Error) -> Self {
+        GltfError::Import(e)
+    }
+}
+
+// normal of the triangle (a, b, c), assuming counter clockwise winding. degenerate triangles just point up
+fn face_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    let a = Vector3::from(a);
+    let b = Vector3::from(b);
+    let c = Vector3::from(c);
+    (b - a)
+        .cross(&(c - a))
+        .try_normalize(1e-12)
+        .unwrap_or(Vector3::y())
+        .into()
+}
+
+/// loads every model in an obj file into a single triangle list.
+/// faces with more than 3 vertices are triangulated.
+/// vertices are colored with their material's diffuse color (`Kd`) if present, otherwise `default_color`.
+/// if the file has no normals, each triangle gets its face normal. texture coordinates are kept if present
+pub fn load_obj(path: &Path, default_color: [f32; 4]) -> Result<Vec<Vertex>, ObjError> {
+    let (models, materials) = tobj::load_obj(
+        path,
+        &tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        },
+    )?;
+    // a missing or broken mtl file isn't fatal, we just use the default color
+    let materials = materials.unwrap_or_default();
+
+    let mut vertexes = Vec::new();
+    for model in models {
+        let mesh = &model.mesh;
+        let color = mesh
+            .material_id
+            .and_then(|id| materials.get(id))
+            .and_then(|material| material.diffuse)
+            .map(|[r, g, b]| [r, g, b, 1.0])
+            .unwrap_or(default_color);
+
+        let position = |i: u32| -> [f32; 3] {
+            let i = i as usize * 3;
+            [mesh.positions[i], mesh.positions[i + 1], mesh.positions[i + 2]]
+        };
+        let normal = |i: u32| -> Option<[f32; 3]> {
+            let i = i as usize * 3;
+            mesh.normals.get(i..i + 3).map(|n| [n[0], n[1], n[2]])
+        };
+        let uv = |i: u32| -> [f32; 2] {
+            let i = i as usize * 2;
+            // obj puts v = 0 at the bottom of the texture, vulkan at the top
+            mesh.texcoords.get(i..i + 2).map_or([0.0, 0.0], |t| [t[0], 1.0 - t[1]])
+        };
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            let locs = [
+                position(triangle[0]),
+                position(triangle[1]),
+                position(triangle[2]),
+            ];
+            let fallback_normal = face_normal(locs[0], locs[1], locs[2]);
+            for (&index, loc) in triangle.iter().zip(locs) {
+                vertexes.push(
+                    Vertex::new(loc, color, normal(index).unwrap_or(fallback_normal))
+                        .with_uv(uv(index)),
+                );
+            }
+        }
+    }
+    Ok(vertexes)
+}
+
+/// loads every triangle primitive in a gltf file, returning each one with the name of the node it belongs to.
+/// node transforms are applied, so vertices are in world space.
+/// vertices are colored with COLOR_0 if present, otherwise the material's base color.
+/// if a primitive has no normals, each triangle gets its face normal. TEXCOORD_0 is kept if present
+pub fn load_gltf(path: &Path) -> Result<Vec<(String, Vec<Vertex>)>, GltfError> {
+    let (document, buffers, _) = gltf::import(path)?;
+
+    let mut primitives = Vec::new();
+    for scene in document.scenes() {
+        for node in scene.nodes() {
+            load_gltf_node(&node, Matrix4::identity(), &buffers, &mut primitives)?;
+        }
+    }
+    Ok(primitives)
+}
+
+// loads the node's mesh (if any) and then its children, accumulating transforms down the hierarchy
+fn load_gltf_node(
+    node: &gltf::Node,
+    parent_transform: Matrix4<f32>,
+    buffers: &[gltf::buffer::Data],
+    primitives: &mut Vec<(String, Vec<Vertex>)>,
+) -> Result<(), GltfError> {
+    let transform = parent_transform * Matrix4::from(node.transform().matrix());
+    let name = node
+        .name()
+        .map(String::from)
+        .unwrap_or_else(|| format!("node{}", node.index()));
+
+    if let Some(mesh) = node.mesh() {
+        // normals have to be transformed by the inverse transpose, in case of non-uniform scaling
+        let normal_transform = transform
+            .fixed_view::<3, 3>(0, 0)
+            .try_inverse()
+            .unwrap_or_else(Matrix3::identity)
+            .transpose();
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            let positions: Vec<[f32; 3]> = reader
+                .read_positions()
+                .ok_or_else(|| GltfError::MissingPositions(name.clone()))?
+                .map(|p| transform.transform_point(&Point3::from(p)).into())
+                .collect();
+            let colors: Option<Vec<[f32; 4]>> =
+                reader.read_colors(0).map(|c| c.into_rgba_f32().collect());
+            let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|n| {
+                n.map(|n| (normal_transform * Vector3::from(n)).normalize().into())
+                    .collect()
+            });
+            let uvs: Option<Vec<[f32; 2]>> =
+                reader.read_tex_coords(0).map(|t| t.into_f32().collect());
+            let default_color = primitive
+                .material()
+                .pbr_metallic_roughness()
+                .base_color_factor();
+            // unindexed primitives are plain triangle lists
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+
+            let mut vertexes = Vec::with_capacity(indices.len());
+            for triangle in indices.chunks_exact(3) {
+                let locs = [triangle[0], triangle[1], triangle[2]].map(|i| positions[i as usize]);
+                let fallback_normal = face_normal(locs[0], locs[1], locs[2]);
+                for (&index, loc) in triangle.iter().zip(locs) {
+                    let index = index as usize;
+                    vertexes.push(
+                        Vertex::new(
+                            loc,
+                            colors.as_ref().map_or(default_color, |c| c[index]),
+                            normals.as_ref().map_or(fallback_normal, |n| n[index]),
+                        )
+                        .with_uv(uvs.as_ref().map_or([0.0, 0.0], |t| t[index])),
+                    );
+                }
+            }
+            primitives.push((name.clone(), vertexes));
+        }
+    }
+
+    for child in node.children() {
+        load_gltf_node(&child, transform, buffers, primitives)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a unit cube made of quads, without normals or materials
+    const CUBE_OBJ: &str = "\
+v -0.5 -0.5 -0.5
+v 0.5 -0.5 -0.5
+v 0.5 0.5 -0.5
+v -0.5 0.5 -0.5
+v -0.5 -0.5 0.5
+v 0.5 -0.5 0.5
+v 0.5 0.5 0.5
+v -0.5 0.5 0.5
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+f 4 8 7 3
+f 1 5 8 4
+f 2 3 7 6
+";
+
+    #[test]
+    fn loads_cube_obj() {
+        let path = std::env::temp_dir().join(format!("minidrive_cube_{}.obj", std::process::id()));
+        std::fs::write(&path, CUBE_OBJ).unwrap();
+        let result = load_obj(&path, [0.2, 0.4, 0.6, 1.0]);
+        std::fs::remove_file(&path).unwrap();
+        let cube = result.unwrap();
+
+        // each quad is split into two triangles
+        assert_eq!(cube.len() / 3, 12);
+        for v in &cube {
+            assert_eq!(v.color, [0.2, 0.4, 0.6, 1.0]);
+            assert!((Vector3::from(v.normal).norm() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn degenerate_face_normal_is_finite() {
+        assert_eq!(face_normal([1.0; 3], [1.0; 3], [1.0; 3]), [0.0, 1.0, 0.0]);
+    }
+}
@@ -1,7 +1,11 @@
-use nalgebra::{Isometry3, Point3, Vector3};
+use nalgebra::{Isometry3, Point3, UnitQuaternion, Vector2, Vector3};
 
 use crate::vertex::mVertex as Vertex;
 
+// at sharp corners the miter gets arbitrarily long, so corners that would need more than this multiple of the width
+// are beveled instead
+const MITER_LIMIT: f32 = 4.0;
+
 pub fn flat_polyline(points: Vec<Vector3<f32>>, width: f32, color: [f32; 4]) -> Vec<Vertex> {
     let points: Vec<Vector3<f32>> = points
         .iter()
@@ -15,6 +19,51 @@ pub fn flat_polyline(points: Vec<Vector3<f32>>, width: f32, color: [f32; 4]) ->
     polyline(points, normals, width, colors)
 }
 
+/// like `flat_polyline`, but the color fades from `start_color` to `end_color` along the length of the line.
+/// each segment is colored with the gradient sampled at its midpoint
+pub fn flat_polyline_gradient(
+    points: Vec<Vector3<f32>>,
+    width: f32,
+    start_color: [f32; 4],
+    end_color: [f32; 4],
+) -> Vec<Vertex> {
+    assert!(points.len() > 1, "not enough points");
+    let segment_lengths: Vec<f32> = points.windows(2).map(|w| (w[1] - w[0]).norm()).collect();
+    let total_length: f32 = segment_lengths.iter().sum();
+
+    let mut distance = 0.0;
+    let colors = segment_lengths
+        .iter()
+        .map(|&length| {
+            let midpoint = distance + length * 0.5;
+            distance += length;
+            // a line of zero length has nowhere to fade, so it gets the start color
+            let t = if total_length > 0.0 {
+                midpoint / total_length
+            } else {
+                0.0
+            };
+            [0, 1, 2, 3].map(|i| start_color[i] + (end_color[i] - start_color[i]) * t)
+        })
+        .collect();
+
+    let normals: Vec<Vector3<f32>> = std::iter::repeat([0.0, 1.0, 0.0].into())
+        .take(points.len())
+        .collect();
+    let width: Vec<f32> = std::iter::repeat(width).take(points.len()).collect();
+    polyline(points, normals, width, colors)
+}
+
+/// like `flat_polyline`, but the last point is connected back to the first, forming a loop
+pub fn flat_polyline_closed(points: Vec<Vector3<f32>>, width: f32, color: [f32; 4]) -> Vec<Vertex> {
+    let normals: Vec<Vector3<f32>> = std::iter::repeat([0.0, 1.0, 0.0].into())
+        .take(points.len())
+        .collect();
+    let width: Vec<f32> = std::iter::repeat(width).take(points.len()).collect();
+    let colors = std::iter::repeat(color).take(points.len()).collect();
+    polyline_closed(points, normals, width, colors)
+}
+
 pub fn polyline(
     points: Vec<Vector3<f32>>,
     normals: Vec<Vector3<f32>>,
@@ -34,56 +83,150 @@ pub fn polyline(
         points.len() - 1 == colors.len(),
         "there must be exactly one color per line segment"
     );
-    // find the vector of each line segment
-    let dposition_per_segment: Vec<Vector3<f32>> = points.windows(2).map(|w| w[1] - w[0]).collect();
+    ribbon(points, normals, width, colors, false)
+}
+
+/// like `polyline`, but the last point is connected back to the first, so there is one more segment (and color)
+pub fn polyline_closed(
+    points: Vec<Vector3<f32>>,
+    normals: Vec<Vector3<f32>>,
+    width: Vec<f32>,
+    colors: Vec<[f32; 4]>,
+) -> Vec<Vertex> {
+    assert!(points.len() > 2, "not enough points");
+    assert!(
+        points.len() == normals.len(),
+        "there must be exactly one normal per point"
+    );
+    assert!(
+        points.len() == width.len(),
+        "there must be exactly one width per point"
+    );
+    assert!(
+        points.len() == colors.len(),
+        "there must be exactly one color per line segment"
+    );
+    ribbon(points, normals, width, colors, true)
+}
+
+// shared implementation of `polyline` and `polyline_closed`
+fn ribbon(
+    mut points: Vec<Vector3<f32>>,
+    mut normals: Vec<Vector3<f32>>,
+    mut width: Vec<f32>,
+    colors: Vec<[f32; 4]>,
+    closed: bool,
+) -> Vec<Vertex> {
+    // a loop is a polyline that ends where it starts
+    if closed {
+        points.push(points[0]);
+        normals.push(normals[0]);
+        width.push(width[0]);
+    }
+
+    // find the direction of each line segment
+    let dposition_per_segment: Vec<Vector3<f32>> = points
+        .windows(2)
+        .map(|w| (w[1] - w[0]).normalize())
+        .collect();
 
     // dposition_per_points[0] = dposition_per_segment[0] and dposition_per_points[n] = dposition_per_segment[n-1], but it is the average of the two for the points in between
+    // for a loop, the endpoints are the average of the last and first segment instead
+    let first_segment = dposition_per_segment[0];
+    let last_segment = dposition_per_segment[dposition_per_segment.len() - 1];
+    // where the line doubles back on itself the average is zero, so keep the incoming direction (the corner is beveled)
+    let average = |incoming: Vector3<f32>, outgoing: Vector3<f32>| {
+        (incoming + outgoing).try_normalize(1e-6).unwrap_or(incoming)
+    };
+    let endpoint_dposition = match closed {
+        true => average(last_segment, first_segment),
+        false => first_segment,
+    };
     let dposition_per_points: Vec<Vector3<f32>> = {
         let mut dposition_per_points = Vec::new();
-        dposition_per_points.push(dposition_per_segment[0]);
+        dposition_per_points.push(endpoint_dposition);
         for i in 1..dposition_per_segment.len() {
             dposition_per_points
-                .push((dposition_per_segment[i - 1] + dposition_per_segment[i]).normalize());
+                .push(average(dposition_per_segment[i - 1], dposition_per_segment[i]));
         }
-        dposition_per_points.push(dposition_per_segment[dposition_per_segment.len() - 1]);
+        dposition_per_points.push(match closed {
+            true => endpoint_dposition,
+            false => last_segment,
+        });
         dposition_per_points
     };
 
-    // find the cross vectors (along which the width will be applied)
-    let cross_vectors: Vec<Vector3<f32>> = dposition_per_points
-        .iter()
-        .zip(normals.iter())
-        .map(|(&v, n)| v.cross(n).normalize())
+    // at corners, the points have to be pushed out by 1/cos(theta/2) to keep the ribbon's width constant,
+    // where theta/2 is the angle between the averaged direction and the segments.
+    // past MITER_LIMIT the corner is beveled: each segment keeps its own square end, and a triangle fills the gap
+    let cos_half_theta: Vec<f32> = (0..points.len())
+        .map(|i| {
+            let is_endpoint = i == 0 || i == points.len() - 1;
+            if is_endpoint && !closed {
+                return 1.0;
+            }
+            let segment = dposition_per_segment[i % dposition_per_segment.len()];
+            dposition_per_points[i].dot(&segment)
+        })
         .collect();
-
-    // find the left and right points
-    let left_points: Vec<Vector3<f32>> = cross_vectors
+    let beveled: Vec<bool> = cos_half_theta
         .iter()
-        .zip(width.iter())
-        .zip(points.iter())
-        .map(|((v, &w), p)| p - v * w)
+        .map(|&cos_half_theta| cos_half_theta < 1.0 / MITER_LIMIT)
         .collect();
 
-    let right_points: Vec<Vector3<f32>> = cross_vectors
-        .iter()
-        .zip(width.iter())
-        .zip(points.iter())
-        .map(|((v, &w), p)| p + v * w)
-        .collect();
+    // the vector from point i to its right side, on segment s (one of the segments touching the point)
+    let offset = |i: usize, s: usize| -> Vector3<f32> {
+        match beveled[i] {
+            true => dposition_per_segment[s].cross(&normals[i]).normalize() * width[i],
+            false => {
+                dposition_per_points[i].cross(&normals[i]).normalize() * (width[i] / cos_half_theta[i])
+            }
+        }
+    };
 
-    let vertexes: Vec<Vertex> = std::iter::zip(left_points.windows(2), right_points.windows(2))
-        .zip(colors)
-        .flat_map(|((l, r), color)| {
-            vec![
-                Vertex::new(l[0].into(), color),
-                Vertex::new(l[1].into(), color),
-                Vertex::new(r[0].into(), color),
-                Vertex::new(r[0].into(), color),
-                Vertex::new(l[1].into(), color),
-                Vertex::new(r[1].into(), color),
-            ]
-        })
-        .collect();
+    let mut vertexes: Vec<Vertex> = Vec::new();
+    for (s, &color) in colors.iter().enumerate() {
+        let (a, b) = (s, s + 1);
+        let l0 = points[a] - offset(a, s);
+        let r0 = points[a] + offset(a, s);
+        let l1 = points[b] - offset(b, s);
+        let r1 = points[b] + offset(b, s);
+        vertexes.extend([
+            Vertex::new(l0.into(), color, normals[a].into()),
+            Vertex::new(l1.into(), color, normals[b].into()),
+            Vertex::new(r0.into(), color, normals[a].into()),
+            Vertex::new(r0.into(), color, normals[a].into()),
+            Vertex::new(l1.into(), color, normals[b].into()),
+            Vertex::new(r1.into(), color, normals[b].into()),
+        ]);
+    }
+
+    // fill in the beveled corners on both sides (the inner one just overlaps the segments).
+    // for a loop, the corner where it closes is the last point, between the last and first segment
+    let segment_count = dposition_per_segment.len();
+    let corners = match closed {
+        true => 1..points.len(),
+        false => 1..points.len() - 1,
+    };
+    for i in corners.filter(|&i| beveled[i]) {
+        let (incoming, outgoing) = (i - 1, i % segment_count);
+        let color = colors[incoming];
+        let n = normals[i];
+        for side in [-1.0, 1.0] {
+            let p = points[i];
+            let mut a = p + offset(i, incoming) * side;
+            let mut b = p + offset(i, outgoing) * side;
+            // wind the same way as the segments, which face away from the normal
+            if (a - p).cross(&(b - p)).dot(&n) > 0.0 {
+                std::mem::swap(&mut a, &mut b);
+            }
+            vertexes.extend([
+                Vertex::new(p.into(), color, n.into()),
+                Vertex::new(a.into(), color, n.into()),
+                Vertex::new(b.into(), color, n.into()),
+            ]);
+        }
+    }
     vertexes
 }
 
@@ -96,42 +239,334 @@ pub fn cuboid(loc: Point3<f32>, dims: Vector3<f32>) -> Vec<Vertex> {
     let y = loc[1];
     let z = loc[2];
 
-    let lbu = Vertex::new([x - xsize, y + ysize, z - zsize], [0.5, 0.9, 0.9, 1.0]);
-    let rbu = Vertex::new([x + xsize, y + ysize, z - zsize], [0.5, 0.5, 0.9, 1.0]);
-    let lfu = Vertex::new([x - xsize, y + ysize, z + zsize], [0.9, 0.5, 0.9, 1.0]);
-    let rfu = Vertex::new([x + xsize, y + ysize, z + zsize], [0.5, 0.9, 0.9, 1.0]);
-    let lbl = Vertex::new([x - xsize, y - ysize, z - zsize], [0.5, 0.5, 0.3, 1.0]);
-    let rbl = Vertex::new([x + xsize, y - ysize, z - zsize], [0.9, 0.5, 0.3, 1.0]);
-    let lfl = Vertex::new([x - xsize, y - ysize, z + zsize], [0.5, 0.5, 0.3, 1.0]);
-    let rfl = Vertex::new([x + xsize, y - ysize, z + zsize], [0.0, 0.0, 0.3, 1.0]);
-
-    vec![
-        lbu, rbu, lfu, lfu, rfu, rbu, // upper square
-        lbl, rbl, lfl, lfl, rfl, rbl, // lower square
-        lfu, rfu, lfl, lfl, rfl, rfu, // front square
-        lbu, rbu, lbl, lbl, rbl, rbu, // back square
-        lbu, lfu, lbl, lbl, lfl, lfu, // left square
-        rbu, rfu, rbl, rbl, rfl, rfu, // right square
+    // corners: (l)eft/(r)ight, (b)ack/(f)ront, (u)pper/(l)ower
+    let lbu = ([x - xsize, y + ysize, z - zsize], [0.5, 0.9, 0.9, 1.0]);
+    let rbu = ([x + xsize, y + ysize, z - zsize], [0.5, 0.5, 0.9, 1.0]);
+    let lfu = ([x - xsize, y + ysize, z + zsize], [0.9, 0.5, 0.9, 1.0]);
+    let rfu = ([x + xsize, y + ysize, z + zsize], [0.5, 0.9, 0.9, 1.0]);
+    let lbl = ([x - xsize, y - ysize, z - zsize], [0.5, 0.5, 0.3, 1.0]);
+    let rbl = ([x + xsize, y - ysize, z - zsize], [0.9, 0.5, 0.3, 1.0]);
+    let lfl = ([x - xsize, y - ysize, z + zsize], [0.5, 0.5, 0.3, 1.0]);
+    let rfl = ([x + xsize, y - ysize, z + zsize], [0.0, 0.0, 0.3, 1.0]);
+
+    // two triangles per face, all sharing the face's normal. each face is textured with the whole texture
+    let face = |a: ([f32; 3], [f32; 4]),
+                b: ([f32; 3], [f32; 4]),
+                c: ([f32; 3], [f32; 4]),
+                d: ([f32; 3], [f32; 4]),
+                normal: [f32; 3]| {
+        [
+            (a, [0.0, 0.0]),
+            (b, [1.0, 0.0]),
+            (c, [0.0, 1.0]),
+            (c, [0.0, 1.0]),
+            (d, [1.0, 1.0]),
+            (b, [1.0, 0.0]),
+        ]
+        .map(|((loc, color), uv)| Vertex::new(loc, color, normal).with_uv(uv))
+    };
+
+    [
+        face(lbu, rbu, lfu, rfu, [0.0, 1.0, 0.0]),  // upper square
+        face(lbl, rbl, lfl, rfl, [0.0, -1.0, 0.0]), // lower square
+        face(lfu, rfu, lfl, rfl, [0.0, 0.0, 1.0]),  // front square
+        face(lbu, rbu, lbl, rbl, [0.0, 0.0, -1.0]), // back square
+        face(lbu, lfu, lbl, lfl, [-1.0, 0.0, 0.0]), // left square
+        face(rbu, rfu, rbl, rfl, [1.0, 0.0, 0.0]),  // right square
     ]
+    .concat()
+}
+
+/// horizontal rectangle in the XZ plane, `size[0]` along x and `size[1]` along z, facing +y.
+/// it is split into `subdivisions[0] * subdivisions[1]` cells of two triangles each.
+/// the whole plane is covered by the texture once, with u along x and v along z
+pub fn plane(
+    center: Point3<f32>,
+    size: Vector2<f32>,
+    subdivisions: [u32; 2],
+    color: [f32; 4],
+) -> Vec<Vertex> {
+    assert!(
+        subdivisions[0] > 0 && subdivisions[1] > 0,
+        "need at least one subdivision along each axis"
+    );
+    let normal = [0.0, 1.0, 0.0];
+    let cell_x = size[0] / subdivisions[0] as f32;
+    let cell_z = size[1] / subdivisions[1] as f32;
+    let corner = |i: u32, j: u32| {
+        let loc = [
+            center[0] - size[0] * 0.5 + i as f32 * cell_x,
+            center[1],
+            center[2] - size[1] * 0.5 + j as f32 * cell_z,
+        ];
+        let uv = [
+            i as f32 / subdivisions[0] as f32,
+            j as f32 / subdivisions[1] as f32,
+        ];
+        Vertex::new(loc, color, normal).with_uv(uv)
+    };
+
+    let mut vertexes = Vec::with_capacity((subdivisions[0] * subdivisions[1] * 6) as usize);
+    for i in 0..subdivisions[0] {
+        for j in 0..subdivisions[1] {
+            let a = corner(i, j);
+            let b = corner(i, j + 1);
+            let c = corner(i + 1, j);
+            let d = corner(i + 1, j + 1);
+            vertexes.extend([a, b, c, c, b, d]);
+        }
+    }
+    vertexes
+}
+
+/// like `cuboid`, but each corner of each face is only stored once and reused through indices
+/// corners can't be shared between faces since each face has its own normal, so there are 24 vertices and 36 indices
+pub fn cuboid_indexed(loc: Point3<f32>, dims: Vector3<f32>) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertexes = Vec::new();
+    let mut indexes = Vec::new();
+    // `cuboid` emits each face as (a, b, c, c, d, b)
+    for face in cuboid(loc, dims).chunks(6) {
+        let base = vertexes.len() as u32;
+        vertexes.extend([face[0], face[1], face[2], face[4]]);
+        indexes.extend([0, 1, 2, 2, 3, 1].map(|i| base + i));
+    }
+    (vertexes, indexes)
+}
+
+/// latitude/longitude sphere. `rings` is the number of bands from pole to pole, `sectors` the number around the equator.
+/// each (ring, sector) quad becomes two triangles, so there are `rings * sectors * 6` vertices
+/// (the triangles touching the poles are degenerate)
+pub fn uv_sphere(
+    center: Point3<f32>,
+    radius: f32,
+    rings: u32,
+    sectors: u32,
+    color: [f32; 4],
+) -> Vec<Vertex> {
+    assert!(rings >= 2, "sphere needs at least 2 rings");
+    assert!(sectors >= 3, "sphere needs at least 3 sectors");
+
+    // radial direction of the point at the start of ring i and sector j
+    let direction = |i: u32, j: u32| {
+        let polar = std::f32::consts::PI * i as f32 / rings as f32;
+        let azimuth = 2.0 * std::f32::consts::PI * j as f32 / sectors as f32;
+        Vector3::new(
+            polar.sin() * azimuth.cos(),
+            polar.cos(),
+            polar.sin() * azimuth.sin(),
+        )
+    };
+    let vertex = |i: u32, j: u32| {
+        let n = direction(i, j);
+        Vertex::new((center + n * radius).into(), color, n.into())
+    };
+
+    (0..rings)
+        .flat_map(|i| (0..sectors).map(move |j| (i, j)))
+        .flat_map(|(i, j)| {
+            // counter clockwise when seen from outside
+            let a = vertex(i, j);
+            let b = vertex(i, j + 1);
+            let c = vertex(i + 1, j);
+            let d = vertex(i + 1, j + 1);
+            [a, b, c, c, b, d]
+        })
+        .collect()
+}
+
+// unit vector in the xz plane pointing towards segment j of a circle with the given number of segments.
+// j wraps around, so that the last segment ends exactly where the first one starts
+fn circle_direction(j: u32, segments: u32) -> Vector3<f32> {
+    let angle = 2.0 * std::f32::consts::PI * (j % segments) as f32 / segments as f32;
+    Vector3::new(angle.cos(), 0.0, angle.sin())
+}
+
+/// closed cylinder along the y axis, with caps on both ends.
+/// triangles are counter clockwise when seen from outside
+pub fn cylinder(
+    center: Point3<f32>,
+    radius: f32,
+    height: f32,
+    segments: u32,
+    color: [f32; 4],
+) -> Vec<Vertex> {
+    assert!(segments >= 3, "cylinder needs at least 3 segments");
+
+    let top_center = center + Vector3::new(0.0, height * 0.5, 0.0);
+    let bottom_center = center - Vector3::new(0.0, height * 0.5, 0.0);
+    let up = Vector3::new(0.0, 1.0, 0.0);
+    let down = -up;
+
+    (0..segments)
+        .flat_map(|j| {
+            let n0 = circle_direction(j, segments);
+            let n1 = circle_direction(j + 1, segments);
+            let top0 = top_center + n0 * radius;
+            let top1 = top_center + n1 * radius;
+            let bottom0 = bottom_center + n0 * radius;
+            let bottom1 = bottom_center + n1 * radius;
+            [
+                // side
+                Vertex::new(top0.into(), color, n0.into()),
+                Vertex::new(top1.into(), color, n1.into()),
+                Vertex::new(bottom0.into(), color, n0.into()),
+                Vertex::new(bottom0.into(), color, n0.into()),
+                Vertex::new(top1.into(), color, n1.into()),
+                Vertex::new(bottom1.into(), color, n1.into()),
+                // top cap
+                Vertex::new(top_center.into(), color, up.into()),
+                Vertex::new(top1.into(), color, up.into()),
+                Vertex::new(top0.into(), color, up.into()),
+                // bottom cap
+                Vertex::new(bottom_center.into(), color, down.into()),
+                Vertex::new(bottom0.into(), color, down.into()),
+                Vertex::new(bottom1.into(), color, down.into()),
+            ]
+        })
+        .collect()
+}
+
+/// closed cone pointing up the y axis, with a cap on its base.
+/// triangles are counter clockwise when seen from outside
+pub fn cone(
+    base_center: Point3<f32>,
+    radius: f32,
+    height: f32,
+    segments: u32,
+    color: [f32; 4],
+) -> Vec<Vertex> {
+    assert!(segments >= 3, "cone needs at least 3 segments");
+
+    let apex = base_center + Vector3::new(0.0, height, 0.0);
+    let down = Vector3::new(0.0, -1.0, 0.0);
+    // the side normal tilts up by the slope of the cone
+    let side_normal = |d: Vector3<f32>| (d * height + Vector3::new(0.0, radius, 0.0)).normalize();
+
+    (0..segments)
+        .flat_map(|j| {
+            let n0 = circle_direction(j, segments);
+            let n1 = circle_direction(j + 1, segments);
+            let base0 = base_center + n0 * radius;
+            let base1 = base_center + n1 * radius;
+            // the apex has no single normal, so use the one halfway between the two edges
+            let apex_normal = side_normal((n0 + n1).normalize());
+            [
+                // side
+                Vertex::new(apex.into(), color, apex_normal.into()),
+                Vertex::new(base1.into(), color, side_normal(n1).into()),
+                Vertex::new(base0.into(), color, side_normal(n0).into()),
+                // base cap
+                Vertex::new(base_center.into(), color, down.into()),
+                Vertex::new(base0.into(), color, down.into()),
+                Vertex::new(base1.into(), color, down.into()),
+            ]
+        })
+        .collect()
 }
 
 pub fn unitcube() -> Vec<Vertex> {
     cuboid(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0))
 }
 
+pub fn unitcube_indexed() -> (Vec<Vertex>, Vec<u32>) {
+    cuboid_indexed(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0))
+}
+
+// unnormalized normal of a triangle, its length is twice the triangle's area
+fn triangle_normal(triangle: &[Vertex]) -> Vector3<f32> {
+    let a = Vector3::from(triangle[0].loc);
+    let b = Vector3::from(triangle[1].loc);
+    let c = Vector3::from(triangle[2].loc);
+    (b - a).cross(&(c - a))
+}
+
+/// sets smooth normals on a triangle list: each vertex gets the average of the normals of the faces touching it,
+/// weighted by area. vertices are matched by position, so duplicated corners are smoothed together
+pub fn compute_normals(mesh: &mut [Vertex]) {
+    assert!(mesh.len() % 3 == 0, "mesh must be a triangle list");
+    // positions can't be hashed as floats, so hash their bits
+    let key = |v: &Vertex| v.loc.map(f32::to_bits);
+    let mut accumulated: std::collections::HashMap<[u32; 3], Vector3<f32>> =
+        std::collections::HashMap::new();
+    for triangle in mesh.chunks_exact(3) {
+        let normal = triangle_normal(triangle);
+        for v in triangle {
+            *accumulated.entry(key(v)).or_insert(Vector3::zeros()) += normal;
+        }
+    }
+    // a point only touched by degenerate triangles has no normal, so it just gets pointed up
+    for v in mesh.iter_mut() {
+        v.normal = accumulated[&key(v)]
+            .try_normalize(1e-12)
+            .unwrap_or(Vector3::y())
+            .into();
+    }
+}
+
+/// sets flat normals on a triangle list: each vertex gets the normal of its own face, for hard edges
+pub fn compute_flat_normals(mesh: &mut [Vertex]) {
+    assert!(mesh.len() % 3 == 0, "mesh must be a triangle list");
+    for triangle in mesh.chunks_exact_mut(3) {
+        let normal = triangle_normal(triangle)
+            .try_normalize(1e-12)
+            .unwrap_or(Vector3::y());
+        for v in triangle {
+            v.normal = normal.into();
+        }
+    }
+}
+
 pub fn transform(vec: &Vec<Vertex>, isometry: &Isometry3<f32>) -> Vec<Vertex> {
     vec.iter()
         .map(|v| {
             let loc: Point3<f32> = isometry * Point3::from(v.loc);
-            Vertex::new(loc.into(), v.color)
+            let normal: Vector3<f32> = isometry.rotation * Vector3::from(v.normal);
+            Vertex {
+                loc: loc.into(),
+                normal: normal.into(),
+                ..*v
+            }
         })
         .collect()
 }
 
-// get axis aligned bounding box
-pub fn get_aabb(obj: &[Vertex]) -> Vector3<f32> {
-    let mut min = Vector3::new(std::f32::MAX, std::f32::MAX, std::f32::MAX);
-    let mut max = Vector3::new(std::f32::MIN, std::f32::MIN, std::f32::MIN);
+/// scales each axis of the mesh by the corresponding factor, about the origin.
+/// normals are scaled by the inverse factors (and renormalized) so they stay perpendicular to the surface
+pub fn scale(vec: &[Vertex], factors: Vector3<f32>) -> Vec<Vertex> {
+    vec.iter()
+        .map(|v| {
+            let loc = Vector3::from(v.loc).component_mul(&factors);
+            let normal = Vector3::from(v.normal)
+                .component_div(&factors)
+                .normalize();
+            Vertex {
+                loc: loc.into(),
+                normal: normal.into(),
+                ..*v
+            }
+        })
+        .collect()
+}
+
+/// rotates the mesh about the origin
+pub fn rotate(vec: &[Vertex], rotation: &UnitQuaternion<f32>) -> Vec<Vertex> {
+    vec.iter()
+        .map(|v| {
+            let loc = rotation * Vector3::from(v.loc);
+            let normal = rotation * Vector3::from(v.normal);
+            Vertex {
+                loc: loc.into(),
+                normal: normal.into(),
+                ..*v
+            }
+        })
+        .collect()
+}
+
+// get axis aligned bounding box, as the (min, max) corners
+pub fn get_aabb(obj: &[Vertex]) -> (Point3<f32>, Point3<f32>) {
+    let mut min = Point3::new(std::f32::MAX, std::f32::MAX, std::f32::MAX);
+    let mut max = Point3::new(std::f32::MIN, std::f32::MIN, std::f32::MIN);
     for v in obj.iter() {
         if v.loc[0] < min[0] {
             min[0] = v.loc[0];
@@ -152,5 +587,192 @@ pub fn get_aabb(obj: &[Vertex]) -> Vector3<f32> {
             max[2] = v.loc[2];
         }
     }
-    max - min
+    (min, max)
+}
+
+// half of the width, height, and depth of the axis aligned bounding box
+pub fn aabb_half_extents(obj: &[Vertex]) -> Vector3<f32> {
+    let (min, max) = get_aabb(obj);
+    (max - min) / 2.0
+}
+
+// center of the axis aligned bounding box
+pub fn aabb_center(obj: &[Vertex]) -> Point3<f32> {
+    let (min, max) = get_aabb(obj);
+    nalgebra::center(&min, &max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a unit square in the XZ plane, wound so that it faces up
+    fn upward_quad() -> Vec<Vertex> {
+        let corner = |x: f32, z: f32| Vertex::new([x, 0.0, z], [1.0; 4], [0.0; 3]);
+        vec![
+            corner(0.0, 0.0),
+            corner(0.0, 1.0),
+            corner(1.0, 0.0),
+            corner(1.0, 0.0),
+            corner(0.0, 1.0),
+            corner(1.0, 1.0),
+        ]
+    }
+
+    #[test]
+    fn computed_normals_of_upward_quad_point_up() {
+        let mut smooth = upward_quad();
+        compute_normals(&mut smooth);
+        let mut flat = upward_quad();
+        compute_flat_normals(&mut flat);
+        for v in smooth.iter().chain(&flat) {
+            assert!((Vector3::from(v.normal) - Vector3::y()).norm() < 1e-6, "{:?}", v.normal);
+        }
+    }
+
+    #[test]
+    fn degenerate_triangles_get_finite_normals() {
+        let mut mesh = vec![Vertex::new([1.0, 2.0, 3.0], [1.0; 4], [0.0; 3]); 3];
+        compute_normals(&mut mesh);
+        assert!(mesh.iter().all(|v| v.normal == [0.0, 1.0, 0.0]));
+        compute_flat_normals(&mut mesh);
+        assert!(mesh.iter().all(|v| v.normal == [0.0, 1.0, 0.0]));
+    }
+
+    #[test]
+    fn polyline_keeps_its_width_around_corners() {
+        let width = 0.5;
+        let mesh = flat_polyline(
+            vec![
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(10.0, 0.0, 0.0),
+                Vector3::new(10.0, 0.0, 10.0),
+            ],
+            width,
+            [1.0; 4],
+        );
+        // a right angle is mitered, so there are no extra triangles
+        assert_eq!(mesh.len(), 12);
+        // the first leg runs along x and the second along z, and both are `width` to either side of the line
+        for v in &mesh[..6] {
+            assert!((v.loc[2].abs() - width).abs() < 1e-5, "{:?}", v.loc);
+        }
+        for v in &mesh[6..] {
+            assert!(((v.loc[0] - 10.0).abs() - width).abs() < 1e-5, "{:?}", v.loc);
+        }
+    }
+
+    #[test]
+    fn polyline_bevels_sharp_turns() {
+        let width = 0.5;
+        // doubles straight back on itself
+        let mesh = flat_polyline(
+            vec![
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(5.0, 0.0, 0.0),
+                Vector3::new(0.0, 0.0, 0.0),
+            ],
+            width,
+            [1.0; 4],
+        );
+        assert!(mesh.len() > 12, "no bevel was added");
+        for v in &mesh {
+            assert!(v.loc.iter().all(|x| x.is_finite()), "{:?}", v.loc);
+            // instead of a miter shooting off to infinity, nothing sticks out past the width
+            assert!(v.loc[0] <= 5.0 + 1e-5 && v.loc[2].abs() <= width + 1e-5, "{:?}", v.loc);
+        }
+    }
+
+    #[test]
+    fn uv_sphere_has_six_vertices_per_quad() {
+        let center = Point3::new(1.0, -2.0, 3.0);
+        for (rings, sectors) in [(2, 3), (8, 16), (5, 7)] {
+            let sphere = uv_sphere(center, 2.0, rings, sectors, [1.0; 4]);
+            assert_eq!(sphere.len(), (rings * sectors * 6) as usize);
+            for v in &sphere {
+                assert!(((Point3::from(v.loc) - center).norm() - 2.0).abs() < 1e-5);
+            }
+        }
+    }
+
+    // checks that every edge is shared by exactly two triangles, which use it in opposite directions
+    fn assert_watertight(mesh: &[Vertex]) {
+        let key = |v: &Vertex| v.loc.map(f32::to_bits);
+        let mut edges: std::collections::HashMap<([u32; 3], [u32; 3]), u32> =
+            std::collections::HashMap::new();
+        for triangle in mesh.chunks_exact(3) {
+            for i in 0..3 {
+                let edge = (key(&triangle[i]), key(&triangle[(i + 1) % 3]));
+                *edges.entry(edge).or_insert(0) += 1;
+            }
+        }
+        for (&(a, b), &count) in &edges {
+            assert_eq!(count, 1, "edge used twice in the same direction");
+            assert_eq!(edges.get(&(b, a)), Some(&1), "edge without a neighbor");
+        }
+    }
+
+    #[test]
+    fn cylinder_and_cone_are_watertight() {
+        for segments in [3, 8, 33] {
+            assert_watertight(&cylinder(Point3::new(1.0, 2.0, 3.0), 0.5, 2.0, segments, [1.0; 4]));
+            assert_watertight(&cone(Point3::new(-1.0, 0.0, 4.0), 1.5, 3.0, segments, [1.0; 4]));
+        }
+    }
+
+    #[test]
+    fn transforms_move_cube_corners() {
+        let cube = unitcube();
+        let corner = cube
+            .iter()
+            .position(|v| v.loc == [0.5, 0.5, 0.5])
+            .unwrap();
+        let near = |a: [f32; 3], b: [f32; 3]| (Vector3::from(a) - Vector3::from(b)).norm() < 1e-5;
+
+        // a quarter turn around y takes +x to -z, then the cube is moved 10 along x
+        let quarter_turn = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), std::f32::consts::FRAC_PI_2);
+        let isometry = Isometry3::from_parts(Vector3::new(10.0, 0.0, 0.0).into(), quarter_turn);
+        let moved = transform(&cube, &isometry);
+        assert!(near(moved[corner].loc, [10.5, 0.5, -0.5]), "{:?}", moved[corner].loc);
+        assert!(near(rotate(&cube, &quarter_turn)[corner].loc, [0.5, 0.5, -0.5]));
+
+        // normals are turned along with the faces, and stay unit length when stretched
+        let top = cube.iter().position(|v| v.normal == [0.0, 1.0, 0.0]).unwrap();
+        assert!(near(moved[top].normal, [0.0, 1.0, 0.0]));
+        let stretched = scale(&cube, Vector3::new(2.0, 1.0, 3.0));
+        assert!(near(stretched[corner].loc, [1.0, 0.5, 1.5]));
+        assert!(stretched.iter().all(|v| (Vector3::from(v.normal).norm() - 1.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn single_cell_plane_is_two_upward_triangles() {
+        let plane = plane(Point3::new(1.0, 2.0, 3.0), Vector2::new(4.0, 6.0), [1, 1], [1.0; 4]);
+        assert_eq!(plane.len(), 6);
+        assert!(plane.iter().all(|v| v.normal == [0.0, 1.0, 0.0] && v.loc[1] == 2.0));
+        let (min, max) = get_aabb(&plane);
+        assert_eq!((min.x, min.z, max.x, max.z), (-1.0, 0.0, 3.0, 6.0));
+    }
+
+    #[test]
+    fn gradient_is_halfway_at_the_middle() {
+        let start = [1.0, 0.0, 0.0, 1.0];
+        let end = [0.0, 0.0, 1.0, 0.5];
+        // three equal segments, so the middle one is sampled halfway along the line
+        let mesh = flat_polyline_gradient(
+            vec![
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(1.0, 0.0, 0.0),
+                Vector3::new(2.0, 0.0, 0.0),
+                Vector3::new(3.0, 0.0, 0.0),
+            ],
+            0.1,
+            start,
+            end,
+        );
+        let middle = mesh[6].color;
+        for i in 0..4 {
+            assert!((middle[i] - (start[i] + end[i]) / 2.0).abs() < 1e-6, "{middle:?}");
+        }
+        assert!(mesh[6..12].iter().all(|v| v.color == middle));
+    }
 }
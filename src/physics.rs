@@ -0,0 +1,822 @@
+use std::collections::HashMap;
+
+use nalgebra::Isometry3;
+use nalgebra::Point3;
+use nalgebra::Unit;
+use nalgebra::Vector3;
+use rapier3d::crossbeam;
+use rapier3d::dynamics::CCDSolver;
+use rapier3d::dynamics::FixedJointBuilder;
+use rapier3d::dynamics::GenericJoint;
+use rapier3d::dynamics::ImpulseJointHandle;
+use rapier3d::dynamics::ImpulseJointSet;
+use rapier3d::dynamics::IntegrationParameters;
+use rapier3d::dynamics::IslandManager;
+use rapier3d::dynamics::MassProperties;
+use rapier3d::dynamics::MultibodyJointSet;
+use rapier3d::dynamics::RevoluteJointBuilder;
+use rapier3d::dynamics::RigidBodyBuilder;
+use rapier3d::dynamics::RigidBodyHandle;
+use rapier3d::dynamics::RigidBodySet;
+use rapier3d::geometry::ActiveEvents;
+use rapier3d::geometry::ColliderBuilder;
+use rapier3d::geometry::ColliderHandle;
+use rapier3d::geometry::ColliderSet;
+use rapier3d::geometry::CollisionEvent;
+use rapier3d::geometry::NarrowPhase;
+use rapier3d::geometry::Ray;
+use rapier3d::pipeline::ChannelEventCollector;
+use rapier3d::pipeline::PhysicsPipeline;
+use rapier3d::pipeline::QueryFilter;
+use rapier3d::pipeline::QueryPipeline;
+use rapier3d::prelude::DefaultBroadPhase;
+
+use crate::entity::ColliderShape;
+use crate::entity::ContactKind;
+use crate::entity::EntityCreationPhysicsData;
+use crate::entity::GameWorldError;
+use crate::object;
+use crate::vertex::mVertex;
+
+// the most physics ticks a single call to `accumulate` will ask for
+const MAX_TICKS_PER_STEP: u32 = 8;
+
+/// builds a collider of the given shape for a mesh (in the mesh's own coordinates)
+fn collider_builder(
+    shape: &ColliderShape,
+    mesh: &Vec<mVertex>,
+    indices: &Option<Vec<u32>>,
+) -> ColliderBuilder {
+    let points = || -> Vec<Point3<f32>> { mesh.iter().map(|v| v.loc.into()).collect() };
+    match *shape {
+        ColliderShape::Cuboid => {
+            // cuboid constructor uses "half-extents", which is just half of the cuboid's width, height, and depth
+            let hitbox = object::aabb_half_extents(mesh);
+            // the mesh may not be centered on its origin, so move the box to where the mesh actually is
+            let center = object::aabb_center(mesh);
+            ColliderBuilder::cuboid(hitbox.x, hitbox.y, hitbox.z).translation(center.coords)
+        }
+        ColliderShape::Ball { radius } => ColliderBuilder::ball(radius),
+        ColliderShape::Capsule {
+            half_height,
+            radius,
+        } => ColliderBuilder::capsule_y(half_height, radius),
+        // flat or otherwise degenerate meshes have no hull, so they get their bounding box instead
+        ColliderShape::ConvexHull => ColliderBuilder::convex_hull(&points())
+            .unwrap_or_else(|| collider_builder(&ColliderShape::Cuboid, mesh, indices)),
+        ColliderShape::TriMesh => {
+            // unindexed meshes are plain triangle lists
+            let triangles = match indices {
+                Some(indices) => indices
+                    .chunks_exact(3)
+                    .map(|t| [t[0], t[1], t[2]])
+                    .collect(),
+                None => (0..mesh.len() as u32 / 3)
+                    .map(|i| [3 * i, 3 * i + 1, 3 * i + 2])
+                    .collect(),
+            };
+            ColliderBuilder::trimesh(points(), triangles)
+        }
+    }
+}
+
+struct Body {
+    handle: RigidBodyHandle,
+    // pose before the latest physics tick, see `PhysicsWorld::interpolated_isometry`
+    previous_isometry: Isometry3<f32>,
+}
+
+/// the rigid bodies, colliders and joints of a `GameWorld`'s entities, looked up by entity id.
+/// it doesn't touch vulkan, so it can be stepped without a device
+pub struct PhysicsWorld {
+    rigid_body_set: RigidBodySet,
+    collider_set: ColliderSet,
+    physics_pipeline: PhysicsPipeline,
+    island_manager: IslandManager,
+    broad_phase: DefaultBroadPhase,
+    narrow_phase: NarrowPhase,
+    impulse_joint_set: ImpulseJointSet,
+    multibody_joint_set: MultibodyJointSet,
+    ccd_solver: CCDSolver,
+    query_pipeline: QueryPipeline,
+    // the rigid body of each entity with physics
+    bodies: HashMap<u32, Body>,
+    // which entity each collider belongs to
+    collider_entities: HashMap<ColliderHandle, u32>,
+    // the pair of entities each joint connects
+    joint_entities: HashMap<ImpulseJointHandle, (u32, u32)>,
+    integration_parameters: IntegrationParameters,
+    // simulated time that hasn't been consumed by a physics tick yet
+    accumulator: f32,
+    // collisions that started or stopped since `clear_collision_events`
+    collision_events: Vec<(u32, u32, ContactKind)>,
+    // infinite floor that isn't attached to any entity, see `set_ground_plane`
+    ground_collider: Option<ColliderHandle>,
+}
+
+impl PhysicsWorld {
+    pub fn new() -> PhysicsWorld {
+        PhysicsWorld {
+            rigid_body_set: RigidBodySet::new(),
+            collider_set: ColliderSet::new(),
+            physics_pipeline: PhysicsPipeline::new(),
+            island_manager: IslandManager::new(),
+            broad_phase: DefaultBroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            impulse_joint_set: ImpulseJointSet::new(),
+            multibody_joint_set: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            query_pipeline: QueryPipeline::new(),
+            bodies: HashMap::new(),
+            collider_entities: HashMap::new(),
+            joint_entities: HashMap::new(),
+            integration_parameters: IntegrationParameters::default(),
+            accumulator: 0.0,
+            collision_events: vec![],
+            ground_collider: None,
+        }
+    }
+
+    /// creates the rigid body and collider for an entity at `isometry`, replacing any it already had
+    pub fn insert(
+        &mut self,
+        entity_id: u32,
+        physics: &EntityCreationPhysicsData,
+        mesh: &Vec<mVertex>,
+        indices: &Option<Vec<u32>>,
+        isometry: Isometry3<f32>,
+    ) {
+        self.remove(entity_id);
+
+        let [tx, ty, tz] = physics.locked_translations;
+        let [rx, ry, rz] = physics.locked_rotations;
+        let mut rigid_body = match physics.is_dynamic {
+            true => RigidBodyBuilder::dynamic(),
+            false => RigidBodyBuilder::fixed(),
+        }
+        .position(isometry)
+        .enabled_translations(!tx, !ty, !tz)
+        .enabled_rotations(!rx, !ry, !rz);
+
+        let mut collider = collider_builder(&physics.shape, mesh, indices)
+            .friction(physics.friction)
+            .restitution(physics.restitution)
+            .sensor(physics.is_sensor)
+            .active_events(ActiveEvents::COLLISION_EVENTS);
+        if physics.mass.is_some() || physics.center_of_mass.is_some() {
+            // start from what rapier computes from the shape, moved from the collider's frame (which may be offset,
+            // see `collider_builder`) into the body's, and only replace what was given
+            let shape = collider.build();
+            let computed = shape.mass_properties().transform_by(shape.position());
+            let mass = physics.mass.unwrap_or(computed.mass());
+            let inertia_scale = if computed.mass() > 0.0 {
+                mass / computed.mass()
+            } else {
+                1.0
+            };
+            rigid_body = rigid_body.additional_mass_properties(
+                MassProperties::with_principal_inertia_frame(
+                    physics.center_of_mass.unwrap_or(computed.local_com),
+                    mass,
+                    computed.principal_inertia() * inertia_scale,
+                    computed.principal_inertia_local_frame,
+                ),
+            );
+            // so the collider doesn't add its own mass on top
+            collider = collider.density(0.0);
+        }
+        let rigid_body = rigid_body.build();
+        let collider = collider.build();
+
+        let handle = self.rigid_body_set.insert(rigid_body);
+        let collider_handle =
+            self.collider_set
+                .insert_with_parent(collider, handle, &mut self.rigid_body_set);
+        self.collider_entities.insert(collider_handle, entity_id);
+        // so that the new collider can be queried before the next tick
+        self.query_pipeline.update(&self.collider_set);
+
+        self.bodies.insert(
+            entity_id,
+            Body {
+                handle,
+                previous_isometry: isometry,
+            },
+        );
+    }
+
+    /// removes an entity's rigid body, its collider, and any joints connected to it
+    pub fn remove(&mut self, entity_id: u32) {
+        let body = match self.bodies.remove(&entity_id) {
+            Some(body) => body,
+            None => return,
+        };
+
+        let joint_handles: Vec<ImpulseJointHandle> = self
+            .joint_entities
+            .iter()
+            .filter(|(_, &(a, b))| a == entity_id || b == entity_id)
+            .map(|(&joint_handle, _)| joint_handle)
+            .collect();
+        for joint_handle in joint_handles {
+            self.remove_joint(joint_handle);
+        }
+
+        self.rigid_body_set.remove(
+            body.handle,
+            &mut self.island_manager,
+            &mut self.collider_set,
+            &mut self.impulse_joint_set,
+            &mut self.multibody_joint_set,
+            true,
+        );
+        self.collider_entities.retain(|_, &mut id| id != entity_id);
+        self.query_pipeline.update(&self.collider_set);
+    }
+
+    pub fn contains(&self, entity_id: u32) -> bool {
+        self.bodies.contains_key(&entity_id)
+    }
+
+    fn handle(&self, entity_id: u32) -> Result<RigidBodyHandle, GameWorldError> {
+        self.bodies
+            .get(&entity_id)
+            .map(|body| body.handle)
+            .ok_or(GameWorldError::NoRigidBody(entity_id))
+    }
+
+    /// where the entity's rigid body is as of the latest physics tick
+    pub fn isometry(&self, entity_id: u32) -> Option<Isometry3<f32>> {
+        let handle = self.handle(entity_id).ok()?;
+        Some(*self.rigid_body_set[handle].position())
+    }
+
+    /// where the entity's rigid body was between the last two physics ticks,
+    /// according to how much time is left over in the accumulator
+    pub fn interpolated_isometry(&self, entity_id: u32) -> Option<Isometry3<f32>> {
+        let body = self.bodies.get(&entity_id)?;
+        let alpha = (self.accumulator / self.integration_parameters.dt).clamp(0.0, 1.0);
+        Some(
+            body.previous_isometry
+                .lerp_slerp(self.rigid_body_set[body.handle].position(), alpha),
+        )
+    }
+
+    /// teleports the entity's rigid body to `isometry` and stops it
+    pub fn set_isometry(
+        &mut self,
+        entity_id: u32,
+        isometry: Isometry3<f32>,
+    ) -> Result<(), GameWorldError> {
+        let body = self
+            .bodies
+            .get_mut(&entity_id)
+            .ok_or(GameWorldError::NoRigidBody(entity_id))?;
+        let rigid_body = &mut self.rigid_body_set[body.handle];
+        rigid_body.set_position(isometry, true);
+        rigid_body.set_linvel(Vector3::zeros(), true);
+        rigid_body.set_angvel(Vector3::zeros(), true);
+        // don't interpolate from where the entity was before it was teleported
+        body.previous_isometry = isometry;
+        Ok(())
+    }
+
+    pub fn linear_velocity(&self, entity_id: u32) -> Result<Vector3<f32>, GameWorldError> {
+        let handle = self.handle(entity_id)?;
+        Ok(*self.rigid_body_set[handle].linvel())
+    }
+
+    pub fn angular_velocity(&self, entity_id: u32) -> Result<Vector3<f32>, GameWorldError> {
+        let handle = self.handle(entity_id)?;
+        Ok(*self.rigid_body_set[handle].angvel())
+    }
+
+    pub fn set_linear_velocity(
+        &mut self,
+        entity_id: u32,
+        v: Vector3<f32>,
+    ) -> Result<(), GameWorldError> {
+        let handle = self.handle(entity_id)?;
+        self.rigid_body_set[handle].set_linvel(v, true);
+        Ok(())
+    }
+
+    pub fn set_angular_velocity(
+        &mut self,
+        entity_id: u32,
+        v: Vector3<f32>,
+    ) -> Result<(), GameWorldError> {
+        let handle = self.handle(entity_id)?;
+        self.rigid_body_set[handle].set_angvel(v, true);
+        Ok(())
+    }
+
+    pub fn apply_impulse(
+        &mut self,
+        entity_id: u32,
+        impulse: Vector3<f32>,
+        wake: bool,
+    ) -> Result<(), GameWorldError> {
+        let handle = self.handle(entity_id)?;
+        self.rigid_body_set[handle].apply_impulse(impulse, wake);
+        Ok(())
+    }
+
+    pub fn apply_force(
+        &mut self,
+        entity_id: u32,
+        force: Vector3<f32>,
+    ) -> Result<(), GameWorldError> {
+        let handle = self.handle(entity_id)?;
+        self.rigid_body_set[handle].add_force(force, true);
+        Ok(())
+    }
+
+    pub fn reset_forces(&mut self, entity_id: u32) -> Result<(), GameWorldError> {
+        let handle = self.handle(entity_id)?;
+        self.rigid_body_set[handle].reset_forces(true);
+        Ok(())
+    }
+
+    pub fn apply_torque_impulse(
+        &mut self,
+        entity_id: u32,
+        torque: Vector3<f32>,
+    ) -> Result<(), GameWorldError> {
+        let handle = self.handle(entity_id)?;
+        self.rigid_body_set[handle].apply_torque_impulse(torque, true);
+        Ok(())
+    }
+
+    /// casts a ray from `origin` along `dir`, and returns the first entity hit along with the time of impact
+    pub fn cast_ray(
+        &self,
+        origin: Point3<f32>,
+        dir: Vector3<f32>,
+        max_toi: f32,
+    ) -> Option<(u32, f32)> {
+        let ray = Ray::new(origin, dir);
+        self.query_pipeline
+            .cast_ray(
+                &self.rigid_body_set,
+                &self.collider_set,
+                &ray,
+                max_toi,
+                true,
+                // the ground plane doesn't belong to any entity
+                QueryFilter::default()
+                    .predicate(&|collider_handle, _| self.collider_entities.contains_key(&collider_handle)),
+            )
+            // colliders removed since the last tick may still be in the query pipeline
+            .and_then(|(collider_handle, toi)| {
+                Some((*self.collider_entities.get(&collider_handle)?, toi))
+            })
+    }
+
+    fn add_joint(
+        &mut self,
+        a: u32,
+        b: u32,
+        joint: impl Into<GenericJoint>,
+    ) -> Result<ImpulseJointHandle, GameWorldError> {
+        let handle_a = self.handle(a)?;
+        let handle_b = self.handle(b)?;
+        let joint_handle = self
+            .impulse_joint_set
+            .insert(handle_a, handle_b, joint, true);
+        self.joint_entities.insert(joint_handle, (a, b));
+        Ok(joint_handle)
+    }
+
+    pub fn add_revolute_joint(
+        &mut self,
+        a: u32,
+        b: u32,
+        anchor_a: Point3<f32>,
+        anchor_b: Point3<f32>,
+        axis: Vector3<f32>,
+    ) -> Result<ImpulseJointHandle, GameWorldError> {
+        let joint = RevoluteJointBuilder::new(Unit::new_normalize(axis))
+            .local_anchor1(anchor_a)
+            .local_anchor2(anchor_b);
+        self.add_joint(a, b, joint)
+    }
+
+    pub fn add_fixed_joint(
+        &mut self,
+        a: u32,
+        b: u32,
+        anchor_a: Point3<f32>,
+        anchor_b: Point3<f32>,
+    ) -> Result<ImpulseJointHandle, GameWorldError> {
+        let joint = FixedJointBuilder::new()
+            .local_anchor1(anchor_a)
+            .local_anchor2(anchor_b);
+        self.add_joint(a, b, joint)
+    }
+
+    pub fn remove_joint(&mut self, joint_handle: ImpulseJointHandle) {
+        self.joint_entities.remove(&joint_handle);
+        self.impulse_joint_set.remove(joint_handle, true);
+    }
+
+    /// puts an infinite, fixed floor at height `y`, replacing any previous one
+    pub fn set_ground_plane(&mut self, y: f32) {
+        self.clear_ground_plane();
+        let collider = ColliderBuilder::halfspace(Vector3::y_axis())
+            .translation(Vector3::new(0.0, y, 0.0))
+            .build();
+        self.ground_collider = Some(self.collider_set.insert(collider));
+        self.query_pipeline.update(&self.collider_set);
+    }
+
+    /// removes the floor added by `set_ground_plane`, if any
+    pub fn clear_ground_plane(&mut self) {
+        if let Some(ground_collider) = self.ground_collider.take() {
+            self.collider_set.remove(
+                ground_collider,
+                &mut self.island_manager,
+                &mut self.rigid_body_set,
+                true,
+            );
+            self.query_pipeline.update(&self.collider_set);
+        }
+    }
+
+    /// sets the length of a physics tick, in seconds
+    pub fn set_timestep(&mut self, dt: f32) {
+        assert!(dt > 0.0, "timestep must be positive");
+        self.integration_parameters.dt = dt;
+    }
+
+    /// adds `real_dt` seconds to the accumulator, and takes out as many whole ticks as fit (the remainder is carried
+    /// over to the next call). returns how many ticks the caller should run
+    pub fn accumulate(&mut self, real_dt: f32) -> u32 {
+        self.accumulator += real_dt;
+        let mut ticks = 0;
+        while self.accumulator >= self.integration_parameters.dt {
+            // if we fall too far behind, drop the extra time rather than spiraling
+            if ticks == MAX_TICKS_PER_STEP {
+                self.accumulator = 0.0;
+                break;
+            }
+            self.accumulator -= self.integration_parameters.dt;
+            ticks += 1;
+        }
+        ticks
+    }
+
+    /// forgets the time left over in the accumulator and the collision events so far, e.g. after loading a snapshot
+    pub fn reset_time(&mut self) {
+        self.accumulator = 0.0;
+        self.collision_events.clear();
+    }
+
+    /// advances the simulation by exactly one tick, adding the collisions that started or stopped to `collision_events`
+    pub fn tick(&mut self) {
+        // remember where everything was, to interpolate from
+        for body in self.bodies.values_mut() {
+            body.previous_isometry = *self.rigid_body_set[body.handle].position();
+        }
+
+        // collect collision events while stepping
+        let (collision_send, collision_recv) = crossbeam::channel::unbounded();
+        let (contact_force_send, _) = crossbeam::channel::unbounded();
+        let event_handler = ChannelEventCollector::new(collision_send, contact_force_send);
+
+        // step physics
+        self.physics_pipeline.step(
+            &Vector3::new(0.0, -9.81, 0.0),
+            &self.integration_parameters,
+            &mut self.island_manager,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.rigid_body_set,
+            &mut self.collider_set,
+            &mut self.impulse_joint_set,
+            &mut self.multibody_joint_set,
+            &mut self.ccd_solver,
+            Some(&mut self.query_pipeline),
+            &(),
+            &event_handler,
+        );
+
+        // translate collision events to entity ids. colliders removed since the last tick are skipped.
+        // sensors are reported separately through `intersections`
+        let collision_events = collision_recv
+            .try_iter()
+            .filter(|event| !event.sensor())
+            .filter_map(|event| {
+                let kind = match event {
+                    CollisionEvent::Started(..) => ContactKind::Started,
+                    CollisionEvent::Stopped(..) => ContactKind::Stopped,
+                };
+                let a = self.collider_entities.get(&event.collider1())?;
+                let b = self.collider_entities.get(&event.collider2())?;
+                Some((*a, *b, kind))
+            });
+        self.collision_events.extend(collision_events);
+    }
+
+    /// the pairs of entities that started or stopped touching since `clear_collision_events`
+    pub fn collision_events(&self) -> &[(u32, u32, ContactKind)] {
+        &self.collision_events
+    }
+
+    pub fn clear_collision_events(&mut self) {
+        self.collision_events.clear();
+    }
+
+    /// the pairs of entities where one is a sensor that the other overlaps, as of the latest tick
+    pub fn intersections(&self) -> Vec<(u32, u32)> {
+        self.narrow_phase
+            .intersection_pairs()
+            .filter(|&(_, _, intersecting)| intersecting)
+            .filter_map(|(collider1, collider2, _)| {
+                let a = self.collider_entities.get(&collider1)?;
+                let b = self.collider_entities.get(&collider2)?;
+                Some((*a, *b))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // a unit cube at `position`, with the given physics
+    fn add_cube(
+        world: &mut PhysicsWorld,
+        entity_id: u32,
+        position: Vector3<f32>,
+        physics: EntityCreationPhysicsData,
+    ) {
+        world.insert(
+            entity_id,
+            &physics,
+            &object::unitcube(),
+            &None,
+            Isometry3::translation(position.x, position.y, position.z),
+        );
+    }
+
+    fn dynamic() -> EntityCreationPhysicsData {
+        EntityCreationPhysicsData {
+            is_dynamic: true,
+            ..Default::default()
+        }
+    }
+
+    // the highest a cube dropped onto a fixed floor gets after its first bounce
+    fn rebound_height(restitution: f32) -> f32 {
+        let mut world = PhysicsWorld::new();
+        add_cube(
+            &mut world,
+            0,
+            Vector3::new(0.0, -0.5, 0.0),
+            EntityCreationPhysicsData {
+                restitution,
+                ..Default::default()
+            },
+        );
+        add_cube(
+            &mut world,
+            1,
+            Vector3::new(0.0, 3.0, 0.0),
+            EntityCreationPhysicsData {
+                restitution,
+                ..dynamic()
+            },
+        );
+        // after the cube first touches the floor, see how far back up it gets
+        let mut landed = false;
+        let mut highest = f32::MIN;
+        for _ in 0..120 {
+            world.tick();
+            let y = world.isometry(1).unwrap().translation.y;
+            landed |= y < 0.6;
+            if landed {
+                highest = highest.max(y);
+            }
+        }
+        assert!(landed, "cube never reached the floor");
+        highest
+    }
+
+    #[test]
+    fn cube_comes_to_rest_on_ground_plane() {
+        let mut world = PhysicsWorld::new();
+        world.set_ground_plane(1.0);
+        add_cube(&mut world, 0, Vector3::new(0.0, 4.0, 0.0), dynamic());
+        for _ in 0..300 {
+            world.tick();
+        }
+        // the cube's bottom face lies on the plane
+        let y = world.isometry(0).unwrap().translation.y;
+        assert!((y - 1.5).abs() < 0.05, "cube came to rest at y = {y}");
+        assert!(world.linear_velocity(0).unwrap().norm() < 0.01);
+    }
+
+    #[test]
+    fn ray_hits_nearest_cube() {
+        let mut world = PhysicsWorld::new();
+        add_cube(&mut world, 7, Vector3::new(5.0, 0.0, 0.0), Default::default());
+        add_cube(&mut world, 8, Vector3::new(10.0, 0.0, 0.0), Default::default());
+        let right = Vector3::new(1.0, 0.0, 0.0);
+        let (entity_id, toi) = world.cast_ray(Point3::origin(), right, 100.0).unwrap();
+        assert_eq!(entity_id, 7);
+        assert!((toi - 4.5).abs() < 1e-4);
+        // too short to reach the first cube
+        assert_eq!(world.cast_ray(Point3::origin(), right, 4.0), None);
+        world.remove(7);
+        let (entity_id, toi) = world.cast_ray(Point3::origin(), right, 100.0).unwrap();
+        assert_eq!(entity_id, 8);
+        assert!((toi - 9.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rays_pass_through_ground_plane() {
+        let mut world = PhysicsWorld::new();
+        world.set_ground_plane(0.0);
+        let down = Vector3::new(0.0, -1.0, 0.0);
+        assert_eq!(world.cast_ray(Point3::new(0.0, 5.0, 0.0), down, 100.0), None);
+        // a cube buried under the plane is still found
+        add_cube(&mut world, 0, Vector3::new(0.0, -3.0, 0.0), Default::default());
+        let (entity_id, toi) = world.cast_ray(Point3::new(0.0, 5.0, 0.0), down, 100.0).unwrap();
+        assert_eq!(entity_id, 0);
+        assert!((toi - 7.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn interpolates_between_last_two_ticks() {
+        let mut world = PhysicsWorld::new();
+        world.set_timestep(0.1);
+        add_cube(&mut world, 0, Vector3::new(0.0, 10.0, 0.0), dynamic());
+        assert_eq!(world.interpolated_isometry(0), world.isometry(0));
+
+        // one whole tick, with half a tick left over
+        let ticks = world.accumulate(0.15);
+        assert_eq!(ticks, 1);
+        world.tick();
+        let current = world.isometry(0).unwrap().translation.y;
+        assert!(current < 10.0);
+        let interpolated = world.interpolated_isometry(0).unwrap().translation.y;
+        let halfway = (10.0 + current) / 2.0;
+        assert!((interpolated - halfway).abs() < 1e-3, "expected {halfway}, got {interpolated}");
+
+        // teleporting doesn't leave a trail to interpolate along
+        let teleported = Isometry3::translation(3.0, 20.0, 0.0);
+        world.set_isometry(0, teleported).unwrap();
+        let interpolated = world.interpolated_isometry(0).unwrap();
+        assert!((interpolated.translation.vector - teleported.translation.vector).norm() < 1e-5);
+    }
+
+    // how far along x the body's origin moves when it's spun around z, with its center of mass at `center_of_mass`
+    fn origin_drift(center_of_mass: Point3<f32>) -> f32 {
+        let mut world = PhysicsWorld::new();
+        // the mesh is off center, so its collider is offset from the body
+        let mesh = object::cuboid(Point3::new(0.0, 2.0, 0.0), Vector3::new(1.0, 1.0, 1.0));
+        world.insert(
+            0,
+            &EntityCreationPhysicsData {
+                center_of_mass: Some(center_of_mass),
+                ..dynamic()
+            },
+            &mesh,
+            &None,
+            Isometry3::identity(),
+        );
+        world
+            .apply_torque_impulse(0, Vector3::new(0.0, 0.0, 0.1))
+            .unwrap();
+        for _ in 0..10 {
+            world.tick();
+        }
+        world.isometry(0).unwrap().translation.x
+    }
+
+    #[test]
+    fn center_of_mass_is_in_body_frame() {
+        // bodies turn about their center of mass, so the origin swings the opposite way when it's below it
+        let high = origin_drift(Point3::new(0.0, 1.0, 0.0));
+        let low = origin_drift(Point3::new(0.0, -1.0, 0.0));
+        assert!(high > 0.0, "origin below the center of mass moved {high}");
+        assert!(low < 0.0, "origin above the center of mass moved {low}");
+        // and stays put when it's the center of mass itself
+        assert!(origin_drift(Point3::origin()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn flat_convex_hull_falls_back_to_box() {
+        let mut world = PhysicsWorld::new();
+        let mesh = object::plane(
+            Point3::origin(),
+            nalgebra::Vector2::new(2.0, 2.0),
+            [1, 1],
+            [1.0; 4],
+        );
+        world.insert(
+            0,
+            &EntityCreationPhysicsData {
+                shape: ColliderShape::ConvexHull,
+                ..Default::default()
+            },
+            &mesh,
+            &None,
+            Isometry3::identity(),
+        );
+        let hit = world.cast_ray(Point3::new(0.5, 5.0, 0.5), Vector3::new(0.0, -1.0, 0.0), 10.0);
+        assert_eq!(hit.map(|(entity_id, _)| entity_id), Some(0));
+    }
+
+    #[test]
+    fn reports_contacts_starting_and_stopping() {
+        let mut world = PhysicsWorld::new();
+        add_cube(&mut world, 1, Vector3::new(0.0, -0.5, 0.0), Default::default());
+        add_cube(&mut world, 2, Vector3::new(0.0, 1.0, 0.0), dynamic());
+        let touching = |world: &PhysicsWorld, kind| {
+            world
+                .collision_events()
+                .iter()
+                .any(|&(a, b, k)| k == kind && [a.min(b), a.max(b)] == [1, 2])
+        };
+        for _ in 0..60 {
+            world.tick();
+        }
+        assert!(touching(&world, ContactKind::Started));
+
+        world.clear_collision_events();
+        world
+            .set_isometry(2, Isometry3::translation(0.0, 10.0, 0.0))
+            .unwrap();
+        world.tick();
+        assert!(touching(&world, ContactKind::Stopped));
+    }
+
+    #[test]
+    fn sensors_report_overlaps_without_pushing() {
+        let mut world = PhysicsWorld::new();
+        world.set_ground_plane(0.0);
+        add_cube(
+            &mut world,
+            1,
+            Vector3::new(0.3, 0.5, 0.0),
+            EntityCreationPhysicsData {
+                is_sensor: true,
+                ..Default::default()
+            },
+        );
+        add_cube(&mut world, 2, Vector3::new(0.0, 0.5, 0.0), dynamic());
+        for _ in 0..10 {
+            world.tick();
+        }
+        let pairs: Vec<[u32; 2]> = world
+            .intersections()
+            .iter()
+            .map(|&(a, b)| [a.min(b), a.max(b)])
+            .collect();
+        assert_eq!(pairs, vec![[1, 2]]);
+        // a solid cube there would have pushed it aside
+        assert!(world.isometry(2).unwrap().translation.x.abs() < 1e-3);
+        // and overlaps aren't contacts
+        assert!(world.collision_events().iter().all(|&(a, b, _)| a != 1 && b != 1));
+    }
+
+    #[test]
+    fn locked_axes_stay_put() {
+        let mut world = PhysicsWorld::new();
+        add_cube(
+            &mut world,
+            0,
+            Vector3::new(0.0, 5.0, 0.0),
+            EntityCreationPhysicsData {
+                locked_translations: [false, true, false],
+                locked_rotations: [true, false, true],
+                ..dynamic()
+            },
+        );
+        world.set_linear_velocity(0, Vector3::new(1.0, 0.0, 0.0)).unwrap();
+        world
+            .apply_torque_impulse(0, Vector3::new(1.0, 0.0, 1.0))
+            .unwrap();
+        for _ in 0..60 {
+            world.tick();
+        }
+        let isometry = world.isometry(0).unwrap();
+        // gravity doesn't pull it down, but it still slides along x
+        assert!((isometry.translation.y - 5.0).abs() < 1e-5);
+        assert!(isometry.translation.x > 0.5);
+        assert!(isometry.rotation.angle() < 1e-5);
+    }
+
+    #[test]
+    fn restitution_makes_cubes_rebound() {
+        let bouncy = rebound_height(1.0);
+        let dull = rebound_height(0.0);
+        // the cube rests at y = 0.5 on the floor, and a perfectly elastic one comes back most of the way up
+        assert!(bouncy > 2.0, "bouncy cube only got back up to {bouncy}");
+        assert!(dull < 0.6, "inelastic cube bounced up to {dull}");
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use vulkano::{
+    device::Device,
+    image::SampleCount,
+    pipeline::{
+        cache::PipelineCache,
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::VertexInputState,
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        DynamicState, GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{RenderPass, Subpass},
+    shader::EntryPoint,
+};
+
+use crate::render_system::error::RendererError;
+
+/// builds the pipeline for a background (e.g. a skybox), drawn before the scene as a single fullscreen triangle.
+/// the vertex shader takes no vertex input, and must output the triangle's corners from `gl_VertexIndex`.
+/// the background doesn't read or write depth, so everything drawn after it ends up in front
+pub(crate) fn create_background_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<RenderPass>,
+    stages: Vec<EntryPoint>,
+    viewport_extent: Option<[u32; 2]>,
+    samples: SampleCount,
+    pipeline_cache: Option<Arc<PipelineCache>>,
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    let stages: Vec<_> = stages
+        .into_iter()
+        .map(PipelineShaderStageCreateInfo::new)
+        .collect();
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())?,
+    )?;
+    let subpass = Subpass::from(render_pass, 0).unwrap();
+
+    let pipeline = GraphicsPipeline::new(
+        device,
+        pipeline_cache,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(VertexInputState::default()),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(match viewport_extent {
+                Some(extent) => ViewportState {
+                    viewports: [Viewport {
+                        offset: [0.0, 0.0],
+                        extent: [extent[0] as f32, extent[1] as f32],
+                        depth_range: 0.0..=1.0,
+                    }]
+                    .into_iter()
+                    .collect(),
+                    ..Default::default()
+                },
+                None => ViewportState::default(),
+            }),
+            rasterization_state: Some(RasterizationState::default()),
+            // no depth state, so the depth test and depth writes are disabled
+            depth_stencil_state: None,
+            multisample_state: Some(MultisampleState {
+                rasterization_samples: samples,
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
+            // without an extent, the viewport is set in the command buffer like the renderer's other pipelines
+            dynamic_state: match viewport_extent {
+                Some(_) => Default::default(),
+                None => [DynamicState::Viewport].into_iter().collect(),
+            },
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )?;
+    Ok(pipeline)
+}
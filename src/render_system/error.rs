@@ -0,0 +1,111 @@
+use vulkano::{
+    buffer::AllocateBufferError, format::Format, image::AllocateImageError,
+    pipeline::layout::IntoPipelineLayoutCreateInfoError, Validated, ValidationError, VulkanError,
+};
+
+/// errors that can occur while creating a renderer's swapchain, images, render pass or pipeline
+#[derive(Debug)]
+pub enum RendererError {
+    // a vulkan call failed or was given invalid arguments
+    Vulkan(Validated<VulkanError>),
+    // a render target or depth image couldn't be allocated
+    AllocateImage(Validated<AllocateImageError>),
+    // a staging buffer couldn't be allocated
+    AllocateBuffer(Validated<AllocateBufferError>),
+    // the vertex type doesn't match the inputs of the vertex shader
+    VertexDefinition(Box<ValidationError>),
+    // the descriptor set layouts of the shader stages couldn't be combined
+    PipelineLayout(IntoPipelineLayoutCreateInfoError),
+    // the surface doesn't support any image formats
+    NoSurfaceFormat,
+    // frames can't be recorded because the surface doesn't allow copying from swapchain images
+    SwapchainNotReadable,
+    // frames can't be recorded because the swapchain format isn't 8 bit RGBA or BGRA
+    UnreadableSwapchainFormat(Format),
+    // the device can't render to images of this format, or copy them to a buffer
+    UnsupportedFormat(Format),
+}
+
+impl std::fmt::Display for RendererError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RendererError::Vulkan(e) => write!(f, "vulkan error: {e}"),
+            RendererError::AllocateImage(e) => write!(f, "failed to allocate image: {e}"),
+            RendererError::AllocateBuffer(e) => write!(f, "failed to allocate buffer: {e}"),
+            RendererError::VertexDefinition(e) => {
+                write!(f, "vertex type does not match the vertex shader: {e}")
+            }
+            RendererError::PipelineLayout(e) => write!(f, "failed to create pipeline layout: {e}"),
+            RendererError::NoSurfaceFormat => write!(f, "the surface supports no image formats"),
+            RendererError::SwapchainNotReadable => {
+                write!(f, "the surface doesn't support copying from swapchain images")
+            }
+            RendererError::UnreadableSwapchainFormat(format) => {
+                write!(f, "can't read back swapchain images with format {format:?}")
+            }
+            RendererError::UnsupportedFormat(format) => {
+                write!(f, "can't render to and read back images with format {format:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RendererError {}
+
+impl From<Validated<VulkanError>> for RendererError {
+    fn from(e: Validated<VulkanError>) -> Self {
+        RendererError::Vulkan(e)
+    }
+}
+
+impl From<Validated<AllocateImageError>> for RendererError {
+    fn from(e: Validated<AllocateImageError>) -> Self {
+        RendererError::AllocateImage(e)
+    }
+}
+
+impl From<Validated<AllocateBufferError>> for RendererError {
+    fn from(e: Validated<AllocateBufferError>) -> Self {
+        RendererError::AllocateBuffer(e)
+    }
+}
+
+impl From<Box<ValidationError>> for RendererError {
+    fn from(e: Box<ValidationError>) -> Self {
+        RendererError::VertexDefinition(e)
+    }
+}
+
+impl From<IntoPipelineLayoutCreateInfoError> for RendererError {
+    fn from(e: IntoPipelineLayoutCreateInfoError) -> Self {
+        RendererError::PipelineLayout(e)
+    }
+}
+
+/// errors that can occur while picking a physical device and creating the logical device
+#[derive(Debug)]
+pub enum DeviceSelectionError {
+    // the physical devices couldn't be listed
+    Enumerate(VulkanError),
+    // no physical device supports the required extensions and has a graphics queue that can present to the surface
+    NoSuitableDevice,
+    // the logical device couldn't be created
+    DeviceCreation(Validated<VulkanError>),
+}
+
+impl std::fmt::Display for DeviceSelectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceSelectionError::Enumerate(e) => {
+                write!(f, "failed to enumerate physical devices: {e}")
+            }
+            DeviceSelectionError::NoSuitableDevice => write!(
+                f,
+                "no physical device supports the required extensions and can present to the surface"
+            ),
+            DeviceSelectionError::DeviceCreation(e) => write!(f, "failed to create device: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for DeviceSelectionError {}
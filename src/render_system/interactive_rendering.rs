@@ -1,55 +1,78 @@
-use std::sync::Arc;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use image::RgbaImage;
 
 use vulkano::{
-    buffer::{BufferContents, Subbuffer},
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
     command_buffer::{
         allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
-        RenderPassBeginInfo,
+        CopyImageToBufferInfo, RenderPassBeginInfo,
     },
+    descriptor_set::PersistentDescriptorSet,
     device::{
         physical::PhysicalDeviceType, Device, DeviceCreateInfo, DeviceExtensions, DeviceOwned,
-        Queue, QueueCreateInfo, QueueFlags,
+        Features, Queue, QueueCreateInfo, QueueFlags,
     },
     format::Format,
-    image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage},
+    image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount},
     instance::Instance,
-    memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
     pipeline::{
+        cache::PipelineCache,
         graphics::{
-            color_blend::{ColorBlendAttachmentState, ColorBlendState},
-            depth_stencil::{DepthState, DepthStencilState},
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
             input_assembly::InputAssemblyState,
             multisample::MultisampleState,
-            rasterization::RasterizationState,
+            rasterization::{PolygonMode, RasterizationState},
             vertex_input::{Vertex, VertexBufferDescription, VertexDefinition},
             viewport::{Viewport, ViewportState},
             GraphicsPipelineCreateInfo,
         },
         layout::PipelineDescriptorSetLayoutCreateInfo,
-        GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+        DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
     },
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
     shader::{spirv::ExecutionModel, EntryPoint},
-    swapchain::{self, Surface, Swapchain, SwapchainCreateInfo, SwapchainPresentInfo},
+    swapchain::{self, PresentMode, Surface, Swapchain, SwapchainCreateInfo, SwapchainPresentInfo},
     sync::{self, GpuFuture},
     Validated, VulkanError,
 };
-use winit::window::Window;
+use winit::window::{CursorGrabMode, Window};
+
+use crate::render_system::background::create_background_pipeline;
+use crate::render_system::error::{DeviceSelectionError, RendererError};
+use crate::render_system::scene::{identity_instance_buffer, DrawBuffers, InstanceData};
 
+/// returns the device, a graphics queue that can present to `surface`, and a queue for transfers.
+/// the transfer queue comes from a separate queue family when the device has one, otherwise it is the graphics queue
 pub fn get_device_for_rendering_on(
     instance: Arc<Instance>,
     surface: Arc<Surface>,
-) -> (Arc<Device>, Arc<Queue>) {
+) -> Result<(Arc<Device>, Arc<Queue>, Arc<Queue>), DeviceSelectionError> {
     let device_extensions = DeviceExtensions {
         khr_swapchain: true,
         ..DeviceExtensions::empty()
     };
+    get_device(instance, device_extensions, Some(&surface))
+}
 
+/// like `get_device_for_rendering_on`, but with the given extensions enabled.
+/// with no `surface`, the graphics queue doesn't have to be able to present, e.g. for offscreen rendering only
+pub fn get_device(
+    instance: Arc<Instance>,
+    device_extensions: DeviceExtensions,
+    surface: Option<&Surface>,
+) -> Result<(Arc<Device>, Arc<Queue>, Arc<Queue>), DeviceSelectionError> {
     // We then choose which physical device to use. First, we enumerate all the available physical
     // devices, then apply filters to narrow them down to those that can support our needs.
     let (physical_device, queue_family_index) = instance
         .enumerate_physical_devices()
-        .unwrap()
+        .map_err(DeviceSelectionError::Enumerate)?
         .filter(|p| {
             // Some devices may not support the extensions or features that your application, or
             // report properties and limits that are not sufficient for your application. These
@@ -77,7 +100,9 @@ pub fn get_device_for_rendering_on(
                     // a window surface, as we do in this example, we also need to check that queues
                     // in this queue family are capable of presenting images to the surface.
                     q.queue_flags.intersects(QueueFlags::GRAPHICS)
-                        && p.surface_support(i as u32, &surface).unwrap_or(false)
+                        && surface.map_or(true, |surface| {
+                            p.surface_support(i as u32, surface).unwrap_or(false)
+                        })
                 })
                 // The code here searches for the first queue family that is suitable. If none is
                 // found, `None` is returned to `filter_map`, which disqualifies this physical
@@ -103,85 +128,237 @@ pub fn get_device_for_rendering_on(
                 _ => 5,
             }
         })
-        .expect("No suitable physical device found");
+        .ok_or(DeviceSelectionError::NoSuitableDevice)?;
+
+    // wireframe rendering needs `fill_mode_non_solid`, but it's optional: renderers fall back to filled polygons without it
+    let device_features = Features {
+        fill_mode_non_solid: physical_device.supported_features().fill_mode_non_solid,
+        ..Features::empty()
+    };
+
+    // look for a queue family dedicated to transfers, so that image readback doesn't have to wait behind draws
+    let transfer_queue_family_index = physical_device
+        .queue_family_properties()
+        .iter()
+        .position(|q| {
+            q.queue_flags.intersects(QueueFlags::TRANSFER)
+                && !q.queue_flags.intersects(QueueFlags::GRAPHICS)
+        })
+        .map(|i| i as u32)
+        .filter(|&i| i != queue_family_index);
+
+    let mut queue_create_infos = vec![QueueCreateInfo {
+        queue_family_index,
+        ..Default::default()
+    }];
+    if let Some(transfer_queue_family_index) = transfer_queue_family_index {
+        queue_create_infos.push(QueueCreateInfo {
+            queue_family_index: transfer_queue_family_index,
+            ..Default::default()
+        });
+    }
 
     let (device, mut queues) = Device::new(
         physical_device,
         DeviceCreateInfo {
             enabled_extensions: device_extensions,
-            queue_create_infos: vec![QueueCreateInfo {
-                queue_family_index,
-                ..Default::default()
-            }],
+            enabled_features: device_features,
+            queue_create_infos,
             ..Default::default()
         },
     )
-    .unwrap();
+    .map_err(DeviceSelectionError::DeviceCreation)?;
 
     let queue = queues.next().unwrap();
+    // fall back to the graphics queue if there's no separate transfer family
+    let transfer_queue = queues.next().unwrap_or_else(|| queue.clone());
+
+    Ok((device, queue, transfer_queue))
+}
 
-    (device, queue)
+/// like `get_device_for_rendering_on`, but panics with the reason if no device could be created
+pub fn get_device_or_panic(
+    instance: Arc<Instance>,
+    surface: Arc<Surface>,
+) -> (Arc<Device>, Arc<Queue>, Arc<Queue>) {
+    match get_device_for_rendering_on(instance, surface) {
+        Ok(r) => r,
+        Err(e) => panic!("{e}"),
+    }
+}
+
+/// returns `samples` if the device can render color and depth with that many samples, otherwise 1
+fn supported_sample_count(device: &Device, samples: SampleCount) -> SampleCount {
+    let properties = device.physical_device().properties();
+    let supported =
+        properties.framebuffer_color_sample_counts & properties.framebuffer_depth_sample_counts;
+    if supported.contains_enum(samples) {
+        samples
+    } else {
+        println!("{samples:?} is not supported, falling back to SampleCount::Sample1");
+        SampleCount::Sample1
+    }
+}
+
+/// with multisampling, we render to a multisampled image that is resolved into the swapchain image at the end of the pass
+fn create_render_pass(device: Arc<Device>, format: Format, samples: SampleCount) -> Result<Arc<RenderPass>, RendererError> {
+    let render_pass = match samples {
+        SampleCount::Sample1 => vulkano::single_pass_renderpass!(
+            device,
+            attachments: {
+                color: {
+                    format: format,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+                depth_stencil: {
+                    format: Format::D32_SFLOAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {depth_stencil},
+            },
+        )?,
+        samples => vulkano::single_pass_renderpass!(
+            device,
+            attachments: {
+                intermediary: {
+                    format: format,
+                    samples: samples,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+                color: {
+                    format: format,
+                    samples: 1,
+                    load_op: DontCare,
+                    store_op: Store,
+                },
+                depth_stencil: {
+                    format: Format::D32_SFLOAT,
+                    samples: samples,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+            },
+            pass: {
+                color: [intermediary],
+                color_resolve: [color],
+                depth_stencil: {depth_stencil},
+            },
+        )?,
+    };
+    Ok(render_pass)
+}
+
+/// the viewport covering all of a render target of the given extent
+fn full_viewport(extent: [u32; 2]) -> Viewport {
+    Viewport {
+        offset: [0.0, 0.0],
+        extent: [extent[0] as f32, extent[1] as f32],
+        depth_range: 0.0..=1.0,
+    }
 }
 
-/// This function is called once during initialization, then again whenever the window is resized.
-fn window_size_dependent_setup(
+/// the depth buffer, multisampled image (if any) and framebuffers for the swapchain images.
+/// these have to be recreated whenever the window is resized
+fn create_framebuffers(
     memory_allocator: Arc<StandardMemoryAllocator>,
     images: &[Arc<Image>],
     render_pass: Arc<RenderPass>,
-    stages: Vec<EntryPoint>,
-    vertex_buffer_descriptions: &[VertexBufferDescription],
-) -> (Arc<GraphicsPipeline>, Vec<Arc<Framebuffer>>) {
-    // validate stages
-    assert!(stages.len() > 0, "no shader stages provided");
-    assert!(
-        stages[0].info().execution_model == ExecutionModel::Vertex,
-        "first shader stage must be vertex shader"
-    );
-
-    let device = memory_allocator.device().clone();
-    let extent = images[0].extent();
-
+    samples: SampleCount,
+) -> Result<Vec<Arc<Framebuffer>>, RendererError> {
     let depth_buffer = ImageView::new_default(
         Image::new(
-            memory_allocator,
+            memory_allocator.clone(),
             ImageCreateInfo {
                 image_type: ImageType::Dim2d,
                 format: Format::D32_SFLOAT,
                 extent: images[0].extent(),
                 usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+                samples,
                 ..Default::default()
             },
             AllocationCreateInfo::default(),
-        )
-        .unwrap(),
-    )
-    .unwrap();
+        )?,
+    )?;
+
+    // the multisampled image that gets resolved into the swapchain image
+    let intermediary = match samples {
+        SampleCount::Sample1 => None,
+        samples => Some(
+            ImageView::new_default(
+                Image::new(
+                    memory_allocator,
+                    ImageCreateInfo {
+                        image_type: ImageType::Dim2d,
+                        format: images[0].format(),
+                        extent: images[0].extent(),
+                        usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+                        samples,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo::default(),
+                )?,
+            )?,
+        ),
+    };
 
     let framebuffers = images
         .iter()
-        .map(|image| {
-            let view = ImageView::new_default(image.clone()).unwrap();
-            Framebuffer::new(
+        .map(|image| -> Result<_, RendererError> {
+            let view = ImageView::new_default(image.clone())?;
+            let attachments = match intermediary {
+                Some(ref intermediary) => vec![intermediary.clone(), view, depth_buffer.clone()],
+                None => vec![view, depth_buffer.clone()],
+            };
+            Ok(Framebuffer::new(
                 render_pass.clone(),
                 FramebufferCreateInfo {
-                    attachments: vec![view, depth_buffer.clone()],
+                    attachments,
                     ..Default::default()
                 },
-            )
-            .unwrap()
+            )?)
         })
-        .collect::<Vec<_>>();
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(framebuffers)
+}
+
+/// the opaque and transparent pipelines. with a `viewport_extent` the viewport is baked into them,
+/// otherwise it's dynamic state that has to be set in the command buffer
+fn create_pipelines(
+    device: Arc<Device>,
+    viewport_extent: Option<[u32; 2]>,
+    render_pass: Arc<RenderPass>,
+    stages: Vec<EntryPoint>,
+    vertex_buffer_descriptions: &[VertexBufferDescription],
+    polygon_mode: PolygonMode,
+    samples: SampleCount,
+    pipeline_cache: Option<Arc<PipelineCache>>,
+) -> Result<(Arc<GraphicsPipeline>, Arc<GraphicsPipeline>), RendererError> {
+    // validate stages
+    assert!(stages.len() > 0, "no shader stages provided");
+    assert!(
+        stages[0].info().execution_model == ExecutionModel::Vertex,
+        "first shader stage must be vertex shader"
+    );
 
     let vs = stages[0].clone();
 
-    // In the triangle example we use a dynamic viewport, as its a simple example. However in the
-    // teapot example, we recreate the pipelines with a hardcoded viewport instead. This allows the
-    // driver to optimize things, at the cost of slower window resizes.
+    // By default we recreate the pipelines with a hardcoded viewport whenever the window is resized.
+    // This allows the driver to optimize things, at the cost of slower window resizes.
+    // With a dynamic viewport, the viewport is set in the command buffer instead, and resizes only
+    // need new framebuffers.
     // https://computergraphics.stackexchange.com/questions/5742/vulkan-best-way-of-updating-pipeline-viewport
-    let pipeline = {
+    let (pipeline, transparent_pipeline) = {
         let vertex_input_state = vertex_buffer_descriptions
-            .definition(&vs.info().input_interface)
-            .unwrap();
+            .definition(&vs.info().input_interface)?;
         let stages: Vec<_> = stages
             .into_iter()
             .map(PipelineShaderStageCreateInfo::new)
@@ -189,71 +366,116 @@ fn window_size_dependent_setup(
         let layout = PipelineLayout::new(
             device.clone(),
             PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
-                .into_pipeline_layout_create_info(device.clone())
-                .unwrap(),
-        )
-        .unwrap();
+                .into_pipeline_layout_create_info(device.clone())?,
+        )?;
         let subpass = Subpass::from(render_pass, 0).unwrap();
+        let num_color_attachments = subpass.num_color_attachments();
 
-        GraphicsPipeline::new(
-            device,
-            None,
-            GraphicsPipelineCreateInfo {
-                stages: stages.into_iter().collect(),
-                vertex_input_state: Some(vertex_input_state),
-                input_assembly_state: Some(InputAssemblyState::default()),
-                viewport_state: Some(ViewportState {
-                    viewports: [Viewport {
-                        offset: [0.0, 0.0],
-                        extent: [extent[0] as f32, extent[1] as f32],
-                        depth_range: 0.0..=1.0,
-                    }]
-                    .into_iter()
-                    .collect(),
+        let create_info = GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(match viewport_extent {
+                Some(extent) => ViewportState {
+                    viewports: [full_viewport(extent)].into_iter().collect(),
                     ..Default::default()
+                },
+                // a single viewport, set with `set_viewport` before drawing
+                None => ViewportState::default(),
+            }),
+            rasterization_state: Some(RasterizationState {
+                polygon_mode,
+                ..Default::default()
+            }),
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(DepthState::simple()),
+                ..Default::default()
+            }),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples: samples,
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                num_color_attachments,
+                ColorBlendAttachmentState::default(),
+            )),
+            dynamic_state: match viewport_extent {
+                Some(_) => Default::default(),
+                None => [DynamicState::Viewport].into_iter().collect(),
+            },
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        };
+        // transparent objects are blended over what's behind them, and don't hide each other
+        let transparent_create_info = GraphicsPipelineCreateInfo {
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(DepthState {
+                    write_enable: false,
+                    compare_op: CompareOp::Less,
                 }),
-                rasterization_state: Some(RasterizationState::default()),
-                depth_stencil_state: Some(DepthStencilState {
-                    depth: Some(DepthState::simple()),
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                num_color_attachments,
+                ColorBlendAttachmentState {
+                    blend: Some(AttachmentBlend::alpha()),
                     ..Default::default()
-                }),
-                multisample_state: Some(MultisampleState::default()),
-                color_blend_state: Some(ColorBlendState::with_attachment_states(
-                    subpass.num_color_attachments(),
-                    ColorBlendAttachmentState::default(),
-                )),
-                subpass: Some(subpass.into()),
-                ..GraphicsPipelineCreateInfo::layout(layout)
-            },
+                },
+            )),
+            ..create_info.clone()
+        };
+
+        (
+            GraphicsPipeline::new(device.clone(), pipeline_cache.clone(), create_info)?,
+            GraphicsPipeline::new(device, pipeline_cache, transparent_create_info)?,
         )
-        .unwrap()
     };
 
-    (pipeline, framebuffers)
+    Ok((pipeline, transparent_pipeline))
+}
+
+/// returns `present_mode` if the surface supports it, otherwise `PresentMode::Fifo`, which is always supported
+fn supported_present_mode(
+    device: &Device,
+    surface: &Surface,
+    present_mode: PresentMode,
+) -> PresentMode {
+    let supported = device
+        .physical_device()
+        .surface_present_modes(surface, Default::default())
+        .unwrap()
+        .any(|mode| mode == present_mode);
+    if supported {
+        present_mode
+    } else {
+        println!("{present_mode:?} is not supported, falling back to PresentMode::Fifo");
+        PresentMode::Fifo
+    }
 }
 
 fn create_swapchain(
     device: Arc<Device>,
     surface: Arc<Surface>,
-) -> (Arc<Swapchain>, Vec<Arc<Image>>) {
+    present_mode: PresentMode,
+) -> Result<(Arc<Swapchain>, Vec<Arc<Image>>), RendererError> {
     // Querying the capabilities of the surface. When we create the swapchain we can only
     // pass values that are allowed by the capabilities.
     let surface_capabilities = device
         .physical_device()
-        .surface_capabilities(&surface, Default::default())
-        .unwrap();
+        .surface_capabilities(&surface, Default::default())?;
 
     // Choosing the internal format that the images will have.
     let image_format = device
         .physical_device()
-        .surface_formats(&surface, Default::default())
-        .unwrap()[0]
+        .surface_formats(&surface, Default::default())?
+        .first()
+        .ok_or(RendererError::NoSurfaceFormat)?
         .0;
 
     let window = surface.object().unwrap().downcast_ref::<Window>().unwrap();
 
     // Please take a look at the docs for the meaning of the parameters we didn't mention.
-    Swapchain::new(
+    Ok(Swapchain::new(
         device.clone(),
         surface.clone(),
         SwapchainCreateInfo {
@@ -276,7 +498,9 @@ fn create_swapchain(
             // use that.
             image_extent: window.inner_size().into(),
 
-            image_usage: ImageUsage::COLOR_ATTACHMENT,
+            // copying from the images is only needed for recording, so don't ask for it if it's not supported
+            image_usage: ImageUsage::COLOR_ATTACHMENT
+                | (surface_capabilities.supported_usage_flags & ImageUsage::TRANSFER_SRC),
 
             // The alpha mode indicates how the alpha value of the final image will behave. For
             // example, you can choose whether the window will be opaque or transparent.
@@ -286,21 +510,79 @@ fn create_swapchain(
                 .next()
                 .unwrap(),
 
+            present_mode,
+
             ..Default::default()
         },
-    )
-    .unwrap()
+    )?)
 }
 
+/// the size of the window the surface was created for, or `[0, 0]` while it's minimized
 pub fn get_surface_extent(surface: &Surface) -> [u32; 2] {
     let window = surface
         .object()
         .unwrap()
         .downcast_ref::<Window>()
         .unwrap();
+    if window.is_minimized() == Some(true) {
+        return [0, 0];
+    }
     window.inner_size().into()
 }
 
+/// grabs and hides the cursor (for mouse look), or releases and shows it again
+pub fn set_cursor_captured(surface: &Surface, captured: bool) {
+    let window = surface
+        .object()
+        .unwrap()
+        .downcast_ref::<Window>()
+        .unwrap();
+    let result = match captured {
+        // not every platform supports locking the cursor in place, so fall back to confining it to the window
+        true => window
+            .set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined)),
+        false => window.set_cursor_grab(CursorGrabMode::None),
+    };
+    if let Err(e) = result {
+        println!("failed to set cursor grab: {e}");
+    }
+    window.set_cursor_visible(!captured);
+}
+
+// sky blue
+const DEFAULT_CLEAR_COLOR: [f32; 4] = [0.53, 0.81, 0.92, 1.0];
+
+// state while presented frames are being written to disk
+struct Recording {
+    dir: PathBuf,
+    // number of the next frame to be written
+    frame_index: u32,
+    // swapchain images are copied here after rendering
+    staging_buffer: Subbuffer<[u8]>,
+    // the swapchain format is BGRA rather than RGBA
+    swap_red_blue: bool,
+}
+
+fn create_recording_buffer(
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    extent: [u32; 3],
+) -> Subbuffer<[u8]> {
+    Buffer::new_unsized(
+        memory_allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        (extent[0] * extent[1] * 4) as u64,
+    )
+    .unwrap()
+}
+
 pub struct Renderer<Vert> {
     stages: Vec<EntryPoint>,
     surface: Arc<Surface>,
@@ -310,62 +592,110 @@ pub struct Renderer<Vert> {
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
     render_pass: Arc<RenderPass>,
     swapchain: Arc<Swapchain>,
+    images: Vec<Arc<Image>>,
     pipeline: Arc<GraphicsPipeline>,
+    // draws with alpha blending and without writing depth
+    transparent_pipeline: Arc<GraphicsPipeline>,
     framebuffers: Vec<Arc<Framebuffer>>,
     vertex_buffer_descriptions: Vec<VertexBufferDescription>,
+    polygon_mode: PolygonMode,
+    samples: SampleCount,
+    present_mode: PresentMode,
+    clear_color: [f32; 4],
+    descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+    // used whenever a pipeline is rebuilt, e.g. on resize
+    pipeline_cache: Option<Arc<PipelineCache>>,
+    // bound as the instance buffer of draws that aren't instanced
+    identity_instance: Subbuffer<[InstanceData]>,
+    // drawn behind everything instead of the clear color, see `set_background`
+    background_stages: Option<Vec<EntryPoint>>,
+    background_pipeline: Option<Arc<GraphicsPipeline>>,
+    background_descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
     wdd_needs_rebuild: bool,
+    // if true, the viewport is set in the command buffer, so resizing doesn't rebuild the pipelines
+    dynamic_viewport: bool,
+    // set when something other than the window size changed that the pipelines depend on
+    pipelines_need_rebuild: bool,
+    recording: Option<Recording>,
     previous_frame_end: Option<Box<dyn GpuFuture>>,
     phantom: std::marker::PhantomData<Vert>,
 }
 
 impl<T> Renderer<T> {
+    /// `present_mode` controls vsync: `Fifo` waits for vertical blank, `Mailbox` and `Immediate` have lower latency.
+    /// falls back to `Fifo` if the surface doesn't support the requested mode.
+    /// `pipeline_cache`, if given, makes rebuilding the pipelines on resize cheaper.
+    /// with `dynamic_viewport`, resizing only recreates the framebuffers and not the pipelines,
+    /// which avoids hitches for frequent resizes at the cost of slightly slower steady-state rendering.
+    /// panics if the swapchain, render pass or pipeline can't be created, see `try_new`
     pub fn new(
         stages: Vec<EntryPoint>,
         surface: Arc<Surface>,
         queue: Arc<Queue>,
         memory_allocator: Arc<StandardMemoryAllocator>,
+        present_mode: PresentMode,
+        pipeline_cache: Option<Arc<PipelineCache>>,
+        dynamic_viewport: bool,
     ) -> Renderer<T>
+    where
+        T: Vertex,
+    {
+        Self::try_new(
+            stages,
+            surface,
+            queue,
+            memory_allocator,
+            present_mode,
+            pipeline_cache,
+            dynamic_viewport,
+        )
+            .expect("failed to create renderer")
+    }
+
+    /// like `new`, but returns an error instead of panicking if creating the vulkan objects fails
+    pub fn try_new(
+        stages: Vec<EntryPoint>,
+        surface: Arc<Surface>,
+        queue: Arc<Queue>,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        present_mode: PresentMode,
+        pipeline_cache: Option<Arc<PipelineCache>>,
+        dynamic_viewport: bool,
+    ) -> Result<Renderer<T>, RendererError>
     where
         T: Vertex,
     {
         let device = memory_allocator.device().clone();
 
-        let (swapchain, images) = create_swapchain(device.clone(), surface.clone());
+        let present_mode = supported_present_mode(&device, &surface, present_mode);
+        let (swapchain, images) = create_swapchain(device.clone(), surface.clone(), present_mode)?;
 
-        let vertex_buffer_descriptions = [T::per_vertex()];
+        let vertex_buffer_descriptions = [T::per_vertex(), InstanceData::per_instance()];
 
-        let render_pass = vulkano::single_pass_renderpass!(
-            device.clone(),
-            attachments: {
-                color: {
-                    format: swapchain.image_format(),
-                    samples: 1,
-                    load_op: Clear,
-                    store_op: Store,
-                },
-                depth_stencil: {
-                    format: Format::D32_SFLOAT,
-                    samples: 1,
-                    load_op: Clear,
-                    store_op: DontCare,
-                },
-            },
-            pass: {
-                color: [color],
-                depth_stencil: {depth_stencil},
-            },
-        )
-        .unwrap();
+        let render_pass =
+            create_render_pass(device.clone(), swapchain.image_format(), SampleCount::Sample1)?;
 
-        let (pipeline, framebuffers) = window_size_dependent_setup(
+        let framebuffers = create_framebuffers(
             memory_allocator.clone(),
             &images,
             render_pass.clone(),
+            SampleCount::Sample1,
+        )?;
+        let [width, height, _] = images[0].extent();
+        let (pipeline, transparent_pipeline) = create_pipelines(
+            device.clone(),
+            (!dynamic_viewport).then_some([width, height]),
+            render_pass.clone(),
             stages.clone(),
             &vertex_buffer_descriptions,
-        );
+            PolygonMode::Fill,
+            SampleCount::Sample1,
+            pipeline_cache.clone(),
+        )?;
+
+        let identity_instance = identity_instance_buffer(memory_allocator.clone());
 
-        Renderer {
+        Ok(Renderer {
             stages,
             surface,
             command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
@@ -376,14 +706,29 @@ impl<T> Renderer<T> {
             device,
             queue,
             swapchain,
+            images,
             pipeline,
+            transparent_pipeline,
             framebuffers,
             memory_allocator,
             render_pass,
+            polygon_mode: PolygonMode::Fill,
+            samples: SampleCount::Sample1,
+            present_mode,
+            clear_color: DEFAULT_CLEAR_COLOR,
+            descriptor_sets: vec![],
+            pipeline_cache,
+            identity_instance,
+            background_stages: None,
+            background_pipeline: None,
+            background_descriptor_sets: vec![],
             wdd_needs_rebuild: false,
+            dynamic_viewport,
+            pipelines_need_rebuild: false,
+            recording: None,
             vertex_buffer_descriptions: vertex_buffer_descriptions.to_vec(),
             phantom: std::marker::PhantomData,
-        }
+        })
     }
 
     pub fn rebuild(&mut self, extent: [u32; 2]) {
@@ -391,29 +736,205 @@ impl<T> Renderer<T> {
             .swapchain
             .recreate(SwapchainCreateInfo {
                 image_extent: extent,
+                present_mode: self.present_mode,
                 ..self.swapchain.create_info()
             })
             .expect("failed to recreate swapchain");
 
         self.swapchain = new_swapchain;
-        let (new_pipeline, new_framebuffers) = window_size_dependent_setup(
+        self.framebuffers = create_framebuffers(
             self.memory_allocator.clone(),
             &new_images,
             self.render_pass.clone(),
-            self.stages.clone(),
-            &self.vertex_buffer_descriptions,
-        );
-        self.pipeline = new_pipeline;
-        self.framebuffers = new_framebuffers;
+            self.samples,
+        )
+        .expect("failed to create framebuffers");
+        // with a dynamic viewport, the pipelines don't depend on the window size
+        if !self.dynamic_viewport || self.pipelines_need_rebuild {
+            let [width, height, _] = new_images[0].extent();
+            let viewport_extent = (!self.dynamic_viewport).then_some([width, height]);
+            let (new_pipeline, new_transparent_pipeline) = create_pipelines(
+                self.device.clone(),
+                viewport_extent,
+                self.render_pass.clone(),
+                self.stages.clone(),
+                &self.vertex_buffer_descriptions,
+                self.polygon_mode,
+                self.samples,
+                self.pipeline_cache.clone(),
+            )
+            .expect("failed to rebuild pipeline");
+            self.pipeline = new_pipeline;
+            self.transparent_pipeline = new_transparent_pipeline;
+            self.background_pipeline = self.background_stages.clone().map(|stages| {
+                create_background_pipeline(
+                    self.device.clone(),
+                    self.render_pass.clone(),
+                    stages,
+                    viewport_extent,
+                    self.samples,
+                    self.pipeline_cache.clone(),
+                )
+                .expect("failed to rebuild background pipeline")
+            });
+            self.pipelines_need_rebuild = false;
+        }
+        if let Some(ref mut recording) = self.recording {
+            recording.staging_buffer =
+                create_recording_buffer(self.memory_allocator.clone(), new_images[0].extent());
+        }
+        self.images = new_images;
+    }
+
+    /// makes the next `render` recreate the swapchain (and everything that depends on its size) before drawing,
+    /// e.g. as soon as the window is resized, instead of waiting for the swapchain to report that it's out of date
+    pub fn mark_needs_rebuild(&mut self) {
+        self.wdd_needs_rebuild = true;
+    }
+
+    /// the extent of the swapchain images, which is what gets rendered to.
+    /// this lags behind the window size until the swapchain is rebuilt
+    pub fn extent(&self) -> [u32; 2] {
+        let [width, height, _] = self.images[0].extent();
+        [width, height]
+    }
+
+    /// sets the color of the background, where no geometry was drawn
+    pub fn set_clear_color(&mut self, clear_color: [f32; 4]) {
+        self.clear_color = clear_color;
     }
 
+    /// switch between filled and wireframe rendering. the pipeline is rebuilt before the next frame.
+    /// falls back to filled polygons if the device doesn't support `fill_mode_non_solid`
+    pub fn set_polygon_mode(&mut self, polygon_mode: PolygonMode) {
+        self.polygon_mode = if polygon_mode == PolygonMode::Fill
+            || self.device.enabled_features().fill_mode_non_solid
+        {
+            polygon_mode
+        } else {
+            println!("fill_mode_non_solid is not enabled, falling back to PolygonMode::Fill");
+            PolygonMode::Fill
+        };
+        self.wdd_needs_rebuild = true;
+        self.pipelines_need_rebuild = true;
+    }
+
+    /// sets the number of samples per pixel used for antialiasing. the render pass is rebuilt before the next frame.
+    /// falls back to 1 sample if the device doesn't support the requested count
+    pub fn set_sample_count(&mut self, samples: SampleCount) {
+        self.samples = supported_sample_count(&self.device, samples);
+        self.render_pass = create_render_pass(
+            self.device.clone(),
+            self.swapchain.image_format(),
+            self.samples,
+        )
+        .expect("failed to create render pass");
+        self.wdd_needs_rebuild = true;
+        self.pipelines_need_rebuild = true;
+    }
+
+    /// sets how frames are presented to the window. the swapchain is recreated before the next frame.
+    /// falls back to `Fifo` if the surface doesn't support the requested mode
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        self.present_mode = supported_present_mode(&self.device, &self.surface, present_mode);
+        self.wdd_needs_rebuild = true;
+    }
+
+    /// starts writing every presented frame to `dir` (which must exist) as `frame_000000.png`, `frame_000001.png`, ...
+    /// fails if the swapchain images can't be read back. recording waits for each frame to finish, so it lowers the frame rate
+    pub fn start_recording(&mut self, dir: &Path) -> Result<(), RendererError> {
+        if !self.swapchain.image_usage().intersects(ImageUsage::TRANSFER_SRC) {
+            return Err(RendererError::SwapchainNotReadable);
+        }
+        let swap_red_blue = match self.swapchain.image_format() {
+            Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB => false,
+            Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB => true,
+            format => return Err(RendererError::UnreadableSwapchainFormat(format)),
+        };
+        self.recording = Some(Recording {
+            dir: dir.to_path_buf(),
+            frame_index: 0,
+            staging_buffer: create_recording_buffer(
+                self.memory_allocator.clone(),
+                self.images[0].extent(),
+            ),
+            swap_red_blue,
+        });
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.recording = None;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // waits for the frame to be copied into the staging buffer, then writes it out
+    fn write_recorded_frame(&mut self) {
+        let [width, height, _] = self.images[0].extent();
+        let Some(ref mut recording) = self.recording else {
+            return;
+        };
+        let mut data = recording.staging_buffer.read().unwrap().to_vec();
+        if recording.swap_red_blue {
+            for pixel in data.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        let path = recording
+            .dir
+            .join(format!("frame_{:06}.png", recording.frame_index));
+        recording.frame_index += 1;
+        let result = RgbaImage::from_raw(width, height, data)
+            .expect("staging buffer size does not match extent")
+            .save_with_format(&path, image::ImageFormat::Png);
+        if let Err(e) = result {
+            println!("failed to write {}: {e}", path.display());
+        }
+    }
+
+    /// descriptor sets bound (starting at set 0) for every following render, e.g. uniform buffers the shaders read.
+    /// they must match the set layouts of the renderer's shaders
+    pub fn set_descriptor_sets(&mut self, descriptor_sets: Vec<Arc<PersistentDescriptorSet>>) {
+        self.descriptor_sets = descriptor_sets;
+    }
+
+    /// draws a fullscreen triangle with `stages` (see `create_background_pipeline`) behind everything at the start of
+    /// every frame, e.g. a skybox. `None` goes back to the plain clear color. the pipeline is rebuilt before the next frame
+    pub fn set_background(&mut self, stages: Option<Vec<EntryPoint>>) {
+        self.background_stages = stages;
+        self.wdd_needs_rebuild = true;
+        self.pipelines_need_rebuild = true;
+    }
+
+    /// descriptor sets bound (starting at set 0) when drawing the background, like `set_descriptor_sets`
+    pub fn set_background_descriptor_sets(
+        &mut self,
+        descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+    ) {
+        self.background_descriptor_sets = descriptor_sets;
+    }
+
+    /// draws each set of buffers with its own draw call, all in the same render pass
     pub fn render<Pc, VB>(&mut self, vertex_buffers: VB, push_data: Pc)
     where
-        Pc: BufferContents,
-        VB: IntoIterator<Item = Subbuffer<[T]>>,
+        Pc: BufferContents + Clone,
+        VB: IntoIterator<Item = DrawBuffers<T>>,
+    {
+        self.render_with_transparent(vertex_buffers, std::iter::empty(), push_data)
+    }
+
+    /// like `render`, but after the opaque buffers, draws `transparent` with alpha blending in the order given
+    /// (which should be back to front, see `Scene::transparent_draw_buffers`)
+    pub fn render_with_transparent<Pc, VB, TB>(&mut self, vertex_buffers: VB, transparent: TB, push_data: Pc)
+    where
+        Pc: BufferContents + Clone,
+        VB: IntoIterator<Item = DrawBuffers<T>>,
+        TB: IntoIterator<Item = DrawBuffers<T>>,
     {
-        // Do not draw frame when screen dimensions are zero.
-        // On Windows, this can occur from minimizing the application.
+        // Do not draw frame when screen dimensions are zero, e.g. when the window is minimized.
         let extent = get_surface_extent(&self.surface);
         if extent[0] == 0 || extent[1] == 0 {
             return;
@@ -467,27 +988,134 @@ impl<T> Renderer<T> {
         builder
             .begin_render_pass(
                 RenderPassBeginInfo {
-                    clear_values: vec![Some([0.53, 0.81, 0.92, 1.0].into()), Some(1f32.into())],
+                    // the resolve attachment is overwritten, so it doesn't need clearing
+                    clear_values: match self.samples {
+                        SampleCount::Sample1 => vec![Some(self.clear_color.into()), Some(1f32.into())],
+                        _ => vec![Some(self.clear_color.into()), None, Some(1f32.into())],
+                    },
                     ..RenderPassBeginInfo::framebuffer(
                         self.framebuffers[image_index as usize].clone(),
                     )
                 },
                 Default::default(),
             )
-            .unwrap()
+            .unwrap();
+
+        if self.dynamic_viewport {
+            builder
+                .set_viewport(0, [full_viewport(self.extent())].into_iter().collect())
+                .unwrap();
+        }
+
+        if let Some(ref background_pipeline) = self.background_pipeline {
+            builder
+                .bind_pipeline_graphics(background_pipeline.clone())
+                .unwrap();
+            if !self.background_descriptor_sets.is_empty() {
+                builder
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        background_pipeline.layout().clone(),
+                        0,
+                        self.background_descriptor_sets.clone(),
+                    )
+                    .unwrap();
+            }
+            builder.draw(3, 1, 0, 0).unwrap();
+        }
+
+        builder
             .bind_pipeline_graphics(self.pipeline.clone())
             .unwrap()
-            .push_constants(self.pipeline.layout().clone(), 0, push_data)
+            .push_constants(self.pipeline.layout().clone(), 0, push_data.clone())
             .unwrap();
 
-        // for each vertex buffer, bind it and draw
-        for vertex_buffer in vertex_buffers {
+        if !self.descriptor_sets.is_empty() {
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    self.pipeline.layout().clone(),
+                    0,
+                    self.descriptor_sets.clone(),
+                )
+                .unwrap();
+        }
+
+        // for each vertex buffer, bind it and draw. the transparent ones come last, after switching pipelines
+        let mut transparent_bound = false;
+        for (
+            is_transparent,
+            DrawBuffers {
+                vertex_buffer,
+                index_buffer,
+                instance_buffer,
+                texture,
+            },
+        ) in vertex_buffers
+            .into_iter()
+            .map(|b| (false, b))
+            .chain(transparent.into_iter().map(|b| (true, b)))
+        {
+            if is_transparent && !transparent_bound {
+                transparent_bound = true;
+                builder
+                    .bind_pipeline_graphics(self.transparent_pipeline.clone())
+                    .unwrap()
+                    .push_constants(
+                        self.transparent_pipeline.layout().clone(),
+                        0,
+                        push_data.clone(),
+                    )
+                    .unwrap();
+                if !self.descriptor_sets.is_empty() {
+                    builder
+                        .bind_descriptor_sets(
+                            PipelineBindPoint::Graphics,
+                            self.transparent_pipeline.layout().clone(),
+                            0,
+                            self.descriptor_sets.clone(),
+                        )
+                        .unwrap();
+                }
+            }
+            let pipeline = match transparent_bound {
+                true => &self.transparent_pipeline,
+                false => &self.pipeline,
+            };
+            // textures go in the set after the renderer's own, if the pipeline's shaders sample one
+            if let Some(texture) = texture {
+                let set = self.descriptor_sets.len();
+                if pipeline.layout().set_layouts().len() > set {
+                    builder
+                        .bind_descriptor_sets(
+                            PipelineBindPoint::Graphics,
+                            pipeline.layout().clone(),
+                            set as u32,
+                            texture,
+                        )
+                        .unwrap();
+                }
+            }
             let vertex_count = vertex_buffer.len() as u32;
+            // plain draws get a single untransformed instance
+            let instance_count = instance_buffer.as_ref().map_or(1, |b| b.len() as u32);
+            let instance_buffer = instance_buffer.unwrap_or_else(|| self.identity_instance.clone());
             builder
-                .bind_vertex_buffers(0, vertex_buffer)
-                .unwrap()
-                .draw(vertex_count, 1, 0, 0)
+                .bind_vertex_buffers(0, (vertex_buffer, instance_buffer))
                 .unwrap();
+            match index_buffer {
+                Some(index_buffer) => {
+                    let index_count = index_buffer.len() as u32;
+                    builder
+                        .bind_index_buffer(index_buffer)
+                        .unwrap()
+                        .draw_indexed(index_count, instance_count, 0, 0, 0)
+                        .unwrap();
+                }
+                None => {
+                    builder.draw(vertex_count, instance_count, 0, 0).unwrap();
+                }
+            }
         }
 
         // We leave the render pass by calling `draw_end`. Note that if we had multiple
@@ -495,6 +1123,15 @@ impl<T> Renderer<T> {
         // next subpass.
         builder.end_render_pass(Default::default()).unwrap();
 
+        if let Some(ref recording) = self.recording {
+            builder
+                .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                    self.images[image_index as usize].clone(),
+                    recording.staging_buffer.clone(),
+                ))
+                .unwrap();
+        }
+
         let command_buffer = builder.build().unwrap();
 
         let future = self
@@ -518,6 +1155,10 @@ impl<T> Renderer<T> {
 
         match future.map_err(Validated::unwrap) {
             Ok(future) => {
+                if self.recording.is_some() {
+                    future.wait(None).unwrap();
+                    self.write_recorded_frame();
+                }
                 self.previous_frame_end = Some(future.boxed());
             }
             Err(VulkanError::OutOfDate) => {
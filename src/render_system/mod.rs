@@ -1,4 +1,9 @@
+mod background;
+pub mod error;
 pub mod interactive_rendering;
 pub mod offscreen_rendering;
 pub mod scene;
+pub mod shadow_map;
+#[cfg(test)]
+pub mod testing;
 pub mod queued_now_future;
\ No newline at end of file
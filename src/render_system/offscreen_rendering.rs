@@ -1,4 +1,9 @@
-use std::sync::Arc;
+use std::{collections::VecDeque, path::Path, sync::Arc};
+
+use image::{
+    error::{ParameterError, ParameterErrorKind},
+    ImageError, RgbaImage,
+};
 
 use vulkano::{
     buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
@@ -6,41 +11,164 @@ use vulkano::{
         allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
         CopyImageToBufferInfo, RenderPassBeginInfo,
     },
+    descriptor_set::PersistentDescriptorSet,
     device::{Device, DeviceOwned, Queue},
-    format::Format,
+    format::{Format, FormatFeatures},
     image::{
         view::ImageView, Image, ImageCreateInfo, ImageLayout, ImageTiling, ImageType, ImageUsage,
+        SampleCount,
     },
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
     pipeline::{
+        cache::PipelineCache,
         graphics::{
-            color_blend::{ColorBlendAttachmentState, ColorBlendState},
-            depth_stencil::{DepthState, DepthStencilState},
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
             input_assembly::InputAssemblyState,
             multisample::MultisampleState,
-            rasterization::RasterizationState,
+            rasterization::{PolygonMode, RasterizationState},
             vertex_input::{Vertex, VertexBufferDescription, VertexDefinition},
             viewport::{Viewport, ViewportState},
             GraphicsPipelineCreateInfo,
         },
         layout::PipelineDescriptorSetLayoutCreateInfo,
-        GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+        GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
     },
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
     shader::{spirv::ExecutionModel, EntryPoint},
-    sync::{future::FenceSignalFuture, GpuFuture},
+    sync::{future::FenceSignalFuture, GpuFuture, Sharing},
     Validated,
 };
 
+use crate::render_system::background::create_background_pipeline;
+use crate::render_system::error::RendererError;
 use crate::render_system::queued_now_future;
+use crate::render_system::scene::{identity_instance_buffer, DrawBuffers, InstanceData};
+
+/// resources that are written on the graphics queue and read on the transfer queue must be shared between both families
+fn sharing_between(queue: &Queue, transfer_queue: &Queue) -> Sharing<smallvec::SmallVec<[u32; 4]>> {
+    if queue.queue_family_index() == transfer_queue.queue_family_index() {
+        Sharing::Exclusive
+    } else {
+        Sharing::Concurrent(
+            [queue.queue_family_index(), transfer_queue.queue_family_index()]
+                .into_iter()
+                .collect(),
+        )
+    }
+}
+
+fn create_depth_image(
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    extent: [u32; 2],
+    sharing: Sharing<smallvec::SmallVec<[u32; 4]>>,
+    samples: SampleCount,
+) -> Result<Arc<Image>, RendererError> {
+    Ok(Image::new(
+        memory_allocator,
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::D32_SFLOAT,
+            extent: [extent[0], extent[1], 1],
+            // the depth is read back after rendering, so it can't be transient
+            usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+            sharing,
+            samples,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )?)
+}
+
+/// returns `samples` if the device can render color and depth with that many samples, otherwise 1
+fn supported_sample_count(device: &Device, samples: SampleCount) -> SampleCount {
+    let properties = device.physical_device().properties();
+    let supported =
+        properties.framebuffer_color_sample_counts & properties.framebuffer_depth_sample_counts;
+    if supported.contains_enum(samples) {
+        samples
+    } else {
+        println!("{samples:?} is not supported, falling back to SampleCount::Sample1");
+        SampleCount::Sample1
+    }
+}
+
+/// with multisampling, we render to a multisampled image that is resolved into `format` image at the end of the pass
+fn create_render_pass(device: Arc<Device>, format: Format, samples: SampleCount) -> Result<Arc<RenderPass>, RendererError> {
+    let render_pass = match samples {
+        SampleCount::Sample1 => vulkano::single_pass_renderpass!(
+            device,
+            attachments: {
+                color: {
+                    format: format,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+                depth_stencil: {
+                    format: Format::D32_SFLOAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {depth_stencil},
+            },
+        )?,
+        // a multisampled depth image can't be copied to a buffer, so there's no point storing it
+        samples => vulkano::single_pass_renderpass!(
+            device,
+            attachments: {
+                intermediary: {
+                    format: format,
+                    samples: samples,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+                color: {
+                    format: format,
+                    samples: 1,
+                    load_op: DontCare,
+                    store_op: Store,
+                },
+                depth_stencil: {
+                    format: Format::D32_SFLOAT,
+                    samples: samples,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+            },
+            pass: {
+                color: [intermediary],
+                color_resolve: [color],
+                depth_stencil: {depth_stencil},
+            },
+        )?,
+    };
+    Ok(render_pass)
+}
+
+/// converts a value read from the depth image back into the distance from the camera.
+/// `near` and `far` must be the clip planes of the perspective projection the image was rendered with,
+/// see `Camera::linearize_depth` for a version that works with any camera
+pub fn linearize_depth(depth: f32, near: f32, far: f32) -> f32 {
+    2.0 * far * near / ((far + near) - depth * (far - near))
+}
 
 fn construct_offscreen_pipeline(
     memory_allocator: Arc<StandardMemoryAllocator>,
     image: Arc<Image>,
+    depth_image: Arc<Image>,
     render_pass: Arc<RenderPass>,
     stages: Vec<EntryPoint>,
     vertex_buffer_descriptions: &[VertexBufferDescription],
-) -> (Arc<GraphicsPipeline>, Arc<Framebuffer>) {
+    polygon_mode: PolygonMode,
+    samples: SampleCount,
+    pipeline_cache: Option<Arc<PipelineCache>>,
+) -> Result<(Arc<GraphicsPipeline>, Arc<GraphicsPipeline>, Arc<Framebuffer>), RendererError> {
     // validate stages
     assert!(stages.len() > 0, "no shader stages provided");
     assert!(
@@ -51,32 +179,39 @@ fn construct_offscreen_pipeline(
     let device = memory_allocator.device().clone();
     let extent = image.extent();
 
-    let depth_buffer = ImageView::new_default(
-        Image::new(
-            memory_allocator,
-            ImageCreateInfo {
-                image_type: ImageType::Dim2d,
-                format: Format::D32_SFLOAT,
-                extent,
-                usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
-                ..Default::default()
-            },
-            AllocationCreateInfo::default(),
-        )
-        .unwrap(),
-    )
-    .unwrap();
+    let depth_buffer = ImageView::new_default(depth_image)?;
 
     let framebuffer = {
-        let view = ImageView::new_default(image.clone()).unwrap();
+        let view = ImageView::new_default(image.clone())?;
+        let attachments = match samples {
+            SampleCount::Sample1 => vec![view, depth_buffer],
+            samples => {
+                // the multisampled image that gets resolved into `image`
+                let intermediary = ImageView::new_default(
+                    Image::new(
+                        memory_allocator,
+                        ImageCreateInfo {
+                            image_type: ImageType::Dim2d,
+                            format: image.format(),
+                            extent,
+                            usage: ImageUsage::COLOR_ATTACHMENT
+                                | ImageUsage::TRANSIENT_ATTACHMENT,
+                            samples,
+                            ..Default::default()
+                        },
+                        AllocationCreateInfo::default(),
+                    )?,
+                )?;
+                vec![intermediary, view, depth_buffer]
+            }
+        };
         Framebuffer::new(
             render_pass.clone(),
             FramebufferCreateInfo {
-                attachments: vec![view, depth_buffer.clone()],
+                attachments,
                 ..Default::default()
             },
-        )
-        .unwrap()
+        )?
     };
 
     let vs = stages[0].clone();
@@ -85,10 +220,9 @@ fn construct_offscreen_pipeline(
     // teapot example, we recreate the pipelines with a hardcoded viewport instead. This allows the
     // driver to optimize things, at the cost of slower window resizes.
     // https://computergraphics.stackexchange.com/questions/5742/vulkan-best-way-of-updating-pipeline-viewport
-    let pipeline = {
+    let (pipeline, transparent_pipeline) = {
         let vertex_input_state = vertex_buffer_descriptions
-            .definition(&vs.info().input_interface)
-            .unwrap();
+            .definition(&vs.info().input_interface)?;
         let stages: Vec<_> = stages
             .into_iter()
             .map(PipelineShaderStageCreateInfo::new)
@@ -96,47 +230,119 @@ fn construct_offscreen_pipeline(
         let layout = PipelineLayout::new(
             device.clone(),
             PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
-                .into_pipeline_layout_create_info(device.clone())
-                .unwrap(),
-        )
-        .unwrap();
+                .into_pipeline_layout_create_info(device.clone())?,
+        )?;
         let subpass = Subpass::from(render_pass, 0).unwrap();
+        let num_color_attachments = subpass.num_color_attachments();
 
-        GraphicsPipeline::new(
-            device,
-            None,
-            GraphicsPipelineCreateInfo {
-                stages: stages.into_iter().collect(),
-                vertex_input_state: Some(vertex_input_state),
-                input_assembly_state: Some(InputAssemblyState::default()),
-                viewport_state: Some(ViewportState {
-                    viewports: [Viewport {
-                        offset: [0.0, 0.0],
-                        extent: [extent[0] as f32, extent[1] as f32],
-                        depth_range: 0.0..=1.0,
-                    }]
-                    .into_iter()
-                    .collect(),
-                    ..Default::default()
+        let create_info = GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState {
+                viewports: [Viewport {
+                    offset: [0.0, 0.0],
+                    extent: [extent[0] as f32, extent[1] as f32],
+                    depth_range: 0.0..=1.0,
+                }]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            }),
+            rasterization_state: Some(RasterizationState {
+                polygon_mode,
+                ..Default::default()
+            }),
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(DepthState::simple()),
+                ..Default::default()
+            }),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples: samples,
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                num_color_attachments,
+                ColorBlendAttachmentState::default(),
+            )),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        };
+        // transparent objects are blended over what's behind them, and don't hide each other
+        let transparent_create_info = GraphicsPipelineCreateInfo {
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(DepthState {
+                    write_enable: false,
+                    compare_op: CompareOp::Less,
                 }),
-                rasterization_state: Some(RasterizationState::default()),
-                depth_stencil_state: Some(DepthStencilState {
-                    depth: Some(DepthState::simple()),
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                num_color_attachments,
+                ColorBlendAttachmentState {
+                    blend: Some(AttachmentBlend::alpha()),
                     ..Default::default()
-                }),
-                multisample_state: Some(MultisampleState::default()),
-                color_blend_state: Some(ColorBlendState::with_attachment_states(
-                    subpass.num_color_attachments(),
-                    ColorBlendAttachmentState::default(),
-                )),
-                subpass: Some(subpass.into()),
-                ..GraphicsPipelineCreateInfo::layout(layout)
-            },
+                },
+            )),
+            ..create_info.clone()
+        };
+
+        (
+            GraphicsPipeline::new(device.clone(), pipeline_cache.clone(), create_info)?,
+            GraphicsPipeline::new(device, pipeline_cache, transparent_create_info)?,
         )
-        .unwrap()
     };
 
-    (pipeline, framebuffer)
+    Ok((pipeline, transparent_pipeline, framebuffer))
+}
+
+// sky blue
+const DEFAULT_CLEAR_COLOR: [f32; 4] = [0.53, 0.81, 0.92, 1.0];
+
+// one slot in the ring of buffers that rendered frames are copied into
+struct StagingBuffers {
+    image: Subbuffer<[u8]>,
+    depth: Subbuffer<[f32]>,
+    // signaled once the copy into this slot has finished
+    frame_end: Option<Arc<FenceSignalFuture<Box<dyn GpuFuture>>>>,
+}
+
+fn create_staging_buffers(
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    extent: [u32; 2],
+    format: Format,
+) -> Result<StagingBuffers, RendererError> {
+    let image = Buffer::new_unsized(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        extent[0] as u64 * extent[1] as u64 * format.block_size(),
+    )?;
+
+    let depth = Buffer::new_slice(
+        memory_allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        (extent[0] * extent[1]) as u64,
+    )?;
+
+    Ok(StagingBuffers {
+        image,
+        depth,
+        frame_end: None,
+    })
 }
 
 pub struct Renderer<Vert> {
@@ -144,129 +350,421 @@ pub struct Renderer<Vert> {
     stages: Vec<EntryPoint>,
     device: Arc<Device>,
     queue: Arc<Queue>,
+    transfer_queue: Arc<Queue>,
     memory_allocator: Arc<StandardMemoryAllocator>,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
     render_pass: Arc<RenderPass>,
     pipeline: Arc<GraphicsPipeline>,
+    // draws with alpha blending and without writing depth
+    transparent_pipeline: Arc<GraphicsPipeline>,
     image: Arc<Image>,
+    depth_image: Arc<Image>,
     framebuffer: Arc<Framebuffer>,
-    staging_buffer: Subbuffer<[u8]>,
+    staging_buffers: Vec<StagingBuffers>,
+    // slot that the next frame will be copied into
+    next_staging_buffers: usize,
+    // slots holding frames that haven't been read yet, oldest first
+    pending_frames: VecDeque<usize>,
+    // slot holding the frame most recently returned by `get_image`
+    last_read_frame: usize,
     vertex_buffer_descriptions: Vec<VertexBufferDescription>,
-    previous_frame_end: Option<FenceSignalFuture<Box<dyn GpuFuture>>>,
+    polygon_mode: PolygonMode,
+    samples: SampleCount,
+    clear_color: [f32; 4],
+    descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+    // shared between renderers so pipelines don't have to be compiled from scratch every time they're rebuilt
+    pipeline_cache: Option<Arc<PipelineCache>>,
+    // bound as the instance buffer of draws that aren't instanced
+    identity_instance: Subbuffer<[InstanceData]>,
+    // drawn behind everything instead of the clear color, see `set_background`
+    background_stages: Option<Vec<EntryPoint>>,
+    background_pipeline: Option<Arc<GraphicsPipeline>>,
+    background_descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+    previous_frame_end: Option<Arc<FenceSignalFuture<Box<dyn GpuFuture>>>>,
     phantom: std::marker::PhantomData<Vert>,
 }
 
 impl<T> Renderer<T> {
+    /// `format` is the format of the rendered image (e.g. `R8G8B8A8_UNORM`, `R8_UNORM` for grayscale or
+    /// `R16G16B16A16_SFLOAT` for float color), and so of the bytes returned by `get_image`.
+    ///
+    /// `staging_buffer_count` is how many rendered frames can be waiting to be read at once.
+    /// with more than one, rendering the next frame doesn't have to wait for the previous one to be read.
+    ///
+    /// `shared_depth_image` lets renderers of the same extent use one depth image (see `depth_image`) instead of allocating their own.
+    /// this is only safe if they never have frames in flight at the same time: read each frame with `get_image`
+    /// before rendering with another renderer that shares the image, and only call `get_depth_data` right after `get_image`.
+    ///
+    /// `pipeline_cache`, if given, is used whenever the renderer builds a pipeline, and can be shared by all renderers on a device.
+    ///
+    /// panics if the images, render pass or pipeline can't be created, see `try_new`
     pub fn new(
         extent: [u32; 2],
+        format: Format,
+        staging_buffer_count: usize,
         stages: Vec<EntryPoint>,
         queue: Arc<Queue>,
+        transfer_queue: Arc<Queue>,
         memory_allocator: Arc<StandardMemoryAllocator>,
+        shared_depth_image: Option<Arc<Image>>,
+        pipeline_cache: Option<Arc<PipelineCache>>,
     ) -> Renderer<T>
+    where
+        T: Vertex,
+    {
+        Self::try_new(
+            extent,
+            format,
+            staging_buffer_count,
+            stages,
+            queue,
+            transfer_queue,
+            memory_allocator,
+            shared_depth_image,
+            pipeline_cache,
+        )
+        .expect("failed to create renderer")
+    }
+
+    /// like `new`, but returns an error instead of panicking if creating the vulkan objects fails
+    /// or the device can't render to `format`
+    pub fn try_new(
+        extent: [u32; 2],
+        format: Format,
+        staging_buffer_count: usize,
+        stages: Vec<EntryPoint>,
+        queue: Arc<Queue>,
+        transfer_queue: Arc<Queue>,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        shared_depth_image: Option<Arc<Image>>,
+        pipeline_cache: Option<Arc<PipelineCache>>,
+    ) -> Result<Renderer<T>, RendererError>
     where
         T: Vertex,
     {
         // validate stages
         assert!(stages.len() > 0, "no shader stages provided");
+        assert!(staging_buffer_count > 0, "need at least one staging buffer");
         assert!(
             stages[0].info().execution_model == ExecutionModel::Vertex,
             "first shader stage must be vertex shader"
         );
         let device = memory_allocator.device().clone();
 
+        // we render to the image and then copy it into the staging buffers
+        let format_features = device
+            .physical_device()
+            .format_properties(format)
+            .map_err(|_| RendererError::UnsupportedFormat(format))?
+            .optimal_tiling_features;
+        if !format_features.contains(FormatFeatures::COLOR_ATTACHMENT | FormatFeatures::TRANSFER_SRC) {
+            return Err(RendererError::UnsupportedFormat(format));
+        }
+
+        let sharing = sharing_between(&queue, &transfer_queue);
+
         // the image we render to
         let image = Image::new(
             memory_allocator.clone(),
             ImageCreateInfo {
                 image_type: ImageType::Dim2d,
-                format: Format::R8G8B8A8_UNORM,
+                format,
                 extent: [extent[0], extent[1], 1],
                 tiling: ImageTiling::Optimal,
                 usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+                sharing: sharing.clone(),
                 ..ImageCreateInfo::default()
             },
             AllocationCreateInfo {
                 ..AllocationCreateInfo::default()
             },
-        )
-        .unwrap();
+        )?;
 
-        let render_pass = vulkano::single_pass_renderpass!(
-            device.clone(),
-            attachments: {
-                color: {
-                    format: image.format(),
-                    samples: 1,
-                    load_op: Clear,
-                    store_op: Store,
-                },
-                depth_stencil: {
-                    format: Format::D32_SFLOAT,
-                    samples: 1,
-                    load_op: Clear,
-                    store_op: DontCare,
-                },
-            },
-            pass: {
-                color: [color],
-                depth_stencil: {depth_stencil},
-            },
-        )
-        .unwrap();
+        let render_pass = create_render_pass(device.clone(), image.format(), SampleCount::Sample1)?;
 
-        let vertex_buffer_descriptions = [T::per_vertex()];
+        let depth_image = match shared_depth_image {
+            Some(depth_image) => {
+                assert!(
+                    depth_image.extent() == [extent[0], extent[1], 1],
+                    "shared depth image must have the same extent as the renderer"
+                );
+                assert!(
+                    depth_image.samples() == SampleCount::Sample1,
+                    "shared depth image must not be multisampled"
+                );
+                depth_image
+            }
+            None => create_depth_image(
+                memory_allocator.clone(),
+                extent,
+                sharing.clone(),
+                SampleCount::Sample1,
+            )?,
+        };
+
+        let vertex_buffer_descriptions = [T::per_vertex(), InstanceData::per_instance()];
 
-        let (pipeline, framebuffer) = construct_offscreen_pipeline(
+        let (pipeline, transparent_pipeline, framebuffer) = construct_offscreen_pipeline(
             memory_allocator.clone(),
             image.clone(),
+            depth_image.clone(),
             render_pass.clone(),
             stages.clone(),
             &vertex_buffer_descriptions,
-        );
+            PolygonMode::Fill,
+            SampleCount::Sample1,
+            pipeline_cache.clone(),
+        )?;
 
-        let staging_buffer = Buffer::new_unsized(
-            memory_allocator.clone(),
-            BufferCreateInfo {
-                usage: BufferUsage::TRANSFER_DST,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::HOST_RANDOM_ACCESS,
-                ..Default::default()
-            },
-            (extent[0] * extent[1] * 4) as u64,
-        )
-        .unwrap();
+        let staging_buffers = (0..staging_buffer_count)
+            .map(|_| create_staging_buffers(memory_allocator.clone(), extent, format))
+            .collect::<Result<_, _>>()?;
+
+        let identity_instance = identity_instance_buffer(memory_allocator.clone());
 
-        Renderer {
+        Ok(Renderer {
             extent,
             stages,
             command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
                 device.clone(),
                 Default::default(),
             )),
-            previous_frame_end: Some(queued_now_future::now(queue.clone()).boxed().then_signal_fence()),
+            previous_frame_end: Some(Arc::new(
+                queued_now_future::now(queue.clone())
+                    .boxed()
+                    .then_signal_fence(),
+            )),
             device,
             queue,
+            transfer_queue,
             pipeline,
+            transparent_pipeline,
             image,
+            depth_image,
             framebuffer,
-            staging_buffer,
+            staging_buffers,
+            next_staging_buffers: 0,
+            pending_frames: VecDeque::new(),
+            last_read_frame: 0,
             memory_allocator,
             render_pass,
             vertex_buffer_descriptions: vertex_buffer_descriptions.to_vec(),
+            polygon_mode: PolygonMode::Fill,
+            samples: SampleCount::Sample1,
+            clear_color: DEFAULT_CLEAR_COLOR,
+            descriptor_sets: vec![],
+            pipeline_cache,
+            identity_instance,
+            background_stages: None,
+            background_pipeline: None,
+            background_descriptor_sets: vec![],
             phantom: std::marker::PhantomData,
-        }
+        })
     }
 
+    /// the size of the rendered image, e.g. to compute the aspect ratio of a camera's projection
     pub fn extent(&self) -> [u32; 2] {
         self.extent
     }
 
+    /// the format of the rendered image, which determines the layout of the bytes returned by `get_image`
+    pub fn format(&self) -> Format {
+        self.image.format()
+    }
+
+    /// the depth image this renderer draws into, which can be passed to `new` to share it with another renderer
+    pub fn depth_image(&self) -> Arc<Image> {
+        self.depth_image.clone()
+    }
+
+    /// sets the color of the background, where no geometry was drawn
+    pub fn set_clear_color(&mut self, clear_color: [f32; 4]) {
+        self.clear_color = clear_color;
+    }
+
+    /// switch between filled and wireframe rendering, rebuilding the pipeline.
+    /// falls back to filled polygons if the device doesn't support `fill_mode_non_solid`
+    pub fn set_polygon_mode(&mut self, polygon_mode: PolygonMode) {
+        self.polygon_mode = if polygon_mode == PolygonMode::Fill
+            || self.device.enabled_features().fill_mode_non_solid
+        {
+            polygon_mode
+        } else {
+            println!("fill_mode_non_solid is not enabled, falling back to PolygonMode::Fill");
+            PolygonMode::Fill
+        };
+        let (pipeline, transparent_pipeline, framebuffer) = construct_offscreen_pipeline(
+            self.memory_allocator.clone(),
+            self.image.clone(),
+            self.depth_image.clone(),
+            self.render_pass.clone(),
+            self.stages.clone(),
+            &self.vertex_buffer_descriptions,
+            self.polygon_mode,
+            self.samples,
+            self.pipeline_cache.clone(),
+        )
+        .expect("failed to rebuild pipeline");
+        self.pipeline = pipeline;
+        self.transparent_pipeline = transparent_pipeline;
+        self.framebuffer = framebuffer;
+    }
+
+    /// sets the number of samples per pixel used for antialiasing, rebuilding the render pass and pipeline.
+    /// falls back to 1 sample if the device doesn't support the requested count.
+    /// depth can't be read back from a multisampled image, so `get_depth_data` is only available with 1 sample.
+    /// this gives the renderer its own depth image, even if it was sharing one
+    pub fn set_sample_count(&mut self, samples: SampleCount) {
+        self.samples = supported_sample_count(&self.device, samples);
+        self.render_pass = create_render_pass(self.device.clone(), self.image.format(), self.samples)
+            .expect("failed to create render pass");
+        self.depth_image = create_depth_image(
+            self.memory_allocator.clone(),
+            self.extent,
+            sharing_between(&self.queue, &self.transfer_queue),
+            self.samples,
+        )
+        .expect("failed to create depth image");
+        let (pipeline, transparent_pipeline, framebuffer) = construct_offscreen_pipeline(
+            self.memory_allocator.clone(),
+            self.image.clone(),
+            self.depth_image.clone(),
+            self.render_pass.clone(),
+            self.stages.clone(),
+            &self.vertex_buffer_descriptions,
+            self.polygon_mode,
+            self.samples,
+            self.pipeline_cache.clone(),
+        )
+        .expect("failed to rebuild pipeline");
+        self.pipeline = pipeline;
+        self.transparent_pipeline = transparent_pipeline;
+        self.framebuffer = framebuffer;
+        self.rebuild_background_pipeline();
+    }
+
+    /// changes the size of the rendered image, recreating the images, framebuffer, pipelines and staging buffers.
+    /// frames that were rendered but not read yet are discarded.
+    /// this gives the renderer its own depth image, even if it was sharing one
+    pub fn resize(&mut self, extent: [u32; 2]) {
+        assert!(extent[0] > 0 && extent[1] > 0, "extent must not be zero");
+        if extent == self.extent {
+            return;
+        }
+        let format = self.image.format();
+        let sharing = sharing_between(&self.queue, &self.transfer_queue);
+        self.image = Image::new(
+            self.memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format,
+                extent: [extent[0], extent[1], 1],
+                tiling: ImageTiling::Optimal,
+                usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+                sharing: sharing.clone(),
+                ..ImageCreateInfo::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .expect("failed to create image");
+        self.depth_image = create_depth_image(
+            self.memory_allocator.clone(),
+            extent,
+            sharing,
+            self.samples,
+        )
+        .expect("failed to create depth image");
+        self.extent = extent;
+        let (pipeline, transparent_pipeline, framebuffer) = construct_offscreen_pipeline(
+            self.memory_allocator.clone(),
+            self.image.clone(),
+            self.depth_image.clone(),
+            self.render_pass.clone(),
+            self.stages.clone(),
+            &self.vertex_buffer_descriptions,
+            self.polygon_mode,
+            self.samples,
+            self.pipeline_cache.clone(),
+        )
+        .expect("failed to rebuild pipeline");
+        self.pipeline = pipeline;
+        self.transparent_pipeline = transparent_pipeline;
+        self.framebuffer = framebuffer;
+        self.rebuild_background_pipeline();
+
+        self.staging_buffers = (0..self.staging_buffers.len())
+            .map(|_| create_staging_buffers(self.memory_allocator.clone(), extent, format))
+            .collect::<Result<_, _>>()
+            .expect("failed to create staging buffers");
+        self.next_staging_buffers = 0;
+        self.pending_frames.clear();
+        self.last_read_frame = 0;
+    }
+
+    /// draws a fullscreen triangle with `stages` (see `create_background_pipeline`) behind everything at the start of
+    /// every render, e.g. a skybox. `None` goes back to the plain clear color
+    pub fn set_background(&mut self, stages: Option<Vec<EntryPoint>>) {
+        self.background_stages = stages;
+        self.rebuild_background_pipeline();
+    }
+
+    /// descriptor sets bound (starting at set 0) when drawing the background, like `set_descriptor_sets`
+    pub fn set_background_descriptor_sets(
+        &mut self,
+        descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+    ) {
+        self.background_descriptor_sets = descriptor_sets;
+    }
+
+    fn rebuild_background_pipeline(&mut self) {
+        self.background_pipeline = self.background_stages.clone().map(|stages| {
+            create_background_pipeline(
+                self.device.clone(),
+                self.render_pass.clone(),
+                stages,
+                Some(self.extent),
+                self.samples,
+                self.pipeline_cache.clone(),
+            )
+            .expect("failed to rebuild background pipeline")
+        });
+    }
+
+    /// descriptor sets bound (starting at set 0) for every following render, e.g. uniform buffers the shaders read.
+    /// they must match the set layouts of the renderer's shaders
+    pub fn set_descriptor_sets(&mut self, descriptor_sets: Vec<Arc<PersistentDescriptorSet>>) {
+        self.descriptor_sets = descriptor_sets;
+    }
+
+    /// draws each set of buffers with its own draw call, all with the same push constants
     pub fn render<Pc, VB>(&mut self, vertex_buffers: VB, push_data: Pc)
+    where
+        Pc: BufferContents + Clone,
+        VB: IntoIterator<Item = DrawBuffers<T>>,
+    {
+        self.render_objects(
+            vertex_buffers
+                .into_iter()
+                .map(|vertex_buffer| (vertex_buffer, push_data.clone())),
+        )
+    }
+
+    /// like `render`, but each draw gets its own push constants
+    pub fn render_objects<Pc, O>(&mut self, objects: O)
+    where
+        Pc: BufferContents,
+        O: IntoIterator<Item = (DrawBuffers<T>, Pc)>,
+    {
+        self.render_objects_with_transparent(objects, std::iter::empty())
+    }
+
+    /// like `render_objects`, but after the opaque objects, draws `transparent` with alpha blending in the order given
+    /// (which should be back to front, see `Scene::transparent_draw_buffers`)
+    pub fn render_objects_with_transparent<Pc, O, TO>(&mut self, objects: O, transparent: TO)
     where
         Pc: BufferContents,
-        VB: IntoIterator<Item = Subbuffer<[T]>>,
+        O: IntoIterator<Item = (DrawBuffers<T>, Pc)>,
+        TO: IntoIterator<Item = (DrawBuffers<T>, Pc)>,
     {
         // free memory
         self.previous_frame_end.as_mut().unwrap().cleanup_finished();
@@ -290,68 +788,756 @@ impl<T> Renderer<T> {
         builder
             .begin_render_pass(
                 RenderPassBeginInfo {
-                    clear_values: vec![Some([0.53, 0.81, 0.92, 1.0].into()), Some(1f32.into())],
+                    // the resolve attachment is overwritten, so it doesn't need clearing
+                    clear_values: match self.samples {
+                        SampleCount::Sample1 => vec![Some(self.clear_color.into()), Some(1f32.into())],
+                        _ => vec![Some(self.clear_color.into()), None, Some(1f32.into())],
+                    },
                     ..RenderPassBeginInfo::framebuffer(self.framebuffer.clone())
                 },
                 Default::default(),
             )
-            .unwrap()
+            .unwrap();
+
+        if let Some(ref background_pipeline) = self.background_pipeline {
+            builder
+                .bind_pipeline_graphics(background_pipeline.clone())
+                .unwrap();
+            if !self.background_descriptor_sets.is_empty() {
+                builder
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        background_pipeline.layout().clone(),
+                        0,
+                        self.background_descriptor_sets.clone(),
+                    )
+                    .unwrap();
+            }
+            builder.draw(3, 1, 0, 0).unwrap();
+        }
+
+        builder
             .bind_pipeline_graphics(self.pipeline.clone())
-            .unwrap()
-            .push_constants(self.pipeline.layout().clone(), 0, push_data)
             .unwrap();
 
-        // for each vertex buffer, bind it and draw
-        for vertex_buffer in vertex_buffers {
+        if !self.descriptor_sets.is_empty() {
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    self.pipeline.layout().clone(),
+                    0,
+                    self.descriptor_sets.clone(),
+                )
+                .unwrap();
+        }
+
+        // for each vertex buffer, bind it and draw. the transparent ones come last, after switching pipelines
+        let mut pipeline = self.pipeline.clone();
+        for (
+            is_transparent,
+            (
+                DrawBuffers {
+                    vertex_buffer,
+                    index_buffer,
+                    instance_buffer,
+                    texture,
+                },
+                push_data,
+            ),
+        ) in objects
+            .into_iter()
+            .map(|o| (false, o))
+            .chain(transparent.into_iter().map(|o| (true, o)))
+        {
+            if is_transparent && !Arc::ptr_eq(&pipeline, &self.transparent_pipeline) {
+                pipeline = self.transparent_pipeline.clone();
+                builder.bind_pipeline_graphics(pipeline.clone()).unwrap();
+                if !self.descriptor_sets.is_empty() {
+                    builder
+                        .bind_descriptor_sets(
+                            PipelineBindPoint::Graphics,
+                            pipeline.layout().clone(),
+                            0,
+                            self.descriptor_sets.clone(),
+                        )
+                        .unwrap();
+                }
+            }
+            // textures go in the set after the renderer's own, if the pipeline's shaders sample one
+            if let Some(texture) = texture {
+                let set = self.descriptor_sets.len();
+                if pipeline.layout().set_layouts().len() > set {
+                    builder
+                        .bind_descriptor_sets(
+                            PipelineBindPoint::Graphics,
+                            pipeline.layout().clone(),
+                            set as u32,
+                            texture,
+                        )
+                        .unwrap();
+                }
+            }
             let vertex_count = vertex_buffer.len() as u32;
+            // plain draws get a single untransformed instance
+            let instance_count = instance_buffer.as_ref().map_or(1, |b| b.len() as u32);
+            let instance_buffer = instance_buffer.unwrap_or_else(|| self.identity_instance.clone());
             builder
-                .bind_vertex_buffers(0, vertex_buffer)
+                .push_constants(pipeline.layout().clone(), 0, push_data)
                 .unwrap()
-                .draw(vertex_count, 1, 0, 0)
+                .bind_vertex_buffers(0, (vertex_buffer, instance_buffer))
                 .unwrap();
+            match index_buffer {
+                Some(index_buffer) => {
+                    let index_count = index_buffer.len() as u32;
+                    builder
+                        .bind_index_buffer(index_buffer)
+                        .unwrap()
+                        .draw_indexed(index_count, instance_count, 0, 0, 0)
+                        .unwrap();
+                }
+                None => {
+                    builder.draw(vertex_count, instance_count, 0, 0).unwrap();
+                }
+            }
         }
 
         builder.end_render_pass(Default::default()).unwrap();
 
-        // we now copy the results of the render to the staging buffer
+        let command_buffer = builder.build().unwrap();
+
+        // pick the slot to copy into. if the ring is full, the oldest unread frame is overwritten
+        let staging_index = self.next_staging_buffers;
+        self.next_staging_buffers = (staging_index + 1) % self.staging_buffers.len();
+        self.pending_frames.retain(|&i| i != staging_index);
+        let staging_buffers = &mut self.staging_buffers[staging_index];
+
+        // we now copy the results of the render to the staging buffers.
+        // this runs on the transfer queue, so it doesn't hold up draws on the graphics queue
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.transfer_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        // `image_buffer` copies with a buffer row length and image height of 0, which vulkan defines as tightly packed:
+        // rows are `width * block_size` bytes apart no matter how the driver lays out the optimally tiled image,
+        // so the staging buffers can be read back row by row without de-striding
         builder
             .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
                 self.image.clone(),
-                self.staging_buffer.clone(),
+                staging_buffers.image.clone(),
             ))
             .unwrap();
+        if self.samples == SampleCount::Sample1 {
+            builder
+                .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                    self.depth_image.clone(),
+                    staging_buffers.depth.clone(),
+                ))
+                .unwrap();
+        }
 
-        let command_buffer = builder.build().unwrap();
+        let copy_command_buffer = builder.build().unwrap();
 
+        // the render has to wait for the previous copy (on the transfer queue) to finish reading the image,
+        // and the copy has to wait for the render to finish, which is done with semaphores
         let future = self
             .previous_frame_end
             .take()
             .unwrap()
+            .then_signal_semaphore()
             .then_execute(self.queue.clone(), command_buffer)
             .unwrap()
-            .boxed()
-            .then_signal_fence_and_flush();
+            .then_signal_semaphore_and_flush()
+            .map_err(Validated::unwrap)
+            .and_then(|future| {
+                future
+                    .then_execute(self.transfer_queue.clone(), copy_command_buffer)
+                    .unwrap()
+                    .boxed()
+                    .then_signal_fence_and_flush()
+                    .map_err(Validated::unwrap)
+            });
 
-        match future.map_err(Validated::unwrap) {
+        match future {
             Ok(future) => {
+                let future = Arc::new(future);
+                staging_buffers.frame_end = Some(future.clone());
+                self.pending_frames.push_back(staging_index);
                 self.previous_frame_end = Some(future);
             }
             Err(e) => {
                 println!("failed to flush future: {e}");
-                self.previous_frame_end =
-                    Some(queued_now_future::now(self.queue.clone()).boxed().then_signal_fence());
+                self.previous_frame_end = Some(Arc::new(
+                    queued_now_future::now(self.transfer_queue.clone())
+                        .boxed()
+                        .then_signal_fence(),
+                ));
             }
         }
     }
 
+    /// returns the pixels of the oldest frame that hasn't been read yet, row by row, in the renderer's `format`
+    /// (`format().block_size()` bytes per pixel), blocking until the GPU has finished rendering it.
+    /// if every frame has been read, returns the last one again
     pub fn get_image(&mut self) -> Vec<u8> {
-        // wait for fence to be signaled
-        self.previous_frame_end
-            .as_mut()
+        self.wait_for_next_frame();
+        // read the staging buffer
+        self.staging_buffers[self.last_read_frame]
+            .image
+            .read()
             .unwrap()
-            .wait(None)
+            .to_vec()
+    }
+
+    /// like `get_image`, but drops the alpha channel, returning 3 bytes per pixel.
+    /// panics if the format isn't 8 bit RGBA
+    pub fn get_image_rgb(&mut self) -> Vec<u8> {
+        assert!(
+            matches!(
+                self.image.format(),
+                Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB
+            ),
+            "can't convert {:?} to RGB",
+            self.image.format()
+        );
+        self.wait_for_next_frame();
+        let rgba = self.staging_buffers[self.last_read_frame]
+            .image
+            .read()
             .unwrap();
+        rgba.chunks_exact(4)
+            .flat_map(|pixel| pixel[..3].iter().copied())
+            .collect()
+    }
+
+    // makes the oldest unread frame the one that is read, blocking until it has been copied into its staging buffers
+    fn wait_for_next_frame(&mut self) {
+        if let Some(index) = self.pending_frames.pop_front() {
+            // wait for fence to be signaled
+            if let Some(frame_end) = self.staging_buffers[index].frame_end.take() {
+                frame_end.wait(None).unwrap();
+            }
+            self.last_read_frame = index;
+        }
+    }
+
+    /// like `get_image`, but returns None instead of blocking if the GPU hasn't finished rendering yet
+    pub fn try_get_image(&mut self) -> Option<Vec<u8>> {
+        // check if fence is signaled
+        if let Some(&index) = self.pending_frames.front() {
+            let signaled = match self.staging_buffers[index].frame_end {
+                Some(ref frame_end) => frame_end.is_signaled().unwrap(),
+                None => true,
+            };
+            if !signaled {
+                return None;
+            }
+        }
+        Some(self.get_image())
+    }
+
+    /// returns the oldest unread frame as an image, blocking until the GPU has finished rendering.
+    /// only available for 8 bit RGBA formats
+    pub fn to_image_buffer(&mut self) -> Result<RgbaImage, ImageError> {
+        let [width, height] = self.extent;
+        if width == 0 || height == 0 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+        if !matches!(
+            self.image.format(),
+            Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB
+        ) {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!(
+                    "can't convert {:?} to RGBA",
+                    self.image.format()
+                )),
+            )));
+        }
+        // the staging buffer is tightly packed, so each row is exactly width * 4 bytes
+        let data = self.get_image();
+        Ok(RgbaImage::from_raw(width, height, data)
+            .expect("staging buffer size does not match extent"))
+    }
+
+    /// encodes the oldest unread frame as a PNG and writes it to `path`
+    pub fn save_png(&mut self, path: &Path) -> Result<(), ImageError> {
+        self.to_image_buffer()?
+            .save_with_format(path, image::ImageFormat::Png)
+    }
+
+    /// returns the depth of each pixel of the frame most recently returned by `get_image`, row by row.
+    /// values are the depth in clip space (after the perspective divide) in the range [0, 1],
+    /// use `Camera::linearize_depth` of the camera the frame was rendered with to convert them to distances
+    pub fn get_depth_data(&mut self) -> Vec<f32> {
+        assert!(
+            self.samples == SampleCount::Sample1,
+            "depth data is not available when multisampling"
+        );
         // read the staging buffer
-        self.staging_buffer.read().unwrap().to_vec()
+        self.staging_buffers[self.last_read_frame]
+            .depth
+            .read()
+            .unwrap()
+            .to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use nalgebra::{Matrix4, Point3, Vector3};
+
+    use crate::camera::{BEVCamera, Camera, FlyCamera};
+    use crate::object;
+    use crate::render_system::scene::Scene;
+    use crate::render_system::testing;
+    use crate::shader::seg_vert::PushConstantData;
+    use crate::vertex::mVertex;
+
+    // like shader::seg_vert, but colors each instance with its own instance color
+    mod instance_color_vert {
+        vulkano_shaders::shader! {
+        ty: "vertex",
+            src: "
+#version 450
+layout(location = 0) in vec3 loc;
+// per instance, see scene::InstanceData
+layout(location = 3) in mat4 model;
+layout(location = 7) in vec4 instance_color;
+
+layout(push_constant) uniform PushConstantData {
+    mat4 mvp;
+} pc;
+
+layout(location = 0) flat out vec4 fragColor;
+void main() {
+    gl_Position = pc.mvp * model * vec4(loc, 1.0);
+    fragColor = instance_color;
+}"
+        }
+    }
+
+    const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+    const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+    fn to_bytes(color: [f32; 4]) -> [u8; 4] {
+        color.map(|c| (c * 255.0) as u8)
+    }
+
+    // a renderer that draws flat colored geometry on a blue background
+    fn flat_renderer(
+        (queue, transfer_queue, memory_allocator): &(Arc<Queue>, Arc<Queue>, Arc<StandardMemoryAllocator>),
+        extent: [u32; 2],
+        staging_buffer_count: usize,
+        shared_depth_image: Option<Arc<Image>>,
+    ) -> Renderer<mVertex> {
+        let device = queue.device().clone();
+        let stages = vec![
+            crate::shader::seg_vert::load(device.clone())
+                .unwrap()
+                .entry_point("main")
+                .unwrap(),
+            crate::shader::seg_frag::load(device)
+                .unwrap()
+                .entry_point("main")
+                .unwrap(),
+        ];
+        let mut renderer = Renderer::new(
+            extent,
+            Format::R8G8B8A8_UNORM,
+            staging_buffer_count,
+            stages,
+            queue.clone(),
+            transfer_queue.clone(),
+            memory_allocator.clone(),
+            shared_depth_image,
+            None,
+        );
+        renderer.set_clear_color(BLUE);
+        renderer
+    }
+
+    // the unit cube, pushed back so its front face is at depth 0 and covering the middle half of the image
+    fn red_cube(
+        memory_allocator: Arc<StandardMemoryAllocator>,
+    ) -> (DrawBuffers<mVertex>, PushConstantData) {
+        red_cube_seen_by(
+            memory_allocator,
+            Matrix4::new_translation(&Vector3::new(0.0, 0.0, 0.5)),
+        )
+    }
+
+    // the unit cube at the origin, drawn with `mvp`
+    fn red_cube_seen_by(
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        mvp: Matrix4<f32>,
+    ) -> (DrawBuffers<mVertex>, PushConstantData) {
+        let vertex_buffer = Buffer::from_iter(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            object::unitcube(),
+        )
+        .unwrap();
+        let draw_buffers = DrawBuffers {
+            vertex_buffer,
+            index_buffer: None,
+            instance_buffer: None,
+            texture: None,
+        };
+        let push_data = PushConstantData {
+            mvp: mvp.into(),
+            color: RED,
+        };
+        (draw_buffers, push_data)
+    }
+
+    fn render_nothing(renderer: &mut Renderer<mVertex>) {
+        renderer.render_objects(std::iter::empty::<(DrawBuffers<mVertex>, PushConstantData)>());
+    }
+
+    fn pixel(image: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * width + x) * 4) as usize;
+        image[i..i + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn try_get_image_returns_the_render() {
+        let device = match testing::headless_device() {
+            Some(device) => device,
+            None => return,
+        };
+        let mut renderer = flat_renderer(&device, [16, 16], 1, None);
+        renderer.render_objects([red_cube(device.2.clone())]);
+        let image = loop {
+            if let Some(image) = renderer.try_get_image() {
+                break image;
+            }
+        };
+        assert_eq!(pixel(&image, 16, 8, 8), to_bytes(RED));
+        assert_eq!(pixel(&image, 16, 0, 0), to_bytes(BLUE));
+    }
+
+    #[test]
+    fn odd_extent_is_read_back_tightly_packed() {
+        let device = match testing::headless_device() {
+            Some(device) => device,
+            None => return,
+        };
+        let mut renderer = flat_renderer(&device, [37, 19], 1, None);
+        renderer.render_objects([red_cube(device.2.clone())]);
+        let image = renderer.get_image();
+        assert_eq!(image.len(), 37 * 19 * 4);
+
+        // if the rows were padded, the cube would drift sideways from row to row
+        for y in 0..19 {
+            assert_eq!(pixel(&image, 37, 0, y), to_bytes(BLUE), "row {y}");
+            assert_eq!(pixel(&image, 37, 36, y), to_bytes(BLUE), "row {y}");
+        }
+        for y in 6..13 {
+            assert_eq!(pixel(&image, 37, 18, y), to_bytes(RED), "row {y}");
+        }
+    }
+
+    #[test]
+    fn frames_are_read_oldest_first() {
+        let device = match testing::headless_device() {
+            Some(device) => device,
+            None => return,
+        };
+        let mut renderer = flat_renderer(&device, [4, 4], 3, None);
+        let colors = [RED, BLUE, [0.0, 1.0, 0.0, 1.0], [1.0, 1.0, 1.0, 1.0]];
+
+        // all three slots fill up without reading anything in between
+        for color in &colors[..3] {
+            renderer.set_clear_color(*color);
+            render_nothing(&mut renderer);
+        }
+        for color in &colors[..3] {
+            assert_eq!(pixel(&renderer.get_image(), 4, 0, 0), to_bytes(*color));
+        }
+        // once everything has been read, the last frame is returned again
+        assert_eq!(pixel(&renderer.get_image(), 4, 0, 0), to_bytes(colors[2]));
+
+        // a full ring overwrites its oldest unread frame
+        for color in &colors {
+            renderer.set_clear_color(*color);
+            render_nothing(&mut renderer);
+        }
+        for color in &colors[1..] {
+            assert_eq!(pixel(&renderer.get_image(), 4, 0, 0), to_bytes(*color));
+        }
+    }
+
+    #[test]
+    fn renderers_can_share_a_depth_image() {
+        let device = match testing::headless_device() {
+            Some(device) => device,
+            None => return,
+        };
+        let mut first = flat_renderer(&device, [16, 16], 1, None);
+        let mut second = flat_renderer(&device, [16, 16], 1, Some(first.depth_image()));
+        assert!(Arc::ptr_eq(&first.depth_image(), &second.depth_image()));
+
+        first.render_objects([red_cube(device.2.clone())]);
+        first.get_image();
+        let center = (8 * 16 + 8) as usize;
+        assert!(first.get_depth_data()[center] < 0.5);
+
+        // the second renderer clears the shared image before drawing
+        render_nothing(&mut second);
+        second.get_image();
+        assert_eq!(second.get_depth_data()[center], 1.0);
+        // the first renderer's copy was already read back, so it's unaffected
+        assert!(first.get_depth_data()[center] < 0.5);
+    }
+
+    #[test]
+    fn rgb_image_drops_alpha() {
+        let device = match testing::headless_device() {
+            Some(device) => device,
+            None => return,
+        };
+        let mut renderer = flat_renderer(&device, [5, 3], 1, None);
+        renderer.set_clear_color([1.0, 0.0, 1.0, 0.0]);
+        render_nothing(&mut renderer);
+        let image = renderer.get_image_rgb();
+        assert_eq!(image.len(), 5 * 3 * 3);
+        assert_eq!(image[..3], [255, 0, 255]);
+    }
+
+    #[test]
+    fn instanced_cubes_are_drawn_at_each_instance() {
+        let (queue, transfer_queue, memory_allocator) = match testing::headless_device() {
+            Some(device) => device,
+            None => return,
+        };
+        let device = queue.device().clone();
+        let stages = vec![
+            instance_color_vert::load(device.clone())
+                .unwrap()
+                .entry_point("main")
+                .unwrap(),
+            crate::shader::seg_frag::load(device)
+                .unwrap()
+                .entry_point("main")
+                .unwrap(),
+        ];
+        let mut renderer: Renderer<mVertex> = Renderer::new(
+            [100, 100],
+            Format::R8G8B8A8_UNORM,
+            1,
+            stages,
+            queue,
+            transfer_queue,
+            memory_allocator.clone(),
+            None,
+            None,
+        );
+        renderer.set_clear_color(BLUE);
+
+        // a 10 by 10 grid of small cubes, one per 10 by 10 pixel cell
+        let color = |i: u32, j: u32| [(i % 2) as f32, (j % 2) as f32, 0.0, 1.0];
+        let instances = (0..10)
+            .flat_map(|j| (0..10).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let center = Vector3::new(-0.9 + 0.2 * i as f32, -0.9 + 0.2 * j as f32, 0.5);
+                let model = Matrix4::new_translation(&center) * Matrix4::new_scaling(0.1);
+                InstanceData::new(model, color(i, j))
+            })
+            .collect();
+        let mut scene = Scene::new(memory_allocator, HashMap::new());
+        scene.add_instanced(0u32, object::unitcube(), instances);
+
+        // the mesh is uploaded once, with all the instances in one buffer
+        let draw_buffers = scene.instanced_draw_buffers();
+        assert_eq!(draw_buffers.len(), 1);
+        let (_, draw_buffers) = draw_buffers.into_iter().next().unwrap();
+        assert_eq!(draw_buffers.vertex_buffer.len(), 36);
+        assert_eq!(draw_buffers.instance_buffer.as_ref().unwrap().len(), 100);
+
+        renderer.render_objects([(
+            draw_buffers,
+            instance_color_vert::PushConstantData {
+                mvp: Matrix4::identity().into(),
+            },
+        )]);
+        let image = renderer.get_image();
+        for (i, j) in [(0, 0), (1, 0), (0, 1), (5, 4), (9, 9), (3, 8)] {
+            assert_eq!(
+                pixel(&image, 100, 10 * i + 5, 10 * j + 5),
+                to_bytes(color(i, j)),
+                "instance ({i}, {j})"
+            );
+        }
+        // the gaps between the cells stay clear
+        assert_eq!(pixel(&image, 100, 0, 0), to_bytes(BLUE));
+    }
+
+    #[test]
+    fn cameras_linearize_their_own_depth() {
+        let device = match testing::headless_device() {
+            Some(device) => device,
+            None => return,
+        };
+        // perspective depth is raw ndc z, orthographic depth goes through vk_depth_correction,
+        // so each camera has to undo its own projection. both see the cube's nearest face 3.5 units away
+        let perspective = FlyCamera::new(Point3::new(-4.0, 0.0, 0.0));
+        let orthographic = BEVCamera::top_down(Point3::new(0.0, 3.0, 0.0), 4.0, 0.0, 10.0);
+        let cameras: [&dyn Camera; 2] = [&perspective, &orthographic];
+        for camera in cameras {
+            let mut renderer = flat_renderer(&device, [16, 16], 1, None);
+            renderer.render_objects([red_cube_seen_by(device.2.clone(), camera.mvp([16, 16]))]);
+            renderer.get_image();
+            let depth = renderer.get_depth_data()[8 * 16 + 8];
+            let distance = camera.linearize_depth(depth);
+            assert!((distance - 3.5).abs() < 1e-2, "cube is {distance} away");
+        }
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+    fn benchmark_one_draw_against_one_per_object() {
+        let device = match testing::headless_device() {
+            Some(device) => device,
+            None => return,
+        };
+        let mut renderer = flat_renderer(&device, [256, 256], 1, None);
+        let mut scene = Scene::new(device.2.clone(), HashMap::new());
+        for key in 0..500u32 {
+            let center = Point3::new((key % 25) as f32 * 0.08 - 1.0, (key / 25) as f32 * 0.1 - 1.0, 0.5);
+            scene.add_object(key, object::cuboid(center, Vector3::new(0.05, 0.05, 0.05)));
+        }
+        let push_data = PushConstantData {
+            mvp: Matrix4::identity().into(),
+            color: RED,
+        };
+        let frames = 100;
+
+        // only building and submitting the command buffers is timed, not the gpu work
+        let mut time_frames = |draws: Vec<DrawBuffers<mVertex>>| {
+            let mut elapsed = std::time::Duration::ZERO;
+            for _ in 0..frames {
+                let start = std::time::Instant::now();
+                renderer.render(draws.clone(), push_data.clone());
+                elapsed += start.elapsed();
+                renderer.get_image();
+            }
+            elapsed
+        };
+        let combined = time_frames(scene.draw_buffers().into_iter().collect());
+        let per_object = time_frames(
+            scene
+                .object_draw_buffers()
+                .into_iter()
+                .map(|(_, draw_buffers)| draw_buffers)
+                .collect(),
+        );
+
+        println!("{frames} frames of 500 objects: one draw {combined:?}, one draw per object {per_object:?}");
+        assert!(combined < per_object);
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+    fn benchmark_staging_ring_against_single_buffer() {
+        let device = match testing::headless_device() {
+            Some(device) => device,
+            None => return,
+        };
+        let frames = 100;
+        let extent = [1024, 1024];
+
+        // renders and reads each frame before starting the next
+        let mut single = flat_renderer(&device, extent, 1, None);
+        let start = std::time::Instant::now();
+        for _ in 0..frames {
+            single.render_objects([red_cube(device.2.clone())]);
+            single.get_image();
+        }
+        let single_elapsed = start.elapsed();
+
+        // keeps two frames in flight, reading each one while the next renders
+        let mut ring = flat_renderer(&device, extent, 3, None);
+        let start = std::time::Instant::now();
+        for frame in 0..frames {
+            ring.render_objects([red_cube(device.2.clone())]);
+            if frame >= 2 {
+                ring.get_image();
+            }
+        }
+        for _ in 0..2 {
+            ring.get_image();
+        }
+        let ring_elapsed = start.elapsed();
+
+        println!("{frames} frames: single staging buffer {single_elapsed:?}, ring of 3 {ring_elapsed:?}");
+        assert!(ring_elapsed < single_elapsed);
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+    fn benchmark_pipeline_cache() {
+        let (queue, transfer_queue, memory_allocator) = match testing::headless_device() {
+            Some(device) => device,
+            None => return,
+        };
+        let device = queue.device().clone();
+        // the lit shaders, like the scene renderers use
+        let stages = vec![
+            crate::shader::vert::load(device.clone())
+                .unwrap()
+                .entry_point("main")
+                .unwrap(),
+            crate::shader::frag::load(device.clone())
+                .unwrap()
+                .entry_point("main")
+                .unwrap(),
+        ];
+        let create_renderers = |pipeline_cache: Option<Arc<PipelineCache>>| {
+            let start = std::time::Instant::now();
+            for _ in 0..16 {
+                Renderer::<mVertex>::new(
+                    [256, 256],
+                    Format::R8G8B8A8_UNORM,
+                    1,
+                    stages.clone(),
+                    queue.clone(),
+                    transfer_queue.clone(),
+                    memory_allocator.clone(),
+                    None,
+                    pipeline_cache.clone(),
+                );
+            }
+            start.elapsed()
+        };
+
+        // the cached run goes first, so the driver's own caching (if any) can only favor the uncached one
+        let pipeline_cache = unsafe {
+            PipelineCache::new(
+                device,
+                vulkano::pipeline::cache::PipelineCacheCreateInfo::default(),
+            )
+        }
+        .unwrap();
+        let cached = create_renderers(Some(pipeline_cache.clone()));
+        let uncached = create_renderers(None);
+
+        println!("16 offscreen renderers: with a shared pipeline cache {cached:?}, without {uncached:?}");
+        assert!(!pipeline_cache.get_data().unwrap().is_empty());
     }
 }
@@ -1,15 +1,127 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, ops::Range, sync::Arc};
+
+use nalgebra::{Isometry3, Matrix4, Point3, Vector4};
 
 use vulkano::{
     buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    descriptor_set::PersistentDescriptorSet,
     memory::allocator::{AllocationCreateInfo, MemoryAllocator, MemoryTypeFilter},
 };
 
+/// vertices that have a location in space, so that the scene can tell where objects are
+pub trait HasPosition {
+    fn position(&self) -> [f32; 3];
+}
+
+struct SceneObject<Vertex> {
+    vertices: Vec<Vertex>,
+    // if not specified, the vertices are drawn in order as a triangle list
+    indices: Option<Vec<u32>>,
+    // where this object's data lives in the scene's combined buffers (set when the buffers are built)
+    vertex_range: Range<u64>,
+    index_range: Range<u64>,
+    // if true, the data changed but still fits in its ranges, so only its slice of the buffers needs rewriting
+    dirty: bool,
+    // hidden objects keep their data but are left out of the buffers
+    visible: bool,
+    // transparent objects are drawn after the opaque ones, sorted back to front
+    transparent: bool,
+    // textured objects are drawn on their own, with this bound as their texture
+    texture: Option<Arc<PersistentDescriptorSet>>,
+    // (min, max) corners of the object's bounding box, computed when first needed
+    aabb: Option<([f32; 3], [f32; 3])>,
+}
+
+impl<Vertex> SceneObject<Vertex> {
+    fn new(vertices: Vec<Vertex>, indices: Option<Vec<u32>>) -> SceneObject<Vertex> {
+        SceneObject {
+            vertices,
+            indices,
+            vertex_range: 0..0,
+            index_range: 0..0,
+            dirty: false,
+            visible: true,
+            transparent: false,
+            texture: None,
+            aabb: None,
+        }
+    }
+
+    // whether `other` takes up exactly as much space in the combined buffers as this object
+    fn same_layout(&self, other: &SceneObject<Vertex>) -> bool {
+        self.vertices.len() == other.vertices.len()
+            && self.indices.as_ref().map(Vec::len) == other.indices.as_ref().map(Vec::len)
+    }
+}
+
+/// geometry that can be bound and drawn with a single draw call
+#[derive(Clone)]
+pub struct DrawBuffers<Vertex> {
+    pub vertex_buffer: Subbuffer<[Vertex]>,
+    // if specified, the vertices are drawn with `draw_indexed`
+    pub index_buffer: Option<Subbuffer<[u32]>>,
+    // if specified, the geometry is drawn once per instance
+    pub instance_buffer: Option<Subbuffer<[InstanceData]>>,
+    // if specified, bound as the descriptor set after the renderer's own (if its shaders use one)
+    pub texture: Option<Arc<PersistentDescriptorSet>>,
+}
+
+/// where and in what color to draw one copy of an instanced mesh.
+/// bound at the second vertex binding (with the instance input rate) of every pipeline,
+/// so the field names must match the vertex shaders' inputs
+#[repr(C)]
+#[derive(BufferContents, vulkano::pipeline::graphics::vertex_input::Vertex, Clone, Copy, Debug)]
+pub struct InstanceData {
+    // transformation from the mesh's coordinates to world space
+    #[format(R32G32B32A32_SFLOAT)]
+    pub model: [[f32; 4]; 4],
+    // multiplied with the mesh's vertex colors
+    #[format(R32G32B32A32_SFLOAT)]
+    pub instance_color: [f32; 4],
+}
+
+impl InstanceData {
+    pub fn new(model: Matrix4<f32>, instance_color: [f32; 4]) -> InstanceData {
+        InstanceData {
+            model: model.into(),
+            instance_color,
+        }
+    }
+
+    /// an instance moved and rotated by `isometry`, keeping the mesh's own colors
+    pub fn from_isometry(isometry: &Isometry3<f32>) -> InstanceData {
+        InstanceData::new(isometry.to_homogeneous(), [1.0; 4])
+    }
+}
+
+/// a buffer with a single instance that leaves the mesh unchanged, for draws that aren't instanced
+pub(crate) fn identity_instance_buffer(
+    memory_allocator: Arc<dyn MemoryAllocator>,
+) -> Subbuffer<[InstanceData]> {
+    upload(
+        memory_allocator,
+        BufferUsage::VERTEX_BUFFER,
+        vec![InstanceData::new(Matrix4::identity(), [1.0; 4])],
+    )
+}
+
+// a mesh that is uploaded once and drawn at many places
+struct InstancedObject<Vertex> {
+    vertex_buffer: Subbuffer<[Vertex]>,
+    // None if there are no instances, since empty buffers aren't allowed
+    instance_buffer: Option<Subbuffer<[InstanceData]>>,
+}
+
 pub struct Scene<K, Vertex> {
-    objects: HashMap<K, Vec<Vertex>>,
+    objects: HashMap<K, SceneObject<Vertex>>,
+    // kept apart from `objects`, and drawn with `instanced_draw_buffers`
+    instanced_objects: HashMap<K, InstancedObject<Vertex>>,
     memory_allocator: Arc<dyn MemoryAllocator>,
-    vertex_buffer: Option<Subbuffer<[Vertex]>>,
-    vertex_buffer_needs_update: bool,
+    draw_buffers: Option<DrawBuffers<Vertex>>,
+    // the combined buffers have to be rebuilt from scratch
+    draw_buffers_need_update: bool,
+    // some objects' slices of the combined buffers have to be rewritten
+    objects_dirty: bool,
 }
 
 #[allow(dead_code)]
@@ -22,71 +134,657 @@ where
         memory_allocator: Arc<dyn MemoryAllocator>,
         objects: HashMap<K, Vec<Vertex>>,
     ) -> Scene<K, Vertex> {
-        Scene {
-            vertex_buffer: vertex_buffer(memory_allocator.clone(), objects.values()),
+        let objects: HashMap<K, SceneObject<Vertex>> = objects
+            .into_iter()
+            .map(|(key, vertices)| (key, SceneObject::new(vertices, None)))
+            .collect();
+        let mut scene = Scene {
+            draw_buffers: None,
             objects,
+            instanced_objects: HashMap::new(),
             memory_allocator,
-            vertex_buffer_needs_update: false,
-        }
+            draw_buffers_need_update: false,
+            objects_dirty: false,
+        };
+        scene.draw_buffers = draw_buffers(scene.memory_allocator.clone(), scene.objects.values_mut());
+        scene
     }
 
     pub fn add_object(&mut self, key: K, object: Vec<Vertex>) {
-        self.objects.insert(key, object);
-        self.vertex_buffer_needs_update = true;
+        self.insert_object(key, SceneObject::new(object, None));
+    }
+
+    /// adds an object whose triangles are given by indices into its own vertices
+    pub fn add_indexed_object(&mut self, key: K, object: Vec<Vertex>, indices: Vec<u32>) {
+        self.insert_object(key, SceneObject::new(object, Some(indices)));
+    }
+
+    // replacing an object with one of the same size (e.g. a moved mesh) only rewrites its own slice of the buffers,
+    // anything else rebuilds them
+    fn insert_object(&mut self, key: K, mut object: SceneObject<Vertex>) {
+        match self.objects.get_mut(&key) {
+            Some(existing) if !self.draw_buffers_need_update && existing.same_layout(&object) => {
+                existing.vertices = object.vertices;
+                existing.indices = object.indices;
+                existing.aabb = None;
+                existing.dirty = true;
+                self.objects_dirty = true;
+            }
+            existing => {
+                // replacing an object keeps it hidden (or transparent, or textured) if it was
+                if let Some(existing) = existing {
+                    object.visible = existing.visible;
+                    object.transparent = existing.transparent;
+                    object.texture = existing.texture.clone();
+                }
+                self.objects.insert(key, object);
+                self.draw_buffers_need_update = true;
+            }
+        }
+    }
+
+    /// hides or shows an object without removing its data
+    pub fn set_visible(&mut self, key: &K, visible: bool) {
+        if let Some(object) = self.objects.get_mut(key) {
+            if object.visible != visible {
+                object.visible = visible;
+                self.draw_buffers_need_update = true;
+            }
+        }
+    }
+
+    /// transparent objects are left out of `draw_buffers` and `object_draw_buffers`,
+    /// and are drawn from `transparent_draw_buffers` instead
+    pub fn set_transparent(&mut self, key: &K, transparent: bool) {
+        if let Some(object) = self.objects.get_mut(key) {
+            if object.transparent != transparent {
+                object.transparent = transparent;
+                self.draw_buffers_need_update = true;
+            }
+        }
+    }
+
+    /// textured objects are left out of `draw_buffers`, and are drawn from `textured_draw_buffers` instead
+    /// (or `transparent_draw_buffers`, if they are also transparent). `texture` must be a descriptor set holding
+    /// the texture in the layout the renderer's shaders expect
+    pub fn set_texture(&mut self, key: &K, texture: Option<Arc<PersistentDescriptorSet>>) {
+        if let Some(object) = self.objects.get_mut(key) {
+            object.texture = texture;
+            self.draw_buffers_need_update = true;
+        }
     }
 
     pub fn remove_object(&mut self, key: K) {
         let removed = self.objects.remove(&key);
         if removed.is_some() {
-            self.vertex_buffer_needs_update = true;
+            self.draw_buffers_need_update = true;
+        }
+    }
+
+    /// adds a mesh that is drawn once per element of `instances`. the mesh is uploaded once, however many instances there are.
+    /// instanced objects have their own keys: they aren't affected by `remove_object`, `set_visible` or `set_transparent`
+    pub fn add_instanced(&mut self, key: K, mesh: Vec<Vertex>, instances: Vec<InstanceData>) {
+        if mesh.is_empty() {
+            self.instanced_objects.remove(&key);
+            return;
+        }
+        let vertex_buffer = upload(self.memory_allocator.clone(), BufferUsage::VERTEX_BUFFER, mesh);
+        let instance_buffer = upload_instances(self.memory_allocator.clone(), instances);
+        self.instanced_objects.insert(
+            key,
+            InstancedObject {
+                vertex_buffer,
+                instance_buffer,
+            },
+        );
+    }
+
+    /// replaces the instances of an instanced object, without uploading its mesh again
+    pub fn set_instances(&mut self, key: &K, instances: Vec<InstanceData>) {
+        if let Some(object) = self.instanced_objects.get_mut(key) {
+            object.instance_buffer = upload_instances(self.memory_allocator.clone(), instances);
+        }
+    }
+
+    pub fn remove_instanced(&mut self, key: &K) {
+        self.instanced_objects.remove(key);
+    }
+
+    /// buffers that draw every instance of each instanced object, one draw call per object
+    pub fn instanced_draw_buffers(&self) -> Vec<(K, DrawBuffers<Vertex>)>
+    where
+        K: Clone,
+    {
+        self.instanced_objects
+            .iter()
+            .filter_map(|(key, o)| {
+                let draw_buffers = DrawBuffers {
+                    vertex_buffer: o.vertex_buffer.clone(),
+                    index_buffer: None,
+                    instance_buffer: Some(o.instance_buffer.clone()?),
+                    texture: None,
+                };
+                Some((key.clone(), draw_buffers))
+            })
+            .collect()
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = (&K, &Vec<Vertex>)> {
+        self.objects.iter().map(|(key, o)| (key, &o.vertices))
+    }
+
+    /// buffers that draw all the visible opaque untextured objects in one draw call
+    pub fn draw_buffers(&mut self) -> Option<DrawBuffers<Vertex>> {
+        let draw_buffers = self.all_draw_buffers()?;
+        // the opaque untextured objects come first in the combined buffers, so they're drawn by a prefix of them
+        let opaque = self
+            .objects
+            .values()
+            .filter(|o| o.visible && !o.transparent && o.texture.is_none());
+        match draw_buffers.index_buffer {
+            Some(index_buffer) => {
+                let end = opaque.map(|o| o.index_range.end).max().unwrap_or(0);
+                if end == 0 {
+                    return None;
+                }
+                Some(DrawBuffers {
+                    vertex_buffer: draw_buffers.vertex_buffer,
+                    index_buffer: Some(index_buffer.slice(0..end)),
+                    instance_buffer: None,
+                    texture: None,
+                })
+            }
+            None => {
+                let end = opaque.map(|o| o.vertex_range.end).max().unwrap_or(0);
+                if end == 0 {
+                    return None;
+                }
+                Some(DrawBuffers {
+                    vertex_buffer: draw_buffers.vertex_buffer.slice(0..end),
+                    index_buffer: None,
+                    instance_buffer: None,
+                    texture: None,
+                })
+            }
+        }
+    }
+
+    // the combined buffers of every visible object, transparent or not
+    fn all_draw_buffers(&mut self) -> Option<DrawBuffers<Vertex>> {
+        if self.objects_dirty && !self.draw_buffers_need_update {
+            // if the buffers are still in use by the gpu we can't write to them, so rebuild instead
+            let updated = match self.draw_buffers {
+                Some(ref draw_buffers) => update_in_place(draw_buffers, self.objects.values_mut()),
+                None => false,
+            };
+            self.draw_buffers_need_update = !updated;
+        }
+        self.objects_dirty = false;
+        if self.draw_buffers_need_update {
+            self.draw_buffers =
+                draw_buffers(self.memory_allocator.clone(), self.objects.values_mut());
+            self.draw_buffers_need_update = false;
+        }
+        return self.draw_buffers.clone();
+    }
+
+    /// buffers that draw each object on its own, for when each object needs different push constants.
+    /// these are views into the scene's combined buffers, so no extra memory is used
+    pub fn object_draw_buffers(&mut self) -> Vec<(K, DrawBuffers<Vertex>)>
+    where
+        K: Clone,
+    {
+        self.draw_buffers_of(|o| !o.transparent)
+    }
+
+    // views into the combined buffers for each visible object matching `filter`
+    fn draw_buffers_of(
+        &mut self,
+        filter: impl Fn(&SceneObject<Vertex>) -> bool,
+    ) -> Vec<(K, DrawBuffers<Vertex>)>
+    where
+        K: Clone,
+    {
+        let draw_buffers = match self.all_draw_buffers() {
+            Some(draw_buffers) => draw_buffers,
+            None => return vec![],
+        };
+        self.objects
+            .iter()
+            .filter(|(_, o)| o.visible && o.vertices.len() > 0 && filter(o))
+            .map(|(key, o)| {
+                let object_draw_buffers = match draw_buffers.index_buffer {
+                    // the indices are relative to the whole vertex buffer
+                    Some(ref index_buffer) => DrawBuffers {
+                        vertex_buffer: draw_buffers.vertex_buffer.clone(),
+                        index_buffer: Some(index_buffer.clone().slice(o.index_range.clone())),
+                        instance_buffer: None,
+                        texture: o.texture.clone(),
+                    },
+                    None => DrawBuffers {
+                        vertex_buffer: draw_buffers
+                            .vertex_buffer
+                            .clone()
+                            .slice(o.vertex_range.clone()),
+                        index_buffer: None,
+                        instance_buffer: None,
+                        texture: o.texture.clone(),
+                    },
+                };
+                (key.clone(), object_draw_buffers)
+            })
+            .collect()
+    }
+
+    /// buffers for each visible opaque textured object, each with its texture
+    pub fn textured_draw_buffers(&mut self) -> Vec<(K, DrawBuffers<Vertex>)>
+    where
+        K: Clone,
+    {
+        self.draw_buffers_of(|o| !o.transparent && o.texture.is_some())
+    }
+
+    /// like `draw_buffers`, but leaves out the objects that are entirely outside the view volume of `view_proj`.
+    /// objects next to each other in the combined buffers are still drawn together, so if nothing is culled
+    /// this is the same single draw as `draw_buffers`
+    pub fn visible_draw_buffers(&mut self, view_proj: Matrix4<f32>) -> Vec<DrawBuffers<Vertex>>
+    where
+        Vertex: HasPosition,
+    {
+        let draw_buffers = match self.all_draw_buffers() {
+            Some(draw_buffers) => draw_buffers,
+            None => return vec![],
+        };
+        self.compute_aabbs();
+        let planes = frustum_planes(&view_proj);
+        let indexed = draw_buffers.index_buffer.is_some();
+        let mut ranges = self
+            .objects
+            .values()
+            .filter(|o| o.visible && !o.transparent && o.texture.is_none() && o.vertices.len() > 0)
+            .filter(|o| aabb_in_frustum(o.aabb.unwrap(), &planes))
+            .map(|o| if indexed { o.index_range.clone() } else { o.vertex_range.clone() })
+            .filter(|range| !range.is_empty())
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|range| range.start);
+        // merge the ranges that touch, so each run of objects is one draw
+        let mut merged: Vec<Range<u64>> = vec![];
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => merged.push(range),
+            }
         }
+        merged
+            .into_iter()
+            .map(|range| match draw_buffers.index_buffer {
+                Some(ref index_buffer) => DrawBuffers {
+                    vertex_buffer: draw_buffers.vertex_buffer.clone(),
+                    index_buffer: Some(index_buffer.clone().slice(range)),
+                    instance_buffer: None,
+                    texture: None,
+                },
+                None => DrawBuffers {
+                    vertex_buffer: draw_buffers.vertex_buffer.clone().slice(range),
+                    index_buffer: None,
+                    instance_buffer: None,
+                    texture: None,
+                },
+            })
+            .collect()
     }
 
-    pub fn objects(&self) -> &HashMap<K, Vec<Vertex>> {
-        &self.objects
+    /// like `object_draw_buffers`, but leaves out the objects that are entirely outside the view volume of `view_proj`
+    pub fn visible_object_draw_buffers(
+        &mut self,
+        view_proj: Matrix4<f32>,
+    ) -> Vec<(K, DrawBuffers<Vertex>)>
+    where
+        K: Clone,
+        Vertex: HasPosition,
+    {
+        self.compute_aabbs();
+        let planes = frustum_planes(&view_proj);
+        self.object_draw_buffers()
+            .into_iter()
+            .filter(|(key, _)| aabb_in_frustum(self.objects[key].aabb.unwrap(), &planes))
+            .collect()
     }
 
-    pub fn vertex_buffer(&mut self) -> Option<Subbuffer<[Vertex]>> {
-        if self.vertex_buffer_needs_update {
-            self.vertex_buffer =
-                vertex_buffer(self.memory_allocator.clone(), self.objects.values());
-            self.vertex_buffer_needs_update = false;
+    /// buffers for each visible transparent object with its squared distance from `eye`, sorted back to front so that
+    /// they blend correctly (the distances let the objects of several scenes be merged in order).
+    /// objects are sorted by the centers of their bounding boxes, so intersecting or nested transparent objects
+    /// may still be drawn in the wrong order
+    pub fn transparent_draw_buffers(
+        &mut self,
+        eye: Point3<f32>,
+    ) -> Vec<(K, f32, DrawBuffers<Vertex>)>
+    where
+        K: Clone,
+        Vertex: HasPosition,
+    {
+        self.compute_aabbs();
+        let mut buffers = self
+            .draw_buffers_of(|o| o.transparent)
+            .into_iter()
+            .map(|(key, buffers)| {
+                let (min, max) = self.objects[&key].aabb.unwrap();
+                let center = Point3::from([0, 1, 2].map(|i| (min[i] + max[i]) / 2.0));
+                (key, (center - eye).norm_squared(), buffers)
+            })
+            .collect::<Vec<_>>();
+        buffers.sort_by(|a, b| b.1.total_cmp(&a.1));
+        buffers
+    }
+
+    fn compute_aabbs(&mut self)
+    where
+        Vertex: HasPosition,
+    {
+        for o in self.objects.values_mut().filter(|o| o.aabb.is_none()) {
+            o.aabb = Some(aabb(&o.vertices));
         }
-        return self.vertex_buffer.clone();
     }
 }
 
-fn vertex_buffer<'a, Vertex, Container>(
+// axis aligned bounding box of the vertices, as (min, max) corners
+fn aabb<Vertex: HasPosition>(vertices: &[Vertex]) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for v in vertices {
+        let p = v.position();
+        for i in 0..3 {
+            min[i] = min[i].min(p[i]);
+            max[i] = max[i].max(p[i]);
+        }
+    }
+    (min, max)
+}
+
+// the six planes bounding the view volume of `view_proj` (with vulkan's 0 to 1 depth range), as (a, b, c, d) such that
+// a point (x, y, z) is inside the plane if ax + by + cz + d >= 0
+fn frustum_planes(view_proj: &Matrix4<f32>) -> [Vector4<f32>; 6] {
+    let row = |i: usize| view_proj.row(i).transpose();
+    [
+        row(3) + row(0), // left
+        row(3) - row(0), // right
+        row(3) + row(1), // bottom
+        row(3) - row(1), // top
+        row(2),          // near
+        row(3) - row(2), // far
+    ]
+}
+
+// whether the box intersects the frustum (conservatively: boxes near the corners may be kept even if outside)
+fn aabb_in_frustum((min, max): ([f32; 3], [f32; 3]), planes: &[Vector4<f32>; 6]) -> bool {
+    planes.iter().all(|plane| {
+        // the corner of the box furthest along the plane's normal
+        let corner = [0, 1, 2].map(|i| if plane[i] >= 0.0 { max[i] } else { min[i] });
+        plane[0] * corner[0] + plane[1] * corner[1] + plane[2] * corner[2] + plane[3] >= 0.0
+    })
+}
+
+fn draw_buffers<'a, Vertex, Container>(
     memory_allocator: Arc<dyn MemoryAllocator>,
     objects: Container,
-) -> Option<Subbuffer<[Vertex]>>
+) -> Option<DrawBuffers<Vertex>>
 where
-    Container: IntoIterator<Item = &'a Vec<Vertex>>,
+    Container: IntoIterator<Item = &'a mut SceneObject<Vertex>>,
     Vertex: Clone + BufferContents,
 {
+    let mut objects = objects.into_iter().collect::<Vec<_>>();
+    // hidden objects get empty ranges
+    for o in objects.iter_mut().filter(|o| !o.visible) {
+        o.vertex_range = 0..0;
+        o.index_range = 0..0;
+        o.dirty = false;
+    }
+    objects.retain(|o| o.visible);
+    // opaque untextured objects go first, so that they can be drawn by a prefix of the buffers
+    objects.sort_by_key(|o| (o.transparent, o.texture.is_some()));
     let vertexes = objects
-        .into_iter()
-        .flat_map(|o| o.iter())
+        .iter()
+        .flat_map(|o| o.vertices.iter())
         .cloned()
         .collect::<Vec<Vertex>>();
     if vertexes.len() == 0 {
         return None;
-    } else {
-        let buffer = Buffer::from_iter(
-            memory_allocator,
-            BufferCreateInfo {
-                usage: BufferUsage::VERTEX_BUFFER,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                ..Default::default()
-            },
-            vertexes,
-        )
-        .unwrap();
+    }
+
+    // we only need an index buffer if at least one of the objects is indexed.
+    // the unindexed objects are then given sequential indices, so that everything can still be drawn together
+    let needs_indexes = objects.iter().any(|o| o.indices.is_some());
+    let mut indexes = Vec::new();
+    let mut base = 0;
+    for o in objects.iter_mut() {
+        let first_index = indexes.len() as u64;
+        if needs_indexes {
+            match o.indices {
+                Some(ref indices) => indexes.extend(indices.iter().map(|i| base + i)),
+                None => indexes.extend(base..base + o.vertices.len() as u32),
+            }
+        }
+        o.vertex_range = base as u64..(base as u64 + o.vertices.len() as u64);
+        o.index_range = first_index..indexes.len() as u64;
+        o.dirty = false;
+        base += o.vertices.len() as u32;
+    }
+    let indexes = if needs_indexes { Some(indexes) } else { None };
+
+    Some(DrawBuffers {
+        vertex_buffer: upload(memory_allocator.clone(), BufferUsage::VERTEX_BUFFER, vertexes),
+        index_buffer: indexes
+            .map(|indexes| upload(memory_allocator, BufferUsage::INDEX_BUFFER, indexes)),
+        instance_buffer: None,
+        texture: None,
+    })
+}
+
+// rewrite the slices of the dirty objects in the combined buffers. returns false if the buffers couldn't be written
+fn update_in_place<'a, Vertex, Container>(
+    draw_buffers: &DrawBuffers<Vertex>,
+    objects: Container,
+) -> bool
+where
+    Container: IntoIterator<Item = &'a mut SceneObject<Vertex>>,
+    Vertex: Clone + BufferContents,
+{
+    for o in objects.into_iter().filter(|o| o.dirty) {
+        // empty and hidden objects have nothing to write (and empty slices aren't allowed)
+        if o.vertices.is_empty() || !o.visible {
+            o.dirty = false;
+            continue;
+        }
+        match draw_buffers
+            .vertex_buffer
+            .clone()
+            .slice(o.vertex_range.clone())
+            .write()
+        {
+            Ok(mut vertexes) => vertexes.clone_from_slice(&o.vertices),
+            Err(_) => return false,
+        }
+        // the indices of unindexed objects are sequential, so they never change
+        if let (Some(index_buffer), Some(indices)) = (&draw_buffers.index_buffer, &o.indices) {
+            if indices.is_empty() {
+                o.dirty = false;
+                continue;
+            }
+            let base = o.vertex_range.start as u32;
+            match index_buffer.clone().slice(o.index_range.clone()).write() {
+                Ok(mut indexes) => {
+                    for (dst, &i) in indexes.iter_mut().zip(indices) {
+                        *dst = base + i;
+                    }
+                }
+                Err(_) => return false,
+            }
+        }
+        o.dirty = false;
+    }
+    true
+}
+
+fn upload_instances(
+    memory_allocator: Arc<dyn MemoryAllocator>,
+    instances: Vec<InstanceData>,
+) -> Option<Subbuffer<[InstanceData]>> {
+    if instances.is_empty() {
+        return None;
+    }
+    Some(upload(memory_allocator, BufferUsage::VERTEX_BUFFER, instances))
+}
+
+fn upload<T>(memory_allocator: Arc<dyn MemoryAllocator>, usage: BufferUsage, data: Vec<T>) -> Subbuffer<[T]>
+where
+    T: BufferContents,
+{
+    Buffer::from_iter(
+        memory_allocator,
+        BufferCreateInfo {
+            usage,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        data,
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector3;
+
+    use crate::camera::{Camera, FlyCamera};
+    use crate::object;
+    use crate::render_system::testing;
+    use crate::vertex::mVertex;
+
+    // an empty scene, or None if there's no gpu to upload to
+    fn headless_scene() -> Option<Scene<u32, mVertex>> {
+        let (_, _, memory_allocator) = testing::headless_device()?;
+        Some(Scene::new(memory_allocator, HashMap::new()))
+    }
+
+    fn cube_at(x: f32, y: f32, z: f32) -> Vec<mVertex> {
+        object::cuboid(Point3::new(x, y, z), Vector3::new(1.0, 1.0, 1.0))
+    }
+
+    // a camera at the origin, facing +x
+    fn camera_view_proj() -> Matrix4<f32> {
+        FlyCamera::new(Point3::origin()).mvp([64, 64])
+    }
+
+    #[test]
+    fn objects_behind_the_camera_are_culled() {
+        let planes = frustum_planes(&camera_view_proj());
+        let in_front = aabb(&cube_at(5.0, 0.0, 0.0));
+        let behind = aabb(&cube_at(-5.0, 0.0, 0.0));
+        let off_to_the_side = aabb(&cube_at(5.0, 0.0, 50.0));
+        assert!(aabb_in_frustum(in_front, &planes));
+        assert!(!aabb_in_frustum(behind, &planes));
+        assert!(!aabb_in_frustum(off_to_the_side, &planes));
+    }
+
+    #[test]
+    fn visible_draw_buffers_skip_culled_objects() {
+        let mut scene = match headless_scene() {
+            Some(scene) => scene,
+            None => return,
+        };
+        scene.add_object(0, cube_at(5.0, 0.0, 0.0));
+        scene.add_object(1, cube_at(-5.0, 0.0, 0.0));
+        scene.add_object(2, cube_at(5.0, 1.0, 0.0));
+
+        let visible = scene.visible_draw_buffers(camera_view_proj());
+        let drawn: u64 = visible.iter().map(|b| b.vertex_buffer.len()).sum();
+        assert_eq!(drawn, 2 * 36);
+        let culled = scene.visible_object_draw_buffers(camera_view_proj());
+        let mut keys: Vec<u32> = culled.into_iter().map(|(key, _)| key).collect();
+        keys.sort();
+        assert_eq!(keys, vec![0, 2]);
+    }
+
+    #[test]
+    fn hidden_objects_are_left_out_of_the_buffers() {
+        let mut scene = match headless_scene() {
+            Some(scene) => scene,
+            None => return,
+        };
+        scene.add_object(0, cube_at(0.0, 0.0, 0.0));
+        scene.add_object(1, cube_at(2.0, 0.0, 0.0));
+        let full = scene.draw_buffers().unwrap().vertex_buffer.len();
+        assert_eq!(full, 2 * 36);
+
+        scene.set_visible(&1, false);
+        assert_eq!(scene.draw_buffers().unwrap().vertex_buffer.len(), full / 2);
+        // the hidden cube's data is still there
+        assert_eq!(scene.objects().count(), 2);
+
+        scene.set_visible(&1, true);
+        assert_eq!(scene.draw_buffers().unwrap().vertex_buffer.len(), full);
+    }
+
+    #[test]
+    fn moving_an_object_rewrites_it_in_place() {
+        let mut scene = match headless_scene() {
+            Some(scene) => scene,
+            None => return,
+        };
+        scene.add_object(0, cube_at(0.0, 0.0, 0.0));
+        scene.add_object(1, cube_at(2.0, 0.0, 0.0));
+        let before = scene.draw_buffers().unwrap().vertex_buffer;
+
+        scene.add_object(1, cube_at(3.0, 0.0, 0.0));
+        let after = scene.draw_buffers().unwrap().vertex_buffer;
+        assert!(Arc::ptr_eq(before.buffer(), after.buffer()));
+        let moved = after.read().unwrap().iter().map(|v| v.loc[0]).fold(f32::MIN, f32::max);
+        assert_eq!(moved, 3.5);
+
+        // a different size doesn't fit, so the buffers are rebuilt
+        scene.add_object(1, object::unitcube()[..6].to_vec());
+        let rebuilt = scene.draw_buffers().unwrap().vertex_buffer;
+        assert!(!Arc::ptr_eq(after.buffer(), rebuilt.buffer()));
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+    fn benchmark_moving_one_of_500_objects() {
+        let mut scene = match headless_scene() {
+            Some(scene) => scene,
+            None => return,
+        };
+        for key in 0..500 {
+            scene.add_object(key, cube_at((key % 25) as f32 * 2.0, 0.0, (key / 25) as f32 * 2.0));
+        }
+        let original = scene.draw_buffers().unwrap().vertex_buffer;
+        let frames = 100;
+
+        let start = std::time::Instant::now();
+        for frame in 0..frames {
+            scene.add_object(0, cube_at(frame as f32 * 0.01, 0.0, 0.0));
+            scene.draw_buffers();
+        }
+        let in_place = start.elapsed();
+        // the moved object was written into the existing buffers every frame
+        let current = scene.draw_buffers().unwrap().vertex_buffer;
+        assert!(Arc::ptr_eq(original.buffer(), current.buffer()));
+
+        let start = std::time::Instant::now();
+        for frame in 0..frames {
+            // removing first forces a full rebuild, like before per-object updates
+            scene.remove_object(0);
+            scene.add_object(0, cube_at(frame as f32 * 0.01, 0.0, 0.0));
+            scene.draw_buffers();
+        }
+        let rebuild = start.elapsed();
 
-        return Some(buffer);
+        println!("{frames} frames of moving one of 500 objects: in place {in_place:?}, full rebuild {rebuild:?}");
+        assert!(in_place < rebuild);
     }
 }
@@ -0,0 +1,241 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{BufferContents, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        PrimaryCommandBufferAbstract, RenderPassBeginInfo,
+    },
+    device::{DeviceOwned, Queue},
+    format::Format,
+    image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage},
+    memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{
+            depth_stencil::{DepthState, DepthStencilState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, Subpass},
+    shader::{spirv::ExecutionModel, EntryPoint},
+    sync::GpuFuture,
+};
+
+use crate::render_system::error::RendererError;
+use crate::render_system::scene::{identity_instance_buffer, DrawBuffers, InstanceData};
+
+/// renders the depth of a scene into a square `D32_SFLOAT` image that shaders can sample, e.g. from a light's point of view
+pub struct ShadowMap<Vert> {
+    queue: Arc<Queue>,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    pipeline: Arc<GraphicsPipeline>,
+    framebuffer: Arc<Framebuffer>,
+    // bound as the instance buffer of draws that aren't instanced
+    identity_instance: Subbuffer<[InstanceData]>,
+    image_view: Arc<ImageView>,
+    phantom: std::marker::PhantomData<Vert>,
+}
+
+impl<T> ShadowMap<T> {
+    /// `size` is the width and height of the depth image in pixels.
+    /// `stages` only need to write `gl_Position`: the fragment shader's output is ignored.
+    /// panics if the image, render pass or pipeline can't be created, see `try_new`
+    pub fn new(
+        size: u32,
+        stages: Vec<EntryPoint>,
+        queue: Arc<Queue>,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+    ) -> ShadowMap<T>
+    where
+        T: Vertex,
+    {
+        Self::try_new(size, stages, queue, memory_allocator).expect("failed to create shadow map")
+    }
+
+    /// like `new`, but returns an error instead of panicking if creating the vulkan objects fails
+    pub fn try_new(
+        size: u32,
+        stages: Vec<EntryPoint>,
+        queue: Arc<Queue>,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+    ) -> Result<ShadowMap<T>, RendererError>
+    where
+        T: Vertex,
+    {
+        // validate stages
+        assert!(stages.len() > 0, "no shader stages provided");
+        assert!(
+            stages[0].info().execution_model == ExecutionModel::Vertex,
+            "first shader stage must be vertex shader"
+        );
+
+        let device = memory_allocator.device().clone();
+
+        let render_pass = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                depth_stencil: {
+                    format: Format::D32_SFLOAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [],
+                depth_stencil: {depth_stencil},
+            },
+        )?;
+
+        let image_view = ImageView::new_default(Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: Format::D32_SFLOAT,
+                extent: [size, size, 1],
+                usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )?)?;
+
+        let framebuffer = Framebuffer::new(
+            render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![image_view.clone()],
+                ..Default::default()
+            },
+        )?;
+
+        let vertex_input_state =
+            [T::per_vertex(), InstanceData::per_instance()].definition(&stages[0].info().input_interface)?;
+        let stages: Vec<_> = stages
+            .into_iter()
+            .map(PipelineShaderStageCreateInfo::new)
+            .collect();
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())?,
+        )?;
+        let pipeline = GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState {
+                    viewports: [Viewport {
+                        offset: [0.0, 0.0],
+                        extent: [size as f32, size as f32],
+                        depth_range: 0.0..=1.0,
+                    }]
+                    .into_iter()
+                    .collect(),
+                    ..Default::default()
+                }),
+                rasterization_state: Some(RasterizationState::default()),
+                depth_stencil_state: Some(DepthStencilState {
+                    depth: Some(DepthState::simple()),
+                    ..Default::default()
+                }),
+                multisample_state: Some(MultisampleState::default()),
+                subpass: Some(Subpass::from(render_pass, 0).unwrap().into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )?;
+
+        Ok(ShadowMap {
+            queue,
+            command_buffer_allocator: StandardCommandBufferAllocator::new(device, Default::default()),
+            pipeline,
+            framebuffer,
+            identity_instance: identity_instance_buffer(memory_allocator),
+            image_view,
+            phantom: std::marker::PhantomData,
+        })
+    }
+
+    /// the depth image, for binding to the shaders that sample it
+    pub fn image_view(&self) -> Arc<ImageView> {
+        self.image_view.clone()
+    }
+
+    /// draws each set of buffers into the depth image, all with the same push constants.
+    /// waits for rendering to finish, so the image can be sampled by anything rendered afterwards
+    pub fn render<Pc, VB>(&mut self, vertex_buffers: VB, push_data: Pc)
+    where
+        Pc: BufferContents,
+        VB: IntoIterator<Item = DrawBuffers<T>>,
+    {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some(1f32.into())],
+                    ..RenderPassBeginInfo::framebuffer(self.framebuffer.clone())
+                },
+                Default::default(),
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .push_constants(self.pipeline.layout().clone(), 0, push_data)
+            .unwrap();
+
+        for DrawBuffers {
+            vertex_buffer,
+            index_buffer,
+            instance_buffer,
+            texture: _,
+        } in vertex_buffers
+        {
+            let vertex_count = vertex_buffer.len() as u32;
+            // plain draws get a single untransformed instance
+            let instance_count = instance_buffer.as_ref().map_or(1, |b| b.len() as u32);
+            let instance_buffer = instance_buffer.unwrap_or_else(|| self.identity_instance.clone());
+            builder
+                .bind_vertex_buffers(0, (vertex_buffer, instance_buffer))
+                .unwrap();
+            match index_buffer {
+                Some(index_buffer) => {
+                    let index_count = index_buffer.len() as u32;
+                    builder
+                        .bind_index_buffer(index_buffer)
+                        .unwrap()
+                        .draw_indexed(index_count, instance_count, 0, 0, 0)
+                        .unwrap();
+                }
+                None => {
+                    builder.draw(vertex_count, instance_count, 0, 0).unwrap();
+                }
+            }
+        }
+
+        builder.end_render_pass(Default::default()).unwrap();
+
+        builder
+            .build()
+            .unwrap()
+            .execute(self.queue.clone())
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+    }
+}
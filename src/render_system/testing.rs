@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use vulkano::device::DeviceExtensions;
+use vulkano::device::Queue;
+use vulkano::instance::Instance;
+use vulkano::instance::InstanceCreateFlags;
+use vulkano::instance::InstanceCreateInfo;
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use vulkano::VulkanLibrary;
+
+use super::interactive_rendering;
+
+/// a queue, a transfer queue and a memory allocator on the best device, without a window.
+/// None if there's no vulkan driver or no device that can render, so that tests that need a gpu can skip themselves
+pub fn headless_device() -> Option<(Arc<Queue>, Arc<Queue>, Arc<StandardMemoryAllocator>)> {
+    let library = VulkanLibrary::new().ok()?;
+    let instance = Instance::new(
+        library,
+        InstanceCreateInfo {
+            flags: InstanceCreateFlags::ENUMERATE_PORTABILITY,
+            ..Default::default()
+        },
+    )
+    .ok()?;
+    let (device, queue, transfer_queue) =
+        interactive_rendering::get_device(instance, DeviceExtensions::empty(), None).ok()?;
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device));
+    Some((queue, transfer_queue, memory_allocator))
+}
@@ -4,9 +4,84 @@ ty: "fragment",
 #version 450
 
 layout(location = 0) in vec4 fragColor;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec3 fragPos;
+layout(location = 3) in vec2 fragUv;
 layout(location = 0) out vec4 outColor;
 
+// shared with the vertex shader
+layout(push_constant) uniform PushConstantData {
+    mat4 mvp;
+    vec4 light_dir;
+    vec4 light_color;
+} pc;
+
+// must match light::MAX_POINT_LIGHTS
+#define MAX_POINT_LIGHTS 8
+
+struct PointLight {
+    // world space position, w is the radius beyond which the light has no effect
+    vec4 position;
+    // color of the light, a is unused
+    vec4 color;
+};
+
+layout(set = 0, binding = 0) uniform PointLights {
+    PointLight lights[MAX_POINT_LIGHTS];
+    // number of entries of lights that are in use
+    uint count;
+} point_lights;
+
+// depth of the scene as seen from the directional light
+layout(set = 0, binding = 1) uniform sampler2D shadow_map;
+
+layout(set = 0, binding = 2) uniform Shadow {
+    // transformation from world space into the light's clip space, the same one the shadow map was rendered with
+    mat4 light_view_proj;
+    // 0 if shadows are disabled, in which case shadow_map isn't read
+    uint enabled;
+} shadow;
+
+// the object's texture. untextured objects get a single white texel, so only their vertex color is used
+layout(set = 1, binding = 0) uniform sampler2D tex;
+
+// keeps surfaces from shadowing themselves because of the shadow map's limited precision
+const float SHADOW_BIAS = 0.005;
+
+// 0 if something is between the fragment and the directional light, 1 otherwise
+float lit_fraction() {
+    if (shadow.enabled == 0) {
+        return 1.0;
+    }
+    vec4 light_pos = shadow.light_view_proj * vec4(fragPos, 1.0);
+    vec3 ndc = light_pos.xyz / light_pos.w;
+    vec2 uv = ndc.xy * 0.5 + 0.5;
+    // nothing outside the shadow map casts shadows
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || ndc.z > 1.0) {
+        return 1.0;
+    }
+    return ndc.z - SHADOW_BIAS > texture(shadow_map, uv).r ? 0.0 : 1.0;
+}
+
+// fraction of the color that is visible even when facing away from the light
+const float AMBIENT = 0.3;
+
 void main() {
-    outColor = fragColor;
+    vec3 normal = normalize(fragNormal);
+    float diffuse = max(dot(normal, -normalize(pc.light_dir.xyz)), 0.0);
+    vec3 light = pc.light_color.rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse * lit_fraction());
+
+    for (uint i = 0; i < point_lights.count; i++) {
+        PointLight point_light = point_lights.lights[i];
+        vec3 to_light = point_light.position.xyz - fragPos;
+        float dist = length(to_light);
+        // falls off smoothly to zero at the radius
+        float attenuation = pow(clamp(1.0 - dist / point_light.position.w, 0.0, 1.0), 2.0);
+        float point_diffuse = max(dot(normal, to_light / max(dist, 0.0001)), 0.0);
+        light += point_light.color.rgb * point_diffuse * attenuation;
+    }
+
+    vec4 color = fragColor * texture(tex, fragUv);
+    outColor = vec4(color.rgb * light, color.a);
 }"
 }
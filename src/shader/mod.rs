@@ -1,3 +1,12 @@
 //Shader modules for rendering the data
 pub mod frag;
 pub mod vert;
+//Shader modules for rendering segmentation masks
+pub mod seg_frag;
+pub mod seg_vert;
+//Shader modules for rendering the skybox
+pub mod sky_frag;
+pub mod sky_vert;
+//Shader modules for rendering the shadow map
+pub mod shadow_frag;
+pub mod shadow_vert;
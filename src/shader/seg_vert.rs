@@ -0,0 +1,20 @@
+vulkano_shaders::shader! {
+ty: "vertex",
+    src: "
+#version 450
+layout(location = 0) in vec3 loc;
+// per instance, see scene::InstanceData
+layout(location = 3) in mat4 model;
+
+layout(push_constant) uniform PushConstantData {
+    mat4 mvp;
+    // flat color identifying the object being drawn
+    vec4 color;
+} pc;
+
+layout(location = 0) flat out vec4 fragColor;
+void main() {
+    gl_Position = pc.mvp * model * vec4(loc, 1.0);
+    fragColor = pc.color;
+}"
+}
@@ -0,0 +1,9 @@
+vulkano_shaders::shader! {
+ty: "fragment",
+    src: "
+#version 450
+
+// only depth is written
+void main() {
+}"
+}
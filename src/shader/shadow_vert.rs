@@ -0,0 +1,17 @@
+vulkano_shaders::shader! {
+ty: "vertex",
+    src: "
+#version 450
+layout(location = 0) in vec3 loc;
+// per instance, see scene::InstanceData
+layout(location = 3) in mat4 model;
+
+layout(push_constant) uniform PushConstantData {
+    // the light's view projection
+    mat4 mvp;
+} pc;
+
+void main() {
+    gl_Position = pc.mvp * model * vec4(loc, 1.0);
+}"
+}
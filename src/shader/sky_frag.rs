@@ -0,0 +1,14 @@
+vulkano_shaders::shader! {
+ty: "fragment",
+    src: "
+#version 450
+
+layout(location = 0) in vec3 fragDir;
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform samplerCube skybox;
+
+void main() {
+    outColor = texture(skybox, fragDir);
+}"
+}
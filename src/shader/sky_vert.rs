@@ -0,0 +1,21 @@
+vulkano_shaders::shader! {
+ty: "vertex",
+    src: "
+#version 450
+
+layout(set = 0, binding = 1) uniform SkyView {
+    // inverse of the camera's projection * view, with the view's translation removed
+    mat4 inv_view_proj;
+} sky_view;
+
+layout(location = 0) out vec3 fragDir;
+void main() {
+    // a single triangle that covers the whole screen
+    vec2 pos = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+    gl_Position = vec4(pos, 1.0, 1.0);
+    // the direction from the camera through this pixel, from the points on the near and far planes behind it
+    vec4 near = sky_view.inv_view_proj * vec4(pos, 0.0, 1.0);
+    vec4 far = sky_view.inv_view_proj * vec4(pos, 1.0, 1.0);
+    fragDir = far.xyz / far.w - near.xyz / near.w;
+}"
+}
@@ -4,14 +4,32 @@ ty: "vertex",
 #version 450
 layout(location = 0) in vec3 loc;
 layout(location = 1) in vec4 color;
+layout(location = 2) in vec3 normal;
+// per instance, see scene::InstanceData. draws that aren't instanced have a single identity instance
+layout(location = 3) in mat4 model;
+layout(location = 7) in vec4 instance_color;
+layout(location = 8) in vec2 uv;
 
+// shared with the fragment shader
 layout(push_constant) uniform PushConstantData {
     mat4 mvp;
+    // direction the light travels in (world space), w is unused
+    vec4 light_dir;
+    // color of the light, a is unused
+    vec4 light_color;
 } pc;
 
 layout(location = 0) out vec4 fragColor;
+layout(location = 1) out vec3 fragNormal;
+layout(location = 2) out vec3 fragPos;
+layout(location = 3) out vec2 fragUv;
 void main() {
-    gl_Position = pc.mvp * vec4(loc, 1.0);
-    fragColor = color;
+    vec4 world_pos = model * vec4(loc, 1.0);
+    gl_Position = pc.mvp * world_pos;
+    fragColor = color * instance_color;
+    // assumes the model matrix doesn't scale non-uniformly, so it can transform normals directly
+    fragNormal = mat3(model) * normal;
+    fragPos = world_pos.xyz;
+    fragUv = uv;
 }"
 }
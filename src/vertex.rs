@@ -1,5 +1,7 @@
 use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input::Vertex};
 
+use crate::render_system::scene::HasPosition;
+
 #[repr(C)]
 #[derive(BufferContents, Vertex, Clone, Copy)]
 pub struct mVertex {
@@ -8,10 +10,33 @@ pub struct mVertex {
 
     #[format(R32G32B32A32_SFLOAT)]
     pub color: [f32; 4],
+
+    #[format(R32G32B32_SFLOAT)]
+    pub normal: [f32; 3],
+
+    // texture coordinates, only used if the object has a texture
+    #[format(R32G32_SFLOAT)]
+    pub uv: [f32; 2],
 }
 
 impl mVertex {
-    pub fn new(loc: [f32; 3], color: [f32; 4]) -> mVertex {
-        mVertex { loc, color }
+    /// a vertex with texture coordinates (0, 0), see `with_uv`
+    pub fn new(loc: [f32; 3], color: [f32; 4], normal: [f32; 3]) -> mVertex {
+        mVertex {
+            loc,
+            color,
+            normal,
+            uv: [0.0, 0.0],
+        }
+    }
+
+    pub fn with_uv(self, uv: [f32; 2]) -> mVertex {
+        mVertex { uv, ..self }
     }
-}
\ No newline at end of file
+}
+
+impl HasPosition for mVertex {
+    fn position(&self) -> [f32; 3] {
+        self.loc
+    }
+}